@@ -70,11 +70,11 @@ fn derive_csv_int(input: &syn::DeriveInput) -> proc_macro2::TokenStream {
     quote! {
         #base
         impl #generics Csv for #struct_name #generics {
-            fn read<'_internal_a, I: Iterator<Item = &'_internal_a str>>(__internal_i: &mut I) -> Self {
-                #(let #names = <#t_types as ::csv::Csv<#types>>::read(__internal_i);)*
-                Self {
+            fn read<'_internal_a, I: Iterator<Item = &'_internal_a str>>(__internal_i: &mut I) -> Result<Self, ::csv::Error> {
+                #(let #names = <#t_types as ::csv::Csv<#types>>::read(__internal_i)?;)*
+                Ok(Self {
                     #(#names,)*
-                }
+                })
             }
         }
     }
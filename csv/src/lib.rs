@@ -1,4 +1,4 @@
-use std::io;
+use std::{fmt, io, str::FromStr};
 
 #[derive(Clone, Copy, Debug)]
 pub enum Desc {
@@ -55,11 +55,42 @@ where
     fn write<W: io::Write>(obj: &T, ctx: &Self::Context, w: &mut W);
 }
 
+/// Error when reading a line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The line has fewer fields than expected.
+    Missing,
+    /// A field cannot be parsed.
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Missing => write!(f, "missing field"),
+            Error::Invalid(value) => write!(f, "invalid value {value:?}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Takes the next field.
+pub fn next<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<&'a str, Error> {
+    r.next().ok_or(Error::Missing)
+}
+
+/// Takes the next field and parses it.
+pub fn parse<'a, T: FromStr, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<T, Error> {
+    let field = next(r)?;
+    field.parse().map_err(|_| Error::Invalid(field.into()))
+}
+
 pub trait Csv<T = Self>: CsvWrite<T>
 where
     T: Sized,
 {
-    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> T;
+    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<T, Error>;
 }
 
 impl CsvWrite for String {
@@ -81,8 +112,8 @@ impl CsvWrite for str {
     }
 }
 impl Csv for String {
-    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Self {
-        r.next().unwrap().parse().unwrap()
+    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<Self, Error> {
+        Ok(next(r)?.into())
     }
 }
 
@@ -100,8 +131,8 @@ macro_rules! iatom {
             }
         }
         impl Csv for $ty {
-            fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Self {
-                r.next().unwrap().parse().unwrap()
+            fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<Self, Error> {
+                parse(r)
             }
         }
     };
@@ -132,13 +163,13 @@ impl<T: CsvWrite> CsvWrite for Option<T> {
     }
 }
 impl<T: Csv> Csv for Option<T> {
-    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Self {
+    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<Self, Error> {
         let mut r = r.peekable();
-        if *r.peek().unwrap() == "_" {
+        if *r.peek().ok_or(Error::Missing)? == "_" {
             r.take(T::DESC.len()).for_each(|_| ());
-            None
+            Ok(None)
         } else {
-            Some(T::read(&mut r))
+            T::read(&mut r).map(Some)
         }
     }
 }
@@ -166,8 +197,9 @@ impl<T: Csv, const N: usize> CsvWrite for [T; N] {
     }
 }
 impl<T: Csv, const N: usize> Csv for [T; N] {
-    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Self {
-        std::array::from_fn(|_| T::read(r))
+    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<Self, Error> {
+        let items = (0..N).map(|_| T::read(r)).collect::<Result<Vec<_>, _>>()?;
+        Ok(items.try_into().unwrap_or_else(|_| unreachable!()))
     }
 }
 
@@ -267,8 +299,8 @@ impl<T: Default> CsvWrite<T> for Skip {
     fn write<W: std::io::Write>(_obj: &T, &(): &Self::Context, _w: &mut W) {}
 }
 impl<T: Default> Csv<T> for Skip {
-    fn read<'a, I: Iterator<Item = &'a str>>(_r: &mut I) -> T {
-        Default::default()
+    fn read<'a, I: Iterator<Item = &'a str>>(_r: &mut I) -> Result<T, Error> {
+        Ok(Default::default())
     }
 }
 
@@ -1,16 +1,223 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, parse_quote, DataStruct, DeriveInput, Expr, Fields, PatType};
+use syn::{
+    ext::IdentExt, parse_macro_input, parse_quote, Attribute, Data, DataEnum, DataStruct,
+    DeriveInput, Expr, ExprPath, Fields, FieldsNamed, GenericParam, Generics, Ident, LitStr,
+    PatType, Token,
+};
 
-fn derive_serialize_with_context_int(input: &syn::DeriveInput) -> proc_macro2::TokenStream {
-    let name = &input.ident;
-    let syn::Data::Struct(DataStruct { fields, .. }) = &input.data else {
-        panic!("serde-context-derive can only be used on structs.")
-    };
-    let Fields::Named(fields) = &fields else {
+/// The subset of serde's attributes that affects serialization.
+#[derive(Default)]
+struct SerdeAttrs {
+    skip: bool,
+    rename: Option<LitStr>,
+    skip_serializing_if: Option<ExprPath>,
+}
+
+impl SerdeAttrs {
+    fn parse(attrs: &[Attribute]) -> Self {
+        let mut result = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") || meta.path.is_ident("skip_serializing") {
+                    result.skip = true;
+                } else if meta.path.is_ident("rename") {
+                    result.rename = Some(meta.value()?.parse()?);
+                } else if meta.path.is_ident("skip_serializing_if") {
+                    let path: LitStr = meta.value()?.parse()?;
+                    result.skip_serializing_if = Some(path.parse()?);
+                } else if meta.input.peek(Token![=]) {
+                    // attributes only relevant for deserialization, e.g. default = "..."
+                    meta.value()?.parse::<Expr>()?;
+                } else if !meta.input.is_empty() && !meta.input.peek(Token![,]) {
+                    meta.parse_nested_meta(|nested| {
+                        if nested.input.peek(Token![=]) {
+                            nested.value()?.parse::<Expr>()?;
+                        }
+                        Ok(())
+                    })?;
+                }
+                Ok(())
+            })
+            .expect("Failed to parse serde attribute");
+        }
+        result
+    }
+
+    fn name(&self, ident: &Ident) -> LitStr {
+        self.rename
+            .clone()
+            .unwrap_or_else(|| LitStr::new(&ident.unraw().to_string(), ident.span()))
+    }
+}
+
+struct FieldSerializations {
+    names: Vec<Ident>,
+    len: proc_macro2::TokenStream,
+    statements: Vec<proc_macro2::TokenStream>,
+}
+
+fn field_serializations(fields: &FieldsNamed) -> FieldSerializations {
+    let mut names = Vec::new();
+    let mut lens = Vec::new();
+    let mut statements = Vec::new();
+
+    for field in &fields.named {
+        let field_name = field.ident.clone().unwrap();
+        let serde_attrs = SerdeAttrs::parse(&field.attrs);
+        names.push(field_name.clone());
+        if serde_attrs.skip {
+            continue;
+        }
+
+        let key = serde_attrs.name(&field_name);
+        let pass_attr = field.attrs.iter().find(|attr| attr.path().is_ident("pass"));
+        let serialization = if let Some(attr) = pass_attr {
+            let pass = attr
+                .parse_args::<Expr>()
+                .expect("pass must be an expression");
+            quote! {
+                serializer.serialize_field_with_context(#key, #field_name, &#pass)?;
+            }
+        } else {
+            quote! {
+                serializer.serialize_field(#key, #field_name)?;
+            }
+        };
+
+        if let Some(predicate) = serde_attrs.skip_serializing_if {
+            lens.push(quote! { if #predicate(#field_name) { 0 } else { 1 } });
+            statements.push(quote! {
+                if #predicate(#field_name) {
+                    serializer.skip_field(#key)?;
+                } else {
+                    #serialization
+                }
+            });
+        } else {
+            lens.push(quote! { 1 });
+            statements.push(serialization);
+        }
+    }
+
+    FieldSerializations {
+        names,
+        len: quote! { 0 #(+ #lens)* },
+        statements,
+    }
+}
+
+fn serialize_struct(name: &LitStr, fields: &Fields) -> proc_macro2::TokenStream {
+    let Fields::Named(fields) = fields else {
         panic!("SerializeWithContext only supports structs with named fields")
     };
 
+    let FieldSerializations {
+        names,
+        len,
+        statements,
+    } = field_serializations(fields);
+
+    quote! {
+        use serde::ser::SerializeStruct;
+        use serde_context::SerializerExt;
+        #[allow(unused_variables)]
+        let Self { #(ref #names),* } = self;
+        let mut serializer = serializer.serialize_struct(#name, #len)?;
+        #(#statements)*
+        serializer.end()
+    }
+}
+
+fn serialize_enum(name: &LitStr, data: &DataEnum) -> proc_macro2::TokenStream {
+    let arms = data.variants.iter().zip(0u32..).map(|(variant, index)| {
+        let variant_ident = &variant.ident;
+        let serde_attrs = SerdeAttrs::parse(&variant.attrs);
+        let variant_name = serde_attrs.name(variant_ident);
+        if serde_attrs.skip {
+            let message = format!("the enum variant {} cannot be serialized", variant_name.value());
+            return quote! {
+                Self::#variant_ident { .. } => Err(serde::ser::Error::custom(#message)),
+            };
+        }
+        match &variant.fields {
+            Fields::Unit => quote! {
+                Self::#variant_ident => serializer.serialize_unit_variant(#name, #index, #variant_name),
+            },
+            Fields::Named(fields) => {
+                let FieldSerializations {
+                    names,
+                    len,
+                    statements,
+                } = field_serializations(fields);
+                quote! {
+                    #[allow(unused_variables)]
+                    Self::#variant_ident { #(ref #names),* } => {
+                        let mut serializer = serializer.serialize_struct_variant(
+                            #name,
+                            #index,
+                            #variant_name,
+                            #len,
+                        )?;
+                        #(#statements)*
+                        serializer.end()
+                    }
+                }
+            }
+            Fields::Unnamed(_) => {
+                panic!("SerializeWithContext only supports unit and struct variants")
+            }
+        }
+    });
+
+    quote! {
+        use serde::ser::SerializeStructVariant;
+        use serde_context::StructVariantSerializerExt;
+        match self {
+            #(#arms)*
+        }
+    }
+}
+
+/// Adds a bound on every serialized field type if the type has generic type parameters.
+fn add_bounds(generics: &mut Generics, data: &Data) {
+    if !generics
+        .params
+        .iter()
+        .any(|param| matches!(param, GenericParam::Type(_)))
+    {
+        return;
+    }
+    let fields: Vec<_> = match data {
+        Data::Struct(s) => s.fields.iter().collect(),
+        Data::Enum(e) => e.variants.iter().flat_map(|v| &v.fields).collect(),
+        Data::Union(_) => vec![],
+    };
+    let where_clause = generics.make_where_clause();
+    for field in fields {
+        if SerdeAttrs::parse(&field.attrs).skip {
+            continue;
+        }
+        let ty = &field.ty;
+        if field.attrs.iter().any(|attr| attr.path().is_ident("pass")) {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: serde_context::SerializeWithContext));
+        } else {
+            where_clause
+                .predicates
+                .push(parse_quote!(#ty: serde::Serialize));
+        }
+    }
+}
+
+fn derive_serialize_with_context_int(input: &syn::DeriveInput) -> proc_macro2::TokenStream {
+    let ident = &input.ident;
+    let name = SerdeAttrs::parse(&input.attrs).name(ident);
+    let mut generics = input.generics.clone();
+    add_bounds(&mut generics, &input.data);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
     let context_arg = input
         .attrs
         .iter()
@@ -26,31 +233,15 @@ fn derive_serialize_with_context_int(input: &syn::DeriveInput) -> proc_macro2::T
         (parse_quote!(_), parse_quote!(()))
     };
 
-    let mut names = Vec::new();
-
-    let field_serializations: Vec<_> =
-        fields.named.iter().map(|field| {
-        let field_name = field.ident.clone().unwrap();
-        let pass_attr = field.attrs.iter().find(|attr| attr.path().is_ident("pass"));
-        names.push(field_name.clone());
-
-        if let Some(attr) = pass_attr {
-            let pass = attr.parse_args::<Expr>().expect("pass must be an expression");
-            quote! {
-                serializer.serialize_field_with_context(stringify!(#field_name), &self.#field_name, &#pass)?;
-            }
-        } else {
-            quote! {
-                serializer.serialize_field(stringify!(#field_name), &self.#field_name)?;
-            }
-        }
-    }).collect();
-
-    let len = field_serializations.len();
+    let body = match &input.data {
+        Data::Struct(DataStruct { fields, .. }) => serialize_struct(&name, fields),
+        Data::Enum(data) => serialize_enum(&name, data),
+        Data::Union(_) => panic!("serde-context-derive can only be used on structs and enums."),
+    };
 
     let serialize_impl = (context_type == parse_quote!(())).then(|| {
         quote! {
-            impl serde::Serialize for #name {
+            impl #impl_generics serde::Serialize for #ident #ty_generics #where_clause {
                 fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
                     serde_context::SerializeWithContext::serialize(self, &(), serializer)
                 }
@@ -61,25 +252,20 @@ fn derive_serialize_with_context_int(input: &syn::DeriveInput) -> proc_macro2::T
     quote! {
         #serialize_impl
 
-        impl serde_context::SerializeWithContext for #name {
+        impl #impl_generics serde_context::SerializeWithContext for #ident #ty_generics #where_clause {
             type Context = #context_type;
 
             fn serialize<S>(&self, #context_name: &Self::Context, serializer: S) -> Result<S::Ok, S::Error>
             where
                 S: serde::Serializer,
             {
-                use serde::ser::SerializeStruct;
-                use serde_context::SerializerExt;
-                let mut serializer = serializer.serialize_struct(stringify!(#name), #len)?;
-                let Self { #(ref #names),* } = self;
-                #(#field_serializations)*
-                serializer.end()
+                #body
             }
         }
     }
 }
 
-#[proc_macro_derive(SerializeWithContext, attributes(context, pass))]
+#[proc_macro_derive(SerializeWithContext, attributes(context, pass, serde))]
 pub fn derive_serialize_with_context(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     derive_serialize_with_context_int(&input).into()
@@ -14,6 +14,28 @@ pub struct ContextWrapper<'a, T: SerializeWithContext> {
     context: &'a T::Context,
 }
 
+impl<'a, T: SerializeWithContext> ContextWrapper<'a, T> {
+    pub fn new(base: &'a T, context: &'a T::Context) -> Self {
+        Self { base, context }
+    }
+}
+
+/// Binds `context` to `base`, so the result can be passed to any serde serializer.
+pub fn with_context<'a, T: SerializeWithContext>(
+    base: &'a T,
+    context: &'a T::Context,
+) -> ContextWrapper<'a, T> {
+    ContextWrapper::new(base, context)
+}
+
+#[cfg(feature = "json")]
+pub fn to_value<T: SerializeWithContext>(
+    base: &T,
+    context: &T::Context,
+) -> serde_json::Result<serde_json::Value> {
+    serde_json::to_value(with_context(base, context))
+}
+
 impl<T: SerializeWithContext> serde::Serialize for ContextWrapper<'_, T> {
     fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
         self.base.serialize(self.context, serializer)
@@ -38,7 +60,29 @@ impl<S: serde::ser::SerializeStruct> SerializerExt for S {
         base: &T,
         context: &T::Context,
     ) -> Result<(), Self::Error> {
-        self.serialize_field(name, &ContextWrapper { base, context })
+        self.serialize_field(name, &ContextWrapper::new(base, context))
+    }
+}
+
+pub trait StructVariantSerializerExt {
+    type Error;
+    fn serialize_field_with_context<T: SerializeWithContext>(
+        &mut self,
+        name: &'static str,
+        base: &T,
+        context: &T::Context,
+    ) -> Result<(), Self::Error>;
+}
+
+impl<S: serde::ser::SerializeStructVariant> StructVariantSerializerExt for S {
+    type Error = S::Error;
+    fn serialize_field_with_context<T: SerializeWithContext>(
+        &mut self,
+        name: &'static str,
+        base: &T,
+        context: &T::Context,
+    ) -> Result<(), Self::Error> {
+        self.serialize_field(name, &ContextWrapper::new(base, context))
     }
 }
 
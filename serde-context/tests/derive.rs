@@ -0,0 +1,167 @@
+use serde_json::json;
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+/// Defines the items in `derived` with `SerializeWithContext` and in
+/// `reference` with serde's `Serialize`, whose outputs must match.
+macro_rules! both {
+    ($($item:item)*) => {
+        mod derived {
+            use super::*;
+            $(#[derive(serde_context::SerializeWithContext)] $item)*
+        }
+        mod reference {
+            use super::*;
+            $(#[derive(serde::Serialize)] $item)*
+        }
+    };
+}
+
+both! {
+    pub struct Plain {
+        pub a: u32,
+        pub b: &'static str,
+    }
+
+    pub struct Attributes {
+        #[serde(skip)]
+        #[allow(dead_code)]
+        pub skipped: u32,
+        #[serde(skip_serializing)]
+        #[allow(dead_code)]
+        pub skipped_serializing: u32,
+        #[serde(rename = "renamed")]
+        pub original: u32,
+        #[serde(skip_serializing_if = "is_zero")]
+        pub nonzero: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub some: Option<u32>,
+        #[serde(default)]
+        pub r#type: u32,
+    }
+
+    #[serde(rename = "Renamed")]
+    pub struct Generic<T, U: Copy> {
+        pub t: T,
+        pub items: Vec<U>,
+        #[serde(skip)]
+        pub phantom: std::marker::PhantomData<U>,
+    }
+
+    pub enum Enum {
+        Unit,
+        #[serde(rename = "other")]
+        Renamed,
+        Struct {
+            a: u32,
+            #[serde(skip_serializing_if = "is_zero")]
+            b: u32,
+        },
+        #[serde(skip)]
+        #[allow(dead_code)]
+        Skipped,
+    }
+}
+
+/// Asserts that both derives serialize to the same json, and returns it.
+macro_rules! same {
+    ($ty:ident $($value:tt)*) => {{
+        let derived = serde_json::to_value(derived::$ty $($value)*).unwrap();
+        let reference = serde_json::to_value(reference::$ty $($value)*).unwrap();
+        assert_eq!(derived, reference);
+        derived
+    }};
+}
+
+#[test]
+fn plain() {
+    assert_eq!(same!(Plain { a: 1, b: "b" }), json!({"a": 1, "b": "b"}));
+}
+
+#[test]
+fn attributes() {
+    let full = same!(Attributes {
+        skipped: 1,
+        skipped_serializing: 2,
+        original: 3,
+        nonzero: 4,
+        some: Some(5),
+        r#type: 6,
+    });
+    assert_eq!(
+        full,
+        json!({"renamed": 3, "nonzero": 4, "some": 5, "type": 6})
+    );
+    let empty = same!(Attributes {
+        skipped: 1,
+        skipped_serializing: 2,
+        original: 3,
+        nonzero: 0,
+        some: None,
+        r#type: 6,
+    });
+    assert_eq!(empty, json!({"renamed": 3, "type": 6}));
+}
+
+#[test]
+fn generic() {
+    let generic = same!(Generic {
+        t: "t",
+        items: vec![1u8, 2],
+        phantom: std::marker::PhantomData,
+    });
+    assert_eq!(generic, json!({"t": "t", "items": [1, 2]}));
+}
+
+#[test]
+fn enums() {
+    assert_eq!(same!(Enum::Unit), json!("Unit"));
+    assert_eq!(same!(Enum::Renamed), json!("other"));
+    assert_eq!(
+        same!(Enum::Struct { a: 1, b: 2 }),
+        json!({"Struct": {"a": 1, "b": 2}})
+    );
+    assert_eq!(
+        same!(Enum::Struct { a: 1, b: 0 }),
+        json!({"Struct": {"a": 1}})
+    );
+    assert!(serde_json::to_value(derived::Enum::Skipped).is_err());
+    assert!(serde_json::to_value(reference::Enum::Skipped).is_err());
+}
+
+/// Length in millimeters, serialized in the unit given by the context
+struct Length(u32);
+
+impl serde_context::SerializeWithContext for Length {
+    type Context = u32;
+
+    fn serialize<S: serde::Serializer>(
+        &self,
+        unit: &u32,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.0 / unit)
+    }
+}
+
+#[derive(serde_context::SerializeWithContext)]
+#[context(unit: u32)]
+struct Segment {
+    #[pass(unit)]
+    length: Length,
+    #[serde(rename = "name")]
+    label: &'static str,
+}
+
+#[test]
+fn context() {
+    let segment = Segment {
+        length: Length(5000),
+        label: "a",
+    };
+    let value = |unit| serde_json::to_value(serde_context::with_context(&segment, &unit)).unwrap();
+    assert_eq!(value(1), json!({"length": 5000, "name": "a"}));
+    assert_eq!(value(1000), json!({"length": 5, "name": "a"}));
+}
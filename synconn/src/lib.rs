@@ -0,0 +1,122 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+pub mod sockopt;
+pub mod traffic;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct ConnArgs {
+    /// Listen on 127.0.0.1 (instead of ::1), or connect via IPv4
+    #[arg(short = '4', conflicts_with_all = ["inet6", "bind"])]
+    pub inet4: bool,
+    /// Listen on ::1 (default), or connect via IPv6
+    #[arg(short = '6', conflicts_with = "bind")]
+    pub inet6: bool,
+    /// Listen on this address
+    #[arg(short = 'b', long)]
+    pub bind: Option<IpAddr>,
+    /// Listen on this port instead of an ephemeral one
+    #[arg(long, default_value_t = 0)]
+    pub port: u16,
+    /// Connect to this remote HOST:PORT instead of listening
+    #[arg(long, value_name = "HOST:PORT", conflicts_with_all = ["bind", "port"])]
+    pub connect: Option<String>,
+    /// Open connections at this rate (e.g. 100/s) instead of all at once
+    #[arg(long, value_parser = parse_rate)]
+    pub rate: Option<f64>,
+    #[command(flatten)]
+    pub traffic: traffic::TrafficArgs,
+    #[command(flatten)]
+    pub socket: sockopt::SocketArgs,
+}
+
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate: f64 = s
+        .strip_suffix("/s")
+        .unwrap_or(s)
+        .parse()
+        .map_err(|e| format!("{e}"))?;
+    if rate.is_finite() && rate > 0.0 {
+        Ok(rate)
+    } else {
+        Err("rate must be positive".into())
+    }
+}
+
+/// Resolves the remote target, respecting `-4` and `-6`.
+fn resolve(target: &str, args: &ConnArgs) -> std::io::Result<SocketAddr> {
+    target
+        .to_socket_addrs()?
+        .find(|addr| !(args.inet4 && addr.is_ipv6() || args.inet6 && addr.is_ipv4()))
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("no suitable address for {target}"),
+            )
+        })
+}
+
+/// A client socket and, for loopback pairs, the accepted server socket.
+pub type Connection = (TcpStream, Option<TcpStream>);
+
+/// The listener of loopback pairs, or the remote target.
+pub struct Endpoint {
+    listener: Option<TcpListener>,
+    /// Address that the clients connect to
+    pub addr: SocketAddr,
+}
+
+impl Endpoint {
+    pub fn new(args: &ConnArgs) -> std::io::Result<Self> {
+        if let Some(target) = &args.connect {
+            let addr = resolve(target, args)?;
+            eprintln!("connecting to {}", addr);
+            return Ok(Self {
+                listener: None,
+                addr,
+            });
+        }
+        let ip = args.bind.unwrap_or(if args.inet4 {
+            IpAddr::V4(Ipv4Addr::LOCALHOST)
+        } else {
+            IpAddr::V6(Ipv6Addr::LOCALHOST)
+        });
+        let listener = TcpListener::bind(SocketAddr::new(ip, args.port))?;
+        let addr = listener.local_addr()?;
+        eprintln!("listening on {}", addr);
+        Ok(Self {
+            listener: Some(listener),
+            addr,
+        })
+    }
+
+    /// Opens `count` connections (at the configured rate) with the configured
+    /// socket options, and starts the configured traffic on them.
+    pub fn open(&self, args: &ConnArgs, count: u32) -> std::io::Result<Vec<Connection>> {
+        let start = Instant::now();
+        let conns = (0..count)
+            .map(|i| {
+                if let Some(rate) = args.rate {
+                    let next = start + Duration::from_secs_f64(f64::from(i) / rate);
+                    std::thread::sleep(next.saturating_duration_since(Instant::now()));
+                }
+                let client = TcpStream::connect(self.addr)?;
+                sockopt::set_linger_zero(&client)?;
+                args.socket.apply(&client)?;
+                let server = match &self.listener {
+                    Some(listener) => {
+                        let (server, _) = listener.accept()?;
+                        sockopt::set_linger_zero(&server)?;
+                        args.socket.apply(&server)?;
+                        Some(server)
+                    }
+                    None => None,
+                };
+                traffic::start(&args.traffic, &client, server.as_ref())?;
+                Ok((client, server))
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        eprintln!("opened {} connections", conns.len());
+        Ok(conns)
+    }
+}
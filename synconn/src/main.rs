@@ -1,10 +1,20 @@
-#![feature(tcp_linger)]
+use clap::Parser;
+use synconn::{ConnArgs, Endpoint};
 
-use std::net::{Ipv6Addr, SocketAddrV6, TcpListener, TcpStream};
-use std::time::Duration;
+/// Open COUNT (default 500) connection pairs over loopback (or connections to
+/// a remote target), then run CMD with the listening (or remote) port as
+/// additional argument (or wait forever).
+#[derive(Parser, Debug)]
+#[command(override_usage = "synconn [OPTIONS] [COUNT] [CMD [ARGS]...]")]
+struct Args {
+    #[command(flatten)]
+    conn: ConnArgs,
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true, hide = true)]
+    rest: Vec<String>,
+}
 
-fn parse_args() -> (u32, Option<(String, Vec<String>)>) {
-    let mut it = std::env::args().skip(1);
+fn parse_rest(rest: Vec<String>) -> (u32, Option<(String, Vec<String>)>) {
+    let mut it = rest.into_iter();
     let Some(count) = it.next() else {
         return (500, None);
     };
@@ -15,25 +25,25 @@ fn parse_args() -> (u32, Option<(String, Vec<String>)>) {
 }
 
 fn main() -> std::io::Result<()> {
-    let (count, cmd) = parse_args();
-    let bind_addr = SocketAddrV6::new(Ipv6Addr::LOCALHOST, 0, 0, 0);
-    let listener = TcpListener::bind(&bind_addr)?;
-    let local_addr = listener.local_addr()?;
-    eprintln!("listening on {}", local_addr);
-    let conns = (0..count)
-        .map(|_| {
-            let client = TcpStream::connect(local_addr)?;
-            client.set_linger(Some(Duration::from_secs(0)))?;
-            let (server, _) = listener.accept()?;
-            server.set_linger(Some(Duration::from_secs(0)))?;
-            Ok([client, server])
-        })
-        .collect::<std::io::Result<Vec<_>>>()?;
-    eprintln!("opened {} connections", conns.len());
+    let args = Args::parse();
+    let (count, mut cmd) = parse_rest(args.rest);
+    let endpoint = Endpoint::new(&args.conn)?;
+    let spawn = |(cmd, mut args): (String, Vec<String>)| {
+        args.push(format!("{}", endpoint.addr.port()));
+        std::process::Command::new(cmd).args(args).spawn()
+    };
+    // While ramping up, CMD runs concurrently to observe the growth.
+    let mut child = None;
+    if args.conn.rate.is_some() {
+        child = cmd.take().map(spawn).transpose()?;
+    }
+    let _conns = endpoint.open(&args.conn, count)?;
 
-    if let Some((cmd, mut args)) = cmd {
-        args.push(format!("{}", local_addr.port()));
-        let ex = std::process::Command::new(cmd).args(args).spawn()?.wait()?;
+    if let Some(cmd) = cmd {
+        child = Some(spawn(cmd)?);
+    }
+    if let Some(mut child) = child {
+        let ex = child.wait()?;
         std::process::exit(ex.code().unwrap_or(1))
     } else {
         loop {
@@ -0,0 +1,87 @@
+use std::net::TcpStream;
+use std::os::fd::AsRawFd;
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct SocketArgs {
+    /// Congestion control algorithm (TCP_CONGESTION, e.g. cubic or bbr)
+    #[arg(long)]
+    pub congestion: Option<String>,
+    /// Send buffer size in bytes (SO_SNDBUF)
+    #[arg(long)]
+    pub sndbuf: Option<u32>,
+    /// Receive buffer size in bytes (SO_RCVBUF)
+    #[arg(long)]
+    pub rcvbuf: Option<u32>,
+    /// Disable Nagle's algorithm (TCP_NODELAY)
+    #[arg(long)]
+    pub nodelay: bool,
+    /// Enable keepalive probes after this idle time in seconds (SO_KEEPALIVE)
+    #[arg(long, value_name = "SECS")]
+    pub keepalive: Option<u32>,
+}
+
+fn setsockopt(sock: &TcpStream, level: i32, name: i32, value: &[u8]) -> std::io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            level,
+            name,
+            value.as_ptr().cast(),
+            value.len().try_into().unwrap(),
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+fn setsockopt_int(sock: &TcpStream, level: i32, name: i32, value: u32) -> std::io::Result<()> {
+    let value = i32::try_from(value).unwrap_or(i32::MAX);
+    setsockopt(sock, level, name, &value.to_ne_bytes())
+}
+
+/// Makes closing the socket reset the connection instead of lingering in
+/// TIME_WAIT, so repeated runs do not exhaust the ports.
+pub fn set_linger_zero(sock: &TcpStream) -> std::io::Result<()> {
+    let linger = libc::linger {
+        l_onoff: 1,
+        l_linger: 0,
+    };
+    let value = unsafe {
+        std::slice::from_raw_parts(
+            (&linger as *const libc::linger).cast::<u8>(),
+            size_of::<libc::linger>(),
+        )
+    };
+    setsockopt(sock, libc::SOL_SOCKET, libc::SO_LINGER, value)
+}
+
+impl SocketArgs {
+    /// Sets the configured options on a connected socket.
+    pub fn apply(&self, sock: &TcpStream) -> std::io::Result<()> {
+        if let Some(congestion) = &self.congestion {
+            setsockopt(
+                sock,
+                libc::IPPROTO_TCP,
+                libc::TCP_CONGESTION,
+                congestion.as_bytes(),
+            )?;
+        }
+        if let Some(sndbuf) = self.sndbuf {
+            setsockopt_int(sock, libc::SOL_SOCKET, libc::SO_SNDBUF, sndbuf)?;
+        }
+        if let Some(rcvbuf) = self.rcvbuf {
+            setsockopt_int(sock, libc::SOL_SOCKET, libc::SO_RCVBUF, rcvbuf)?;
+        }
+        if self.nodelay {
+            sock.set_nodelay(true)?;
+        }
+        if let Some(idle) = self.keepalive {
+            setsockopt_int(sock, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1)?;
+            setsockopt_int(sock, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, idle)?;
+        }
+        Ok(())
+    }
+}
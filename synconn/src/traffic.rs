@@ -0,0 +1,90 @@
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pattern {
+    /// The client sends as fast as possible
+    Bulk,
+    /// The client sends at --throughput
+    FixedRate,
+    /// The client sends requests, each answered by a response
+    PingPong,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct TrafficArgs {
+    /// Move data over each connection
+    #[arg(long)]
+    pub pattern: Option<Pattern>,
+    /// Bytes per write (bulk, fixed-rate) or per request (ping-pong)
+    #[arg(long, default_value_t = 16384, requires = "pattern")]
+    pub size: usize,
+    /// Bytes per response (ping-pong)
+    #[arg(long, default_value_t = 16384, requires = "pattern")]
+    pub response_size: usize,
+    /// Bytes per second and connection (fixed-rate)
+    #[arg(long, required_if_eq("pattern", "fixed-rate"))]
+    pub throughput: Option<u64>,
+}
+
+/// Starts a thread for each side of the connection that moves data according
+/// to the pattern until the connection is closed. Without a server (remote
+/// target), only the client side is handled.
+pub fn start(
+    args: &TrafficArgs,
+    client: &TcpStream,
+    server: Option<&TcpStream>,
+) -> std::io::Result<()> {
+    let Some(pattern) = args.pattern else {
+        return Ok(());
+    };
+    let mut client = client.try_clone()?;
+    let server = server.map(TcpStream::try_clone).transpose()?;
+    let (size, response_size) = (args.size, args.response_size);
+    let interval = args
+        .throughput
+        .map(|t| Duration::from_secs_f64(size as f64 / t as f64));
+    std::thread::spawn(move || {
+        let request = vec![0; size];
+        let mut response = vec![0; response_size];
+        let mut next = Instant::now();
+        loop {
+            if client.write_all(&request).is_err() {
+                return;
+            }
+            match pattern {
+                Pattern::Bulk => (),
+                Pattern::FixedRate => {
+                    next += interval.unwrap();
+                    std::thread::sleep(next.saturating_duration_since(Instant::now()));
+                }
+                Pattern::PingPong => {
+                    if client.read_exact(&mut response).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+    let Some(mut server) = server else {
+        return Ok(());
+    };
+    std::thread::spawn(move || {
+        let mut request = vec![0; size.max(1)];
+        let response = vec![0; response_size];
+        loop {
+            let ok = match pattern {
+                Pattern::PingPong => server
+                    .read_exact(&mut request[..size])
+                    .and_then(|()| server.write_all(&response))
+                    .is_ok(),
+                _ => matches!(server.read(&mut request), Ok(1..)),
+            };
+            if !ok {
+                return;
+            }
+        }
+    });
+    Ok(())
+}
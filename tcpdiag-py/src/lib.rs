@@ -0,0 +1,246 @@
+use clap::Parser;
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+use serde_json::Value;
+use std::{
+    fs::File,
+    io::BufReader,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tcpdiag::{data::InetDiagMsgExtra, timespec::Timespec};
+use tcpdiag::{try_decompress, try_read_capture, try_read_netlink};
+use tcpdiag::{Collector, NetlinkArgs};
+
+struct Sample {
+    time: u64,
+    duration: Option<u64>,
+    samples: Vec<Value>,
+}
+
+#[derive(Default)]
+struct SampleCollector {
+    samples: Vec<Sample>,
+    /// Whether a record was too short or outside of a sample
+    malformed: bool,
+}
+
+impl SampleCollector {
+    /// Returns the samples, or a `ValueError` if a record was malformed.
+    fn into_samples(self) -> PyResult<Vec<Sample>> {
+        match self.malformed {
+            true => Err(PyValueError::new_err("malformed record")),
+            false => Ok(self.samples),
+        }
+    }
+}
+
+impl Collector for SampleCollector {
+    fn out(&mut self, data: &[u8]) {
+        let (Some(sample), Some(extras)) =
+            (self.samples.last_mut(), InetDiagMsgExtra::try_parse(data))
+        else {
+            self.malformed = true;
+            return;
+        };
+        sample.samples.push(serde_json::to_value(&extras).unwrap());
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.samples.push(Sample {
+            // Times before the epoch (only in malformed captures) become 0.
+            time: time
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_micros() as u64),
+            duration: None,
+            samples: Vec::new(),
+        });
+    }
+
+    fn end(&mut self, duration: Duration) {
+        if let Some(sample) = self.samples.last_mut() {
+            sample.duration = Some(duration.as_micros() as u64);
+        }
+    }
+}
+
+fn to_py<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    Ok(match value {
+        Value::Null => py.None().into_bound(py),
+        Value::Bool(b) => b.into_pyobject(py)?.to_owned().into_any(),
+        Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(n), _) => n.into_pyobject(py)?.into_any(),
+            (_, Some(n)) => n.into_pyobject(py)?.into_any(),
+            _ => n.as_f64().unwrap().into_pyobject(py)?.into_any(),
+        },
+        Value::String(s) => s.into_pyobject(py)?.into_any(),
+        Value::Array(a) => PyList::new(
+            py,
+            a.iter()
+                .map(|v| to_py(py, v))
+                .collect::<PyResult<Vec<_>>>()?,
+        )?
+        .into_any(),
+        Value::Object(o) => {
+            let dict = PyDict::new(py);
+            for (k, v) in o {
+                dict.set_item(k, to_py(py, v)?)?;
+            }
+            dict.into_any()
+        }
+    })
+}
+
+/// Flattens nested objects into dotted keys, like the CSV header.
+fn flatten<'py>(dict: &Bound<'py, PyDict>, prefix: &str, value: &Value) -> PyResult<()> {
+    match value {
+        Value::Object(o) => {
+            for (k, v) in o {
+                let key = if prefix.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{prefix}.{k}")
+                };
+                flatten(dict, &key, v)?;
+            }
+            Ok(())
+        }
+        _ => dict.set_item(prefix, to_py(dict.py(), value)?),
+    }
+}
+
+impl Sample {
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("time", self.time)?;
+        let samples = self
+            .samples
+            .iter()
+            .map(|v| to_py(py, v))
+            .collect::<PyResult<Vec<_>>>()?;
+        dict.set_item("samples", PyList::new(py, samples)?)?;
+        dict.set_item("duration", self.duration)?;
+        Ok(dict)
+    }
+
+    fn rows<'py>(&self, py: Python<'py>, rows: &mut Vec<Bound<'py, PyDict>>) -> PyResult<()> {
+        for sample in &self.samples {
+            let row = PyDict::new(py);
+            row.set_item("time", self.time)?;
+            flatten(&row, "", sample)?;
+            row.set_item("duration", self.duration)?;
+            rows.push(row);
+        }
+        Ok(())
+    }
+}
+
+/// Parses a capture file in any format supported by `tcpdiag --convert`,
+/// which may be compressed with gzip or zstd.
+///
+/// Returns a list of dicts (one per measurement period) or, with
+/// `dataframe=True`, a pandas DataFrame with one row per socket. Raises
+/// `OSError` if the file can't be read or has an unrecognized format, and
+/// `ValueError` for malformed records.
+#[pyfunction]
+#[pyo3(signature = (path, dataframe = false))]
+fn parse_capture(py: Python<'_>, path: &str, dataframe: bool) -> PyResult<PyObject> {
+    let reader = try_decompress(BufReader::new(File::open(path)?))?;
+    let mut collector = SampleCollector::default();
+    py.allow_threads(|| try_read_capture(reader, &mut collector))?;
+    let samples = collector.into_samples()?;
+
+    if dataframe {
+        let mut rows = Vec::new();
+        for sample in &samples {
+            sample.rows(py, &mut rows)?;
+        }
+        let pandas = py.import("pandas")?;
+        Ok(pandas.getattr("DataFrame")?.call1((rows,))?.unbind())
+    } else {
+        let samples = samples
+            .iter()
+            .map(|s| s.to_dict(py))
+            .collect::<PyResult<Vec<_>>>()?;
+        Ok(PyList::new(py, samples)?.into_any().unbind())
+    }
+}
+
+/// Samples live TCP connections from the kernel.
+///
+/// `sample()` captures a single measurement period. Iterating over a sampler
+/// with a `period` (in seconds) yields one sample per period. Errors of the
+/// netlink sockets raise `OSError`.
+#[pyclass]
+struct Sampler {
+    args: NetlinkArgs,
+    next: Option<Timespec>,
+}
+
+#[pymethods]
+impl Sampler {
+    #[new]
+    #[pyo3(signature = (period = None, inet4 = false, inet6 = false, sport = 0, dport = 0, all_states = false, listening = false))]
+    fn new(
+        period: Option<f64>,
+        inet4: bool,
+        inet6: bool,
+        sport: u16,
+        dport: u16,
+        all_states: bool,
+        listening: bool,
+    ) -> PyResult<Self> {
+        if period.is_some_and(|period| !(period > 0.0 && period.is_finite())) {
+            return Err(PyValueError::new_err("period must be positive"));
+        }
+        let mut args = NetlinkArgs::parse_from(["tcpdiag"]);
+        args.inet4 = inet4;
+        args.inet6 = inet6;
+        args.sport = sport;
+        args.dport = dport;
+        args.all_states = all_states;
+        args.listening = listening;
+        args.period = period;
+        Ok(Self { args, next: None })
+    }
+
+    fn sample(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let args = NetlinkArgs {
+            period: None,
+            count: None,
+            ..self.args.clone()
+        };
+        let mut collector = SampleCollector::default();
+        py.allow_threads(|| try_read_netlink(&args, &mut collector))?;
+        let samples = collector.into_samples()?;
+        Ok(samples[0].to_dict(py)?.into_any().unbind())
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<PyObject> {
+        let Some(period) = self.args.period else {
+            return Err(PyValueError::new_err("iterating requires a period"));
+        };
+        let clock = self.args.clock;
+        let next = match self.next {
+            Some(mut next) => {
+                next += Duration::from_secs_f64(period);
+                py.allow_threads(|| next.sleep_until(clock));
+                next
+            }
+            None => Timespec::now(clock),
+        };
+        self.next = Some(next);
+        self.sample(py)
+    }
+}
+
+#[pymodule]
+fn tcpdiag_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(parse_capture, m)?)?;
+    m.add_class::<Sampler>()?;
+    Ok(())
+}
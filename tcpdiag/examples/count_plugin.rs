@@ -0,0 +1,43 @@
+//! Output plugin that prints the number of sockets per sample, prefixed with
+//! the `--plugin-arg` (e.g. a host name).
+//!
+//! cargo build --example count_plugin
+//! tcpdiag -p 1 --plugin target/debug/examples/libcount_plugin.so --plugin-arg web1
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tcpdiag::{plugin::Plugin, Collector};
+
+struct Count {
+    prefix: String,
+    time: SystemTime,
+    sockets: usize,
+}
+
+impl Plugin for Count {
+    fn new(arg: Option<&str>) -> Option<Self> {
+        Some(Self {
+            prefix: arg.unwrap_or("sockets").into(),
+            time: UNIX_EPOCH,
+            sockets: 0,
+        })
+    }
+}
+
+impl Collector for Count {
+    fn start(&mut self, time: SystemTime) {
+        self.time = time;
+        self.sockets = 0;
+    }
+
+    fn out(&mut self, _data: &[u8]) {
+        self.sockets += 1;
+    }
+
+    fn end(&mut self, _duration: Duration) {
+        let time = self.time.duration_since(UNIX_EPOCH).unwrap().as_micros();
+        println!("{} {time} {}", self.prefix, self.sockets);
+    }
+}
+
+tcpdiag::export_plugin!(Count);
@@ -0,0 +1,188 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    hash::Hasher,
+    io::Read,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::{Duration, SystemTime},
+};
+
+use siphasher::sip::SipHasher24;
+use siphasher::sip128::{Hasher128, SipHasher};
+use zerocopy::FromBytes;
+
+use crate::data::InetDiagMsg;
+use crate::integer::U16BE;
+use crate::Collector;
+
+/// Deterministic, prefix-preserving mapping of addresses and ports.
+///
+/// Addresses are mapped like in Crypto-PAn: bit i of the result is bit i of
+/// the input XOR a keyed hash of the preceding input bits. Thus, two addresses
+/// with a common prefix of n bits are mapped to addresses with a common prefix
+/// of n bits. IPv4-mapped IPv6 addresses are mapped like their IPv4 address.
+/// Ports are permuted by a keyed Feistel network, keeping port 0. The key has
+/// to be kept secret, as it allows reverting the mapping.
+pub struct Anonymizer {
+    key: [u8; 16],
+    ports: bool,
+    cache: HashMap<IpAddr, IpAddr>,
+}
+
+impl Anonymizer {
+    /// The key is derived from the given string, or random if it is `None`.
+    pub fn new(key: Option<&str>, ports: bool) -> Self {
+        let key = match key {
+            Some(key) => {
+                let mut hasher = SipHasher::new();
+                hasher.write(key.as_bytes());
+                hasher.finish128().as_bytes()
+            }
+            None => {
+                let mut key = [0; 16];
+                let mut urandom = File::open("/dev/urandom").unwrap();
+                urandom.read_exact(&mut key).unwrap();
+                key
+            }
+        };
+        Self {
+            key,
+            ports,
+            cache: HashMap::new(),
+        }
+    }
+
+    fn hash(&self, domain: u8, value: u128, len: u32) -> u64 {
+        let mut hasher = SipHasher24::new_with_key(&self.key);
+        hasher.write_u8(domain);
+        hasher.write_u128(value);
+        hasher.write_u32(len);
+        hasher.finish()
+    }
+
+    /// Maps the highest `bits` bits of `value` (other bits must be zero).
+    fn map_bits(&self, value: u128, bits: u32) -> u128 {
+        let mut result = 0;
+        for i in 0..bits {
+            let prefix = value & !(u128::MAX >> i);
+            let flip = u128::from(self.hash(0, prefix, i) & 1);
+            result |= (((value >> (127 - i)) & 1) ^ flip) << (127 - i);
+        }
+        result
+    }
+
+    /// Maps an address, keeping the unspecified address (of listening sockets).
+    pub fn addr(&mut self, addr: IpAddr) -> IpAddr {
+        if addr.is_unspecified() {
+            return addr;
+        }
+        if let Some(&mapped) = self.cache.get(&addr) {
+            return mapped;
+        }
+        let mapped = match addr {
+            IpAddr::V4(v4) => {
+                let bits = self.map_bits(u128::from(v4.to_bits()) << 96, 32);
+                IpAddr::V4(Ipv4Addr::from_bits((bits >> 96) as u32))
+            }
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => {
+                    let IpAddr::V4(v4) = self.addr(IpAddr::V4(v4)) else {
+                        unreachable!()
+                    };
+                    IpAddr::V6(v4.to_ipv6_mapped())
+                }
+                None => IpAddr::V6(Ipv6Addr::from_bits(self.map_bits(v6.to_bits(), 128))),
+            },
+        };
+        self.cache.insert(addr, mapped);
+        mapped
+    }
+
+    pub fn port(&self, port: u16) -> u16 {
+        if !self.ports || port == 0 {
+            return port;
+        }
+        // Cycle-walking keeps the permutation within 1..=65535.
+        let mut port = port;
+        loop {
+            let (mut l, mut r) = ((port >> 8) as u8, port as u8);
+            for round in 0..4 {
+                let f = self.hash(1, u128::from(r), round) as u8;
+                (l, r) = (r, l ^ f);
+            }
+            port = u16::from(l) << 8 | u16::from(r);
+            if port != 0 {
+                return port;
+            }
+        }
+    }
+}
+
+/// Collector that anonymizes the addresses (and ports) of all connections.
+pub struct Anonymize<C: Collector> {
+    inner: C,
+    anonymizer: Anonymizer,
+    buf: Vec<u8>,
+}
+
+impl<C: Collector> Anonymize<C> {
+    pub fn new(inner: C, anonymizer: Anonymizer) -> Self {
+        Self {
+            inner,
+            anonymizer,
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl<C: Collector> Collector for Anonymize<C> {
+    fn out(&mut self, data: &[u8]) {
+        self.buf.clear();
+        self.buf.extend_from_slice(data);
+        let (msg, _) = InetDiagMsg::mut_from_prefix(&mut self.buf).unwrap();
+        let family = msg.family;
+        msg.id.src = self.anonymizer.addr(msg.id.src.get(family)).into();
+        msg.id.dst = self.anonymizer.addr(msg.id.dst.get(family)).into();
+        msg.id.sport = U16BE::new(self.anonymizer.port(msg.id.sport.get()));
+        msg.id.dport = U16BE::new(self.anonymizer.port(msg.id.dport.get()));
+        self.inner.out(&self.buf);
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.inner.start(time);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.inner.end(duration);
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.inner.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.inner.unscheduled();
+    }
+
+    fn stats(&mut self, stats: &crate::SampleStats) {
+        self.inner.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.inner.offset(offset);
+    }
+
+    fn source(&mut self, source: &str) {
+        self.inner.source(source);
+    }
+
+    /// Drops the hostname and the filters (which may name addresses).
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        let metadata: Vec<_> = metadata
+            .iter()
+            .filter(|(key, _)| key != "hostname" && key != "filters")
+            .cloned()
+            .collect();
+        self.inner.metadata(&metadata);
+    }
+}
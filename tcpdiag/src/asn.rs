@@ -0,0 +1,193 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Read},
+    net::IpAddr,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use zerocopy::FromBytes;
+
+use crate::data::*;
+use crate::{Collector, SampleStats};
+
+/// Marker before the metadata of MaxMind DBs
+const MMDB_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+
+/// Longest-prefix table of a routeviews prefix-to-AS dump (`pfx2as`), with
+/// lines of network, prefix length, and AS number (like `1.0.0.0 24 13335`;
+/// multiple origins as `X_Y` and AS sets as `X,Y` are kept as they are).
+/// IPv4 networks are stored as IPv4-mapped IPv6 networks.
+#[derive(Default)]
+struct PrefixTable {
+    /// Networks by prefix length (of the IPv6 address), longest first
+    lengths: Vec<(u8, HashMap<u128, String>)>,
+}
+
+impl PrefixTable {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut networks: HashMap<u8, HashMap<u128, String>> = HashMap::new();
+        for (i, line) in text.lines().enumerate() {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let (addr, length, asn) = match fields[..] {
+                [] => continue,
+                [addr, length, asn] => (addr, length, asn),
+                _ => return Err(format!("line {}: expected network, length, and AS", i + 1)),
+            };
+            let invalid = || format!("line {}: invalid network {addr}/{length}", i + 1);
+            let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+            let length: u8 = length.parse().map_err(|_| invalid())?;
+            let (bits, length) = match addr {
+                IpAddr::V4(addr) if length <= 32 => (addr.to_ipv6_mapped().to_bits(), length + 96),
+                IpAddr::V6(addr) if length <= 128 => (addr.to_bits(), length),
+                _ => return Err(invalid()),
+            };
+            networks
+                .entry(length)
+                .or_default()
+                .insert(bits & mask(length), asn.into());
+        }
+        let mut lengths: Vec<_> = networks.into_iter().collect();
+        lengths.sort_unstable_by_key(|&(length, _)| std::cmp::Reverse(length));
+        Ok(Self { lengths })
+    }
+
+    fn lookup(&self, addr: IpAddr) -> Option<&str> {
+        let bits = match addr.to_canonical() {
+            IpAddr::V4(addr) => addr.to_ipv6_mapped().to_bits(),
+            IpAddr::V6(addr) => addr.to_bits(),
+        };
+        self.lengths
+            .iter()
+            .find_map(|(length, networks)| networks.get(&(bits & mask(*length))))
+            .map(String::as_str)
+    }
+}
+
+fn mask(length: u8) -> u128 {
+    u128::MAX.checked_shl(128 - u32::from(length)).unwrap_or(0)
+}
+
+enum Database {
+    Table(PrefixTable),
+    #[cfg(feature = "geoip")]
+    Mmdb(maxminddb::Reader<Vec<u8>>),
+}
+
+impl Database {
+    /// AS number and organization of an address
+    fn lookup(&self, addr: IpAddr) -> Result<(Option<String>, Option<&str>), String> {
+        match self {
+            Self::Table(table) => Ok((table.lookup(addr).map(Into::into), None)),
+            #[cfg(feature = "geoip")]
+            Self::Mmdb(reader) => {
+                match reader.lookup::<maxminddb::geoip2::Asn>(addr.to_canonical()) {
+                    Ok(asn) => Ok((
+                        asn.autonomous_system_number.map(|asn| asn.to_string()),
+                        asn.autonomous_system_organization,
+                    )),
+                    Err(maxminddb::MaxMindDBError::AddressNotFoundError(_)) => Ok((None, None)),
+                    Err(err) => Err(err.to_string()),
+                }
+            }
+        }
+    }
+}
+
+/// Collector that tags each socket with the autonomous system of the peer
+/// address: `asn` (the AS number) and, from MaxMind DBs, `as_org` (the
+/// organization). The database is a routeviews prefix-to-AS dump (optionally
+/// compressed) or, with the `geoip` feature, a MaxMind DB like GeoLite2-ASN.
+/// Sockets with peers that aren't in the database are passed on unchanged.
+pub struct Asn<C: Collector> {
+    inner: C,
+    path: String,
+    database: Database,
+    buf: Vec<u8>,
+}
+
+impl<C: Collector> Asn<C> {
+    pub fn new(path: &Path, inner: C) -> Self {
+        let fail = |err: &dyn std::fmt::Display| -> ! { panic!("{}: {err}", path.display()) };
+        let file = File::open(path).unwrap_or_else(|err| fail(&err));
+        let mut data = Vec::new();
+        crate::decompress(BufReader::new(file))
+            .read_to_end(&mut data)
+            .unwrap_or_else(|err| fail(&err));
+        let database = if memchr::memmem::rfind(&data, MMDB_MARKER).is_some() {
+            #[cfg(feature = "geoip")]
+            {
+                Database::Mmdb(
+                    maxminddb::Reader::from_source(data).unwrap_or_else(|err| fail(&err)),
+                )
+            }
+            #[cfg(not(feature = "geoip"))]
+            fail(&"MaxMind DBs require the geoip feature")
+        } else {
+            let text = String::from_utf8(data).unwrap_or_else(|err| fail(&err));
+            Database::Table(PrefixTable::parse(&text).unwrap_or_else(|err| fail(&err)))
+        };
+        Self {
+            inner,
+            path: path.display().to_string(),
+            database,
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl<C: Collector> Collector for Asn<C> {
+    fn out(&mut self, data: &[u8]) {
+        let (msg, _) = InetDiagMsg::ref_from_prefix(data).unwrap();
+        let (asn, org) = match self.database.lookup(msg.id.dst.get(msg.family)) {
+            Ok(asn) => asn,
+            Err(err) => {
+                crate::diagnostic!("{}: {err}", self.path);
+                (None, None)
+            }
+        };
+        let tags: Vec<_> = [("asn", asn.as_deref()), ("as_org", org)]
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?)))
+            .collect();
+        if tags.is_empty() {
+            self.inner.out(data);
+        } else {
+            add_tags(data, &tags, &mut self.buf);
+            self.inner.out(&self.buf);
+        }
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.inner.start(time);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.inner.end(duration);
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.inner.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.inner.unscheduled();
+    }
+
+    fn source(&mut self, source: &str) {
+        self.inner.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.inner.metadata(metadata);
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.inner.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.inner.offset(offset);
+    }
+}
@@ -1,81 +1,220 @@
 use std::{
-    io::{BufReader, Read, StdinLock, Write},
+    io::{BufRead, ErrorKind, Write},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use zerocopy::IntoBytes;
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
 
 use crate::data::*;
 
-use crate::Collector;
+use crate::{Collector, SampleStats};
+
+/// Checksum record (type 9) in front of the records of each sample (from
+/// its start to its end), which the reader verifies before passing them on
+#[derive(KnownLayout, Immutable, FromBytes, IntoBytes, Default, Debug)]
+#[repr(C)]
+struct Frame {
+    /// Length of the following records in bytes
+    len: u32,
+    /// CRC-32 (as used by zlib) of the following records
+    crc: u32,
+}
 
 pub struct BinaryOutput<T: Write> {
     writer: T,
+    /// Records of the current sample, written with their checksum at its end
+    sample: Vec<u8>,
+    in_sample: bool,
 }
 
 crate::impl_output!(BinaryOutput<T>);
 
 impl<T: Write> BinaryOutput<T> {
     pub fn new(writer: T) -> Self {
-        Self { writer }
+        Self {
+            writer,
+            sample: Vec::new(),
+            in_sample: false,
+        }
     }
 
+    /// Writes a record, or adds it to the current sample.
     fn write_ts(&mut self, ty: u16, data: &[u8]) {
-        self.push_header(ty, data.len());
-        self.writer.write_all(data).unwrap();
+        self.sample.extend(header(ty, data.len()).as_bytes());
+        self.sample.extend(data);
+        if !self.in_sample {
+            self.writer.write_all(&self.sample).unwrap();
+            self.sample.clear();
+        }
     }
+}
 
-    fn push_header(&mut self, ty: u16, len: usize) {
-        self.writer
-            .write_all(
-                nlattr {
-                    nla_len: u16::try_from(std::mem::size_of::<nlattr>() + len).unwrap(),
-                    nla_type: ty,
-                }
-                .as_bytes(),
-            )
-            .unwrap()
+fn header(ty: u16, len: usize) -> nlattr {
+    nlattr {
+        nla_len: u16::try_from(std::mem::size_of::<nlattr>() + len).unwrap(),
+        nla_type: ty,
     }
 }
 
 impl<T: Write> Collector for BinaryOutput<T> {
     fn out(&mut self, data: &[u8]) {
-        self.push_header(0, data.len());
-        self.writer.write_all(data).unwrap();
+        self.write_ts(0, data);
     }
 
     fn start(&mut self, time: SystemTime) {
+        self.in_sample = true;
         let ts = time.duration_since(UNIX_EPOCH).unwrap().as_micros() as u64;
         self.write_ts(1, &ts.to_ne_bytes())
     }
 
     fn end(&mut self, duration: Duration) {
-        self.write_ts(2, u32::try_from(duration.as_micros()).unwrap().as_bytes());
+        self.write_ts(2, &(duration.as_micros() as u64).to_ne_bytes());
+        self.in_sample = false;
+        let frame = Frame {
+            len: u32::try_from(self.sample.len()).unwrap(),
+            crc: crc32fast::hash(&self.sample),
+        };
+        let header = header(9, std::mem::size_of::<Frame>());
+        self.writer.write_all(header.as_bytes()).unwrap();
+        self.writer.write_all(frame.as_bytes()).unwrap();
+        self.writer.write_all(&self.sample).unwrap();
+        self.sample.clear();
         self.writer.flush().unwrap();
     }
+
+    fn period(&mut self, period: Duration) {
+        self.write_ts(3, &(period.as_micros() as u64).to_ne_bytes());
+    }
+
+    fn unscheduled(&mut self) {
+        self.write_ts(5, &[]);
+    }
+
+    fn source(&mut self, source: &str) {
+        self.write_ts(4, source.as_bytes());
+    }
+
+    /// Written as lines of `key: value`.
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        let text: String = metadata
+            .iter()
+            .map(|(key, value)| format!("{key}: {}\n", value.replace('\n', " ")))
+            .collect();
+        self.write_ts(6, text.as_bytes());
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.write_ts(7, stats.as_bytes());
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.write_ts(8, u32::try_from(offset.as_micros()).unwrap().as_bytes());
+    }
+}
+
+/// Reads the lines of `key: value` of a metadata record.
+pub(crate) fn parse_metadata(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|line| line.split_once(": "))
+        .map(|(key, value)| (key.into(), value.into()))
+        .collect()
+}
+
+pub fn read_binary<R: BufRead, C: Collector>(reader: R, writer: C) {
+    try_read_binary(reader, writer).unwrap()
+}
+
+/// Like `read_binary`, but returns read errors and malformed records (as
+/// `InvalidData`), e.g. for streams from the network. A record cut off by
+/// the end of the input is `UnexpectedEof`. The records of a sample with a
+/// checksum are only passed on if it matches.
+pub fn try_read_binary<R: BufRead, C: Collector>(
+    mut reader: R,
+    mut writer: C,
+) -> std::io::Result<()> {
+    read_records(&mut reader, &mut writer, Some(&mut Vec::new()))
 }
 
-pub fn read_binary(mut reader: BufReader<StdinLock>, mut writer: Box<dyn Collector>) {
+/// Reads records until the end of the input. `sample` is the buffer for
+/// the records of a sample with a checksum, which are read by a nested call
+/// (without buffer, as checksums can't be nested).
+fn read_records<R: BufRead, C: Collector>(
+    reader: &mut R,
+    writer: &mut C,
+    mut sample: Option<&mut Vec<u8>>,
+) -> std::io::Result<()> {
+    let invalid = |msg: String| std::io::Error::new(ErrorKind::InvalidData, msg);
+    fn utf8(buf: &[u8]) -> std::io::Result<&str> {
+        std::str::from_utf8(buf).map_err(|err| std::io::Error::new(ErrorKind::InvalidData, err))
+    }
     let mut buf = Vec::new();
+    // Position of the current record in the input
+    let mut offset = 0;
     loop {
         let mut attr = nlattr::default();
-        let s = reader.read(attr.as_mut_bytes()).unwrap();
+        let s = reader.read(attr.as_mut_bytes())?;
         if s == 0 {
-            break;
+            return Ok(());
         }
-        reader.read_exact(&mut attr.as_mut_bytes()[s..]).unwrap();
-        buf.resize(usize::from(attr.nla_len) - std::mem::size_of_val(&attr), 0);
-        reader.read_exact(&mut buf[..]).unwrap();
+        reader.read_exact(&mut attr.as_mut_bytes()[s..])?;
+        let len = usize::from(attr.nla_len)
+            .checked_sub(std::mem::size_of_val(&attr))
+            .ok_or_else(|| invalid(format!("invalid record length {}", attr.nla_len)))?;
+        buf.resize(len, 0);
+        reader.read_exact(&mut buf[..])?;
+        let record_offset = offset;
+        offset += usize::from(attr.nla_len);
+        let wrong_len = || invalid(format!("record of type {} has {len} bytes", attr.nla_type));
         match attr.nla_type {
             0 => writer.out(&buf[..]),
             1 => {
-                let time = u64::from_ne_bytes(buf[..].try_into().unwrap());
+                let time = u64::from_ne_bytes(buf[..].try_into().map_err(|_| wrong_len())?);
                 writer.start(UNIX_EPOCH + Duration::from_micros(time));
             }
+            // 4 bytes in captures of earlier versions
             2 => {
-                let duration = u32::from_ne_bytes(buf[..].try_into().unwrap());
-                writer.end(Duration::from_micros(duration.into()));
+                let duration = match buf.len() {
+                    4 => u32::from_ne_bytes(buf[..].try_into().unwrap()).into(),
+                    _ => u64::from_ne_bytes(buf[..].try_into().map_err(|_| wrong_len())?),
+                };
+                writer.end(Duration::from_micros(duration));
+            }
+            3 => {
+                let period = u64::from_ne_bytes(buf[..].try_into().map_err(|_| wrong_len())?);
+                writer.period(Duration::from_micros(period));
+            }
+            4 => writer.source(utf8(&buf)?),
+            5 => writer.unscheduled(),
+            6 => writer.metadata(&parse_metadata(utf8(&buf)?)),
+            7 => {
+                // 24 bytes (without the boottime) or 32 bytes (without the
+                // counters after it) in captures of earlier versions
+                let mut stats = SampleStats::default();
+                match buf.len() {
+                    len @ (24 | 32) => stats.as_mut_bytes()[..len].copy_from_slice(&buf),
+                    _ => stats = SampleStats::read_from_bytes(&buf[..]).map_err(|_| wrong_len())?,
+                }
+                writer.stats(&stats);
+            }
+            8 => {
+                let offset = u32::from_ne_bytes(buf[..].try_into().map_err(|_| wrong_len())?);
+                writer.offset(Duration::from_micros(offset.into()));
+            }
+            9 => {
+                let frame = Frame::read_from_bytes(&buf[..]).map_err(|_| wrong_len())?;
+                let sample = sample
+                    .as_deref_mut()
+                    .ok_or_else(|| invalid("nested checksum record".into()))?;
+                sample.resize(frame.len as usize, 0);
+                reader.read_exact(&mut sample[..])?;
+                offset += sample.len();
+                if crc32fast::hash(sample) != frame.crc {
+                    return Err(invalid(format!(
+                        "checksum mismatch in the sample at offset {record_offset}"
+                    )));
+                }
+                read_records(&mut &sample[..], writer, None)?;
             }
-            _ => panic!(),
+            ty => return Err(invalid(format!("unknown record type {ty}"))),
         }
     }
 }
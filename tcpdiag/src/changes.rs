@@ -0,0 +1,171 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use zerocopy::{FromBytes, IntoBytes};
+
+use crate::data::*;
+use crate::Collector;
+
+/// Metadata entry that marks a capture of changed records only
+const METADATA: (&str, &str) = ("changed_only", "true");
+
+/// Last record of a socket
+struct Socket {
+    record: Vec<u8>,
+    seen: bool,
+}
+
+/// Collector that forwards a socket only if its record changed since the
+/// previous sample (compared by cookie, per source). Fields that age while a
+/// connection idles (the timer expiry and the `last_*` times of tcp_info) are
+/// not compared, but written with the other changes.
+///
+/// A socket that is gone is written once more at the end of the sample, with
+/// its last record in state CLOSE (7). The sockets of each sample are thereby
+/// reconstructible, by carrying over the last record of each cookie until it
+/// is in state CLOSE. The metadata is marked with `changed_only: true`.
+pub struct ChangedOnly<C: Collector> {
+    inner: C,
+    sockets: HashMap<String, HashMap<u64, Socket>>,
+    source: String,
+    metadata: bool,
+    a: Vec<u8>,
+    b: Vec<u8>,
+}
+
+impl<C: Collector> ChangedOnly<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            sockets: HashMap::new(),
+            source: String::new(),
+            metadata: false,
+            a: Vec::new(),
+            b: Vec::new(),
+        }
+    }
+
+    /// Writes the metadata entry for captures without metadata.
+    fn mark(&mut self) {
+        if !std::mem::replace(&mut self.metadata, true) {
+            self.inner
+                .metadata(&[(METADATA.0.into(), METADATA.1.into())]);
+        }
+    }
+}
+
+/// Appends the compared parts of a record to `buf`: the message without the
+/// timer expiry and the attributes, with tcp_info without the `last_*` times.
+fn monitored(data: &[u8], buf: &mut Vec<u8>) {
+    let (mut msg, extra) = InetDiagMsg::read_from_prefix(data).unwrap();
+    msg.expires = 0;
+    buf.extend(msg.as_bytes());
+    for attribute in NlattrIter::new(extra) {
+        buf.extend(attribute.hdr.as_bytes());
+        match TcpInfo::read_from_prefix(&attribute.data) {
+            Ok((mut info, tail)) if attribute.hdr.nla_type == INET_DIAG_INFO => {
+                info.last_data_sent = 0;
+                info.last_ack_sent = 0;
+                info.last_data_recv = 0;
+                info.last_ack_recv = 0;
+                buf.extend(info.as_bytes());
+                buf.extend(tail);
+            }
+            _ => buf.extend(&attribute.data),
+        }
+    }
+}
+
+impl<C: Collector> Collector for ChangedOnly<C> {
+    fn source(&mut self, source: &str) {
+        self.mark();
+        self.source = source.into();
+        self.inner.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        let mut metadata = metadata.to_vec();
+        if !metadata.iter().any(|(key, _)| key == METADATA.0) {
+            metadata.push((METADATA.0.into(), METADATA.1.into()));
+        }
+        self.metadata = true;
+        self.inner.metadata(&metadata);
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.mark();
+        self.inner.start(time);
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        let (msg, _) = InetDiagMsg::ref_from_prefix(data).unwrap();
+        let cookie = msg.id.cookie.get();
+        if !self.sockets.contains_key(&self.source) {
+            self.sockets.insert(self.source.clone(), HashMap::new());
+        }
+        let sockets = self.sockets.get_mut(&self.source).unwrap();
+        let changed = match sockets.get_mut(&cookie) {
+            Some(socket) => {
+                socket.seen = true;
+                self.a.clear();
+                self.b.clear();
+                monitored(&socket.record, &mut self.a);
+                monitored(data, &mut self.b);
+                if self.a != self.b {
+                    socket.record.clear();
+                    socket.record.extend(data);
+                }
+                self.a != self.b
+            }
+            None => {
+                let record = data.to_vec();
+                sockets.insert(cookie, Socket { record, seen: true });
+                true
+            }
+        };
+        // Reported in state CLOSE, it counts as gone.
+        if msg.state == TCP_CLOSE {
+            sockets.remove(&cookie);
+        }
+        if changed {
+            self.inner.out(data);
+        }
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.inner.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.inner.unscheduled();
+    }
+
+    fn stats(&mut self, stats: &crate::SampleStats) {
+        self.inner.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.inner.offset(offset);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        if let Some(sockets) = self.sockets.get_mut(&self.source) {
+            let mut gone: Vec<_> = sockets
+                .iter_mut()
+                .filter_map(|(&cookie, socket)| {
+                    (!std::mem::take(&mut socket.seen)).then_some(cookie)
+                })
+                .collect();
+            gone.sort_unstable();
+            for cookie in gone {
+                let mut record = sockets.remove(&cookie).unwrap().record;
+                let (msg, _) = InetDiagMsg::mut_from_prefix(&mut record).unwrap();
+                msg.state = TCP_CLOSE;
+                self.inner.out(&record);
+            }
+        }
+        self.inner.end(duration);
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use crate::compare::Profile;
+use crate::data::InetDiagMsgExtra;
+use crate::human::{self, Unit};
+use crate::stats::Stats;
+use crate::timestamp::Timestamp;
+use crate::Collector;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Metric {
+    Sockets,
+    Retrans,
+    RetransRate,
+    RttMean,
+    /// RTT percentile, with min and max as 0 and 100
+    Rtt(f64),
+}
+
+impl Metric {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "sockets" => Self::Sockets,
+            "retrans" => Self::Retrans,
+            "retrans_rate" => Self::RetransRate,
+            "rtt_mean" => Self::RttMean,
+            "rtt_min" => Self::Rtt(0.0),
+            "rtt_max" => Self::Rtt(100.0),
+            _ => match name.strip_prefix("rtt_p").map(f64::from_str) {
+                Some(Ok(p)) if (0.0..=100.0).contains(&p) => Self::Rtt(p),
+                _ => return Err(format!("unknown metric {name:?}")),
+            },
+        })
+    }
+
+    fn unit(self) -> Option<Unit> {
+        matches!(self, Self::RttMean | Self::Rtt(_)).then_some(Unit::Micros)
+    }
+
+    /// Value in microseconds for times, otherwise unscaled.
+    fn eval(self, stats: &Stats) -> Option<f64> {
+        match self {
+            Self::Sockets => Some(stats.sockets as f64),
+            Self::Retrans => Some(stats.total_retrans as f64),
+            Self::RetransRate => Some(stats.retrans_rate()),
+            Self::RttMean => stats.rtt_mean(),
+            Self::Rtt(p) => stats.rtt_percentile(p).map(f64::from),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Op {
+    fn apply(self, a: f64, b: f64) -> bool {
+        match self {
+            Self::Lt => a < b,
+            Self::Le => a <= b,
+            Self::Gt => a > b,
+            Self::Ge => a >= b,
+            Self::Eq => a == b,
+            Self::Ne => a != b,
+        }
+    }
+}
+
+/// Parses a number with an optional unit (us, ms, s, or %).
+fn parse_value(value: &str) -> Result<f64, String> {
+    let (number, scale) = [("us", 1.0), ("ms", 1e3), ("s", 1e6), ("%", 0.01)]
+        .into_iter()
+        .find_map(|(suffix, scale)| Some((value.strip_suffix(suffix)?, scale)))
+        .unwrap_or((value, 1.0));
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid value {value:?}"))?;
+    Ok(number * scale)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Assertion {
+    text: String,
+    metric: Metric,
+    op: Op,
+    value: f64,
+}
+
+impl FromStr for Assertion {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        const OPS: [(&str, Op); 6] = [
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+        ];
+        let text = text.trim();
+        let (pos, token, op) = OPS
+            .iter()
+            .find_map(|&(token, op)| Some((text.find(token)?, token, op)))
+            .ok_or_else(|| format!("missing comparison in {text:?}"))?;
+        Ok(Self {
+            text: text.into(),
+            metric: Metric::parse(text[..pos].trim())?,
+            op,
+            value: parse_value(text[pos + token.len()..].trim())?,
+        })
+    }
+}
+
+/// Conjunction of assertions, like `retrans_rate < 0.01 && rtt_p95 < 50ms`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Check(Vec<Assertion>);
+
+impl FromStr for Check {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        s.split("&&")
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// Evaluates `checks` on `stats`, reporting failed assertions and those
+/// without data (e.g. RTTs without connections) `at` a sample or the capture.
+/// Returns the numbers of both.
+fn evaluate(checks: &[Check], stats: &Stats, at: &str) -> (u64, u64) {
+    let (mut failures, mut no_data) = (0, 0);
+    for assertion in checks.iter().flat_map(|c| &c.0) {
+        let Some(actual) = assertion.metric.eval(stats) else {
+            crate::diagnostic!("assertion without data {at}: {}", assertion.text);
+            no_data += 1;
+            continue;
+        };
+        if assertion.op.apply(actual, assertion.value) {
+            continue;
+        }
+        let actual = match assertion.metric.unit() {
+            Some(unit) => human::format(unit, actual as u64),
+            None => actual.to_string(),
+        };
+        crate::diagnostic!(
+            "assertion failed {at}: {} (actual: {actual})",
+            assertion.text
+        );
+        failures += 1;
+    }
+    (failures, no_data)
+}
+
+/// Collector that evaluates checks on each sample, and capture checks on the
+/// whole capture (see `Profile::totals`) in `finish`, and forwards all data.
+pub struct Checker<C: Collector> {
+    inner: C,
+    checks: Vec<Check>,
+    capture_checks: Vec<Check>,
+    time: SystemTime,
+    stats: Stats,
+    profile: Profile,
+    failures: u64,
+    no_data: u64,
+}
+
+impl<C: Collector> Checker<C> {
+    pub fn new(inner: C, checks: Vec<Check>, capture_checks: Vec<Check>) -> Self {
+        Self {
+            inner,
+            checks,
+            capture_checks,
+            time: SystemTime::UNIX_EPOCH,
+            stats: Stats::default(),
+            profile: Profile::default(),
+            failures: 0,
+            no_data: 0,
+        }
+    }
+
+    /// Evaluates the capture checks, once the capture has ended.
+    pub fn finish(&mut self) {
+        let checks = std::mem::take(&mut self.capture_checks);
+        let (failures, no_data) = evaluate(&checks, &self.profile.totals(), "for the capture");
+        self.failures += failures;
+        self.no_data += no_data;
+    }
+
+    /// Number of failed assertions over all samples and the capture.
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+
+    /// Number of assertions that could not be evaluated for lack of data,
+    /// which are not counted as failed.
+    pub fn no_data(&self) -> u64 {
+        self.no_data
+    }
+}
+
+impl<C: Collector> Collector for Checker<C> {
+    fn out(&mut self, data: &[u8]) {
+        if !self.checks.is_empty() {
+            self.stats.add(&InetDiagMsgExtra::parse(data));
+        }
+        if !self.capture_checks.is_empty() {
+            self.profile.out(data);
+        }
+        self.inner.out(data);
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.time = time;
+        self.stats = Stats::default();
+        self.profile.start(time);
+        self.inner.start(time);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.inner.end(duration);
+        self.profile.end(duration);
+        let at = format!("at {}", Timestamp::new(self.time).0);
+        let (failures, no_data) = evaluate(&self.checks, &self.stats, &at);
+        self.failures += failures;
+        self.no_data += no_data;
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.inner.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.inner.unscheduled();
+    }
+
+    fn stats(&mut self, stats: &crate::SampleStats) {
+        self.inner.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.inner.offset(offset);
+    }
+
+    fn source(&mut self, source: &str) {
+        self.inner.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.inner.metadata(metadata);
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    time::{Duration, SystemTime},
+};
+
+use crate::data::*;
+use crate::human::{self, Unit};
+use crate::stats::Stats;
+
+use crate::Collector;
+
+/// Counters of a connection at its first and last observation
+struct Counters {
+    first: [u64; 3],
+    last: [u64; 3],
+}
+
+fn counters(info: &TcpInfo) -> [u64; 3] {
+    [
+        info.total_retrans.into(),
+        info.segs_out.into(),
+        info.bytes_acked.get(),
+    ]
+}
+
+/// Key metrics of a whole capture, for A/B comparisons. RTTs are taken from
+/// all observations; retransmissions, segments, and acked bytes from the
+/// increase of the counters of each connection (counted from zero if it
+/// appeared after the first sample).
+#[derive(Default)]
+pub struct Profile {
+    stats: Stats,
+    connections: HashMap<u64, Counters>,
+    first: Option<SystemTime>,
+    last: Option<SystemTime>,
+    /// Start of the current sample
+    time: Option<SystemTime>,
+}
+
+impl Collector for Profile {
+    fn start(&mut self, time: SystemTime) {
+        self.time = Some(time);
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        let extras = InetDiagMsgExtra::parse(data);
+        self.stats.add(&extras);
+        let Some(info) = extras.tcp_info.filter(|_| extras.base.state != TCP_LISTEN) else {
+            return;
+        };
+        let counters = counters(info);
+        let first_sample = self.first.is_none();
+        self.connections
+            .entry(extras.base.id.cookie.get())
+            .and_modify(|c| c.last = counters)
+            .or_insert(Counters {
+                first: if first_sample { counters } else { [0; 3] },
+                last: counters,
+            });
+    }
+
+    fn end(&mut self, _duration: Duration) {
+        self.first = self.first.or(self.time);
+        self.last = self.time;
+    }
+}
+
+impl Profile {
+    /// Sum of the increases of a counter over all connections
+    fn increase(&self, counter: usize) -> u64 {
+        self.connections
+            .values()
+            .map(|c| c.last[counter].saturating_sub(c.first[counter]))
+            .sum()
+    }
+
+    /// Statistics of the capture as a whole: the connections (instead of
+    /// sockets), the increases of their counters, and the RTTs of all
+    /// observations
+    pub(crate) fn totals(&self) -> Stats {
+        Stats {
+            sockets: self.connections.len() as u64,
+            rtt: self.stats.rtt.clone(),
+            total_retrans: self.increase(0),
+            segs_out: self.increase(1),
+            bytes_acked: self.increase(2),
+            ..Stats::default()
+        }
+    }
+
+    /// Time from the first to the last sample
+    fn span(&self) -> Option<Duration> {
+        self.last?.duration_since(self.first?).ok()
+    }
+
+    fn metrics(&self) -> [Option<f64>; METRICS.len()] {
+        let rtt = |p| self.stats.rtt_percentile(p).map(f64::from);
+        let segs_out = self.increase(1);
+        [
+            self.stats.rtt_mean(),
+            rtt(50.0),
+            rtt(95.0),
+            rtt(99.0),
+            (segs_out > 0).then(|| self.increase(0) as f64 / segs_out as f64),
+            self.span()
+                .filter(|span| !span.is_zero())
+                .map(|span| self.increase(2) as f64 / span.as_secs_f64()),
+        ]
+    }
+}
+
+/// Compared metrics with their unit and whether higher values are better
+const METRICS: [(&str, Option<Unit>, bool); 6] = [
+    ("rtt_mean", Some(Unit::Micros), false),
+    ("rtt_p50", Some(Unit::Micros), false),
+    ("rtt_p95", Some(Unit::Micros), false),
+    ("rtt_p99", Some(Unit::Micros), false),
+    ("retrans_rate", None, false),
+    ("throughput", Some(Unit::BytesPerSec), true),
+];
+
+/// Writes the metrics of both captures, with changes beyond `tolerance`
+/// (relative) marked as regression or improvement. Returns the number of
+/// regressions.
+pub fn write_comparison<W: Write>(
+    baseline: &Profile,
+    capture: &Profile,
+    tolerance: f64,
+    human: bool,
+    mut w: W,
+) -> usize {
+    let value = |unit: Option<Unit>, value: Option<f64>| match (value, unit) {
+        (None, _) => "-".to_string(),
+        (Some(value), Some(unit)) if human => human::format(unit, value as u64),
+        (Some(value), Some(_)) => format!("{value:.0}"),
+        (Some(value), None) => format!("{value:.6}"),
+    };
+    let mut regressions = 0;
+    writeln!(
+        w,
+        "{:12} {:>12} {:>12} {:>8}",
+        "metric", "baseline", "capture", "change"
+    )
+    .unwrap();
+    let metrics = baseline.metrics().into_iter().zip(capture.metrics());
+    for ((name, unit, higher_is_better), (old, new)) in METRICS.into_iter().zip(metrics) {
+        let (change, verdict) = match (old, new) {
+            (Some(old), Some(new)) => {
+                // Infinite if the baseline is zero
+                let change = if old == new {
+                    0.0
+                } else {
+                    (new - old) / old.abs()
+                };
+                let better = if higher_is_better { change } else { -change };
+                let verdict = if better < -tolerance {
+                    regressions += 1;
+                    "regression"
+                } else if better > tolerance {
+                    "improvement"
+                } else {
+                    ""
+                };
+                (format!("{:+.1}%", change * 100.0), verdict)
+            }
+            _ => ("-".to_string(), ""),
+        };
+        let line = format!(
+            "{name:12} {:>12} {:>12} {change:>8} {verdict}",
+            value(unit, old),
+            value(unit, new),
+        );
+        writeln!(w, "{}", line.trim_end()).unwrap();
+    }
+    writeln!(
+        w,
+        "connections: {} in the baseline, {} in the capture",
+        baseline.connections.len(),
+        capture.connections.len(),
+    )
+    .unwrap();
+    w.flush().unwrap();
+    regressions
+}
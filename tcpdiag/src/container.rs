@@ -0,0 +1,146 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use zerocopy::FromBytes;
+
+use crate::data::*;
+use crate::{Collector, SampleStats};
+
+/// Container ID in a cgroup path, like `/docker/ID`,
+/// `/system.slice/docker-ID.scope`, or
+/// `/kubepods.slice/.../cri-containerd-ID.scope` (IDs are 64 hex digits).
+pub fn container_id(cgroup: &str) -> Option<&str> {
+    cgroup.rsplit('/').find_map(|name| {
+        let name = name.strip_suffix(".scope").unwrap_or(name);
+        let id = name.rsplit_once('-').map_or(name, |(_, id)| id);
+        (id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())).then_some(id)
+    })
+}
+
+/// Container of a process, from the cgroup paths in `/proc/PID/cgroup`
+fn process_container(pid: &str) -> Option<String> {
+    let cgroups = std::fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+    cgroups
+        .lines()
+        .filter_map(|line| line.splitn(3, ':').nth(2))
+        .find_map(container_id)
+        .map(Into::into)
+}
+
+/// Maps the inodes of the sockets of all processes to their container (none
+/// for processes outside containers), from the file descriptors in /proc.
+fn scan(sockets: &mut HashMap<u32, Option<String>>) {
+    sockets.clear();
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return;
+    };
+    for process in processes.flatten() {
+        let pid = process.file_name();
+        let Some(pid) = pid
+            .to_str()
+            .filter(|pid| pid.bytes().all(|b| b.is_ascii_digit()))
+        else {
+            continue;
+        };
+        // Processes may exit or deny access while scanning.
+        let Ok(fds) = std::fs::read_dir(format!("/proc/{pid}/fd")) else {
+            continue;
+        };
+        let container = process_container(pid);
+        for fd in fds.flatten() {
+            let Ok(target) = std::fs::read_link(fd.path()) else {
+                continue;
+            };
+            let inode = target
+                .to_str()
+                .and_then(|target| target.strip_prefix("socket:["))
+                .and_then(|target| target.strip_suffix(']'))
+                .and_then(|inode| inode.parse().ok());
+            if let Some(inode) = inode {
+                sockets.entry(inode).or_insert_with(|| container.clone());
+            }
+        }
+    }
+}
+
+/// Collector that tags each socket with the `container` ID of the process
+/// that has it open (Docker, containerd, CRI-O, or Podman), found by the
+/// socket inode in the file descriptors of the processes in /proc and their
+/// cgroup. /proc is scanned at most once per sample, when a socket isn't
+/// known yet. Sockets without a process (like in TIME-WAIT) or of processes
+/// outside containers are passed on unchanged.
+pub struct Containers<C: Collector> {
+    inner: C,
+    /// Container of the sockets by inode, from the last scan
+    sockets: HashMap<u32, Option<String>>,
+    /// Whether /proc was scanned in the current sample
+    scanned: bool,
+    buf: Vec<u8>,
+}
+
+impl<C: Collector> Containers<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            sockets: HashMap::new(),
+            scanned: false,
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl<C: Collector> Collector for Containers<C> {
+    fn out(&mut self, data: &[u8]) {
+        let (msg, _) = InetDiagMsg::ref_from_prefix(data).unwrap();
+        let inode = msg.inode;
+        if inode == 0 {
+            return self.inner.out(data);
+        }
+        if !self.sockets.contains_key(&inode) && !std::mem::replace(&mut self.scanned, true) {
+            scan(&mut self.sockets);
+        }
+        // Not to scan again for sockets without a process.
+        match self.sockets.entry(inode).or_default() {
+            Some(container) => {
+                add_tags(data, &[("container", container)], &mut self.buf);
+                self.inner.out(&self.buf);
+            }
+            None => self.inner.out(data),
+        }
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.scanned = false;
+        self.inner.start(time);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.inner.end(duration);
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.inner.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.inner.unscheduled();
+    }
+
+    fn source(&mut self, source: &str) {
+        self.inner.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.inner.metadata(metadata);
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.inner.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.inner.offset(offset);
+    }
+}
@@ -1,38 +1,72 @@
 use std::{
     collections::HashMap,
-    io::{BufRead, BufReader, StdinLock, Write},
+    io::{BufRead, Write},
+    ops::Range,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use crate::data::*;
+use crate::timestamp::{TimeFormat, Timestamp};
 
-use crate::Collector;
+use crate::{Collector, SampleStats};
 use csv::{Csv, CsvWrite};
 
 pub struct CsvOutput<T: Write> {
     writer: T,
+    /// Whether the header lines are still to be written
+    header: bool,
     time: SystemTime,
-    trailer: &'static str,
+    /// Whether the last line still lacks the trailing columns
+    pending: bool,
+    period: Option<Duration>,
+    unscheduled: bool,
+    stats: Option<SampleStats>,
+    offset: Option<Duration>,
+    source: Option<String>,
+    human: bool,
+    time_format: TimeFormat,
+    /// Comments to be written at the end of the sample
+    comments: Vec<String>,
+    in_sample: bool,
+    buf: Vec<u8>,
 }
 
 crate::impl_output!(CsvOutput<T>);
 
 #[derive(CsvWrite)]
+#[csv(context(format: TimeFormat))]
 struct CsvLine<'a> {
-    time: u64,
+    #[csv(pass(format))]
+    time: Timestamp,
     #[csv(flatten())]
     data: Option<InetDiagMsgExtra<'a>>,
 }
 
 #[derive(Csv)]
+#[csv(context(format: TimeFormat))]
 struct CsvLineOwned {
-    time: u64,
+    #[csv(pass(format))]
+    time: Timestamp,
     #[csv(flatten())]
     data: Option<InetDiagMsgExtraOwned>,
     duration: Option<u64>,
+    period: Option<u64>,
+    source: Option<String>,
+    /// 1 for samples taken on demand
+    unscheduled: Option<u8>,
+    /// `SampleStats` of the dump
+    sockets: Option<u32>,
+    sockets_inet4: Option<u32>,
+    sockets_inet6: Option<u32>,
+    netlink_bytes: Option<u64>,
+    boottime: Option<u64>,
+    netlink_messages: Option<u32>,
+    cpu_time: Option<u64>,
+    netlink_errors: Option<u32>,
+    dropped_samples: Option<u32>,
 }
 
-const CSV_HEADER: &str = csv::post_process(
+pub(crate) const CSV_HEADER: &str = csv::post_process(
     &const {
         const DESC: &csv::Desc = &CsvLineOwned::DESC;
         const SIZE: usize = DESC.desc_size();
@@ -43,109 +77,479 @@ const CSV_HEADER: &str = csv::post_process(
     },
 );
 
+/// Appends the columns of a socket up to the trailing columns (separated by
+/// spaces, like `CSV_HEADER`) to `buf`, with the time in microseconds.
+pub(crate) fn write_socket(time: SystemTime, extras: InetDiagMsgExtra, buf: &mut Vec<u8>) {
+    let line = CsvLine {
+        time: Timestamp::new(time),
+        data: Some(extras),
+    };
+    CsvLine::write(&line, &TimeFormat::Micros, buf);
+}
+
+/// Unit of a column, for the `units` comment
+fn column_unit(column: &str) -> Option<&'static str> {
+    match column {
+        "duration" | "period" | "boottime" | "cpu_time" => Some("us"),
+        "netlink_bytes" => Some("B"),
+        _ => crate::human::unit(column).map(crate::human::Unit::symbol),
+    }
+}
+
 impl<T: Write> CsvOutput<T> {
-    pub fn new(mut writer: T) -> Self {
-        writeln!(&mut writer, "{CSV_HEADER}").unwrap();
+    /// Output starting with the header line, followed by a comment line
+    /// `# units: column=unit ...` (unless values are scaled by `set_human`).
+    pub fn new(writer: T) -> Self {
+        Self {
+            header: true,
+            ..Self::without_header(writer)
+        }
+    }
+
+    /// Output without the header line, e.g. for appending to a csv capture.
+    pub fn without_header(writer: T) -> Self {
         Self {
             writer,
+            header: false,
             time: UNIX_EPOCH,
-            trailer: "",
+            pending: false,
+            period: None,
+            unscheduled: false,
+            stats: None,
+            offset: None,
+            source: None,
+            human: false,
+            time_format: TimeFormat::default(),
+            comments: Vec::new(),
+            in_sample: false,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Scales values to human-readable units. The result cannot be read back.
+    pub fn set_human(&mut self, human: bool) {
+        self.human = human;
+    }
+
+    pub fn set_time_format(&mut self, time_format: TimeFormat) {
+        self.time_format = time_format;
+    }
+
+    /// Adds comment lines (`# ` and a line of `comment` each), e.g. to
+    /// annotate events in a capture. Within a sample, they are written at its
+    /// end, as comments can't interrupt its lines. Readers pass on comments of
+    /// the form `key: value` as metadata and skip others.
+    pub fn comment(&mut self, comment: &str) {
+        if self.in_sample {
+            self.comments.push(comment.into());
+        } else {
+            self.write_header();
+            self.write_comment(comment);
+        }
+    }
+
+    fn write_comment(&mut self, comment: &str) {
+        for line in comment.lines() {
+            writeln!(&mut self.writer, "# {line}").unwrap();
+        }
+    }
+
+    /// Writes the header lines before the first output, when `set_human` is
+    /// known.
+    fn write_header(&mut self) {
+        if !std::mem::take(&mut self.header) {
+            return;
+        }
+        writeln!(&mut self.writer, "{CSV_HEADER}").unwrap();
+        if !self.human {
+            let units: Vec<_> = CSV_HEADER
+                .split(' ')
+                .filter_map(|column| Some(format!("{column}={}", column_unit(column)?)))
+                .collect();
+            writeln!(&mut self.writer, "# units: {}", units.join(" ")).unwrap();
         }
     }
+
+    fn write_line(&mut self, line: &CsvLine) {
+        if !self.human {
+            CsvLine::write(line, &self.time_format, &mut self.writer);
+            return;
+        }
+        self.buf.clear();
+        CsvLine::write(line, &self.time_format, &mut self.buf);
+        let line = std::str::from_utf8(&self.buf).unwrap();
+        let state = CSV_HEADER
+            .split(' ')
+            .zip(line.split(' '))
+            .find(|&(column, _)| column == "base.state")
+            .and_then(|(_, state)| state.parse().ok())
+            .unwrap_or(0);
+        for (i, (column, value)) in CSV_HEADER.split(' ').zip(line.split(' ')).enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+            let value = crate::human::format_column(column, state, value);
+            write!(&mut self.writer, "{separator}{value}").unwrap();
+        }
+    }
+
+    /// Writes the columns after the data and ends the line. The stats are
+    /// written with the duration.
+    fn write_trailer(&mut self, duration: Option<u64>, period: Option<u64>, unscheduled: bool) {
+        write!(&mut self.writer, " ").unwrap();
+        Option::<u64>::write(&duration, &(), &mut self.writer);
+        write!(&mut self.writer, " ").unwrap();
+        Option::<u64>::write(&period, &(), &mut self.writer);
+        write!(&mut self.writer, " ").unwrap();
+        Option::<String>::write(&self.source, &(), &mut self.writer);
+        write!(&mut self.writer, " ").unwrap();
+        Option::<u8>::write(&unscheduled.then_some(1), &(), &mut self.writer);
+        let stats = self.stats.filter(|_| duration.is_some());
+        for count in [
+            stats.map(|s| s.sockets),
+            stats.map(|s| s.inet4),
+            stats.map(|s| s.inet6),
+        ] {
+            write!(&mut self.writer, " ").unwrap();
+            Option::<u32>::write(&count, &(), &mut self.writer);
+        }
+        write!(&mut self.writer, " ").unwrap();
+        Option::<u64>::write(&stats.map(|s| s.bytes), &(), &mut self.writer);
+        write!(&mut self.writer, " ").unwrap();
+        Option::<u64>::write(
+            &stats.map(|s| s.boottime).filter(|&boottime| boottime != 0),
+            &(),
+            &mut self.writer,
+        );
+        write!(&mut self.writer, " ").unwrap();
+        Option::<u32>::write(
+            &stats.map(|s| s.messages).filter(|&n| n != 0),
+            &(),
+            &mut self.writer,
+        );
+        write!(&mut self.writer, " ").unwrap();
+        Option::<u64>::write(
+            &stats.map(|s| s.cpu_time).filter(|&n| n != 0),
+            &(),
+            &mut self.writer,
+        );
+        for count in [stats.map(|s| s.errors), stats.map(|s| s.dropped)] {
+            write!(&mut self.writer, " ").unwrap();
+            Option::<u32>::write(&count.filter(|&n| n != 0), &(), &mut self.writer);
+        }
+        writeln!(&mut self.writer).unwrap();
+    }
 }
 
 impl<T: Write> Collector for CsvOutput<T> {
     fn start(&mut self, time: SystemTime) {
+        self.write_header();
+        self.in_sample = true;
         self.time = time;
-        self.trailer = "";
+        self.pending = false;
+        self.period = None;
+        self.unscheduled = false;
+        self.stats = None;
+        self.offset = None;
     }
 
     fn out(&mut self, data: &[u8]) {
-        write!(&mut self.writer, "{}", self.trailer).unwrap();
-        let time = self.time.duration_since(UNIX_EPOCH).unwrap().as_micros();
+        if self.pending {
+            self.write_trailer(None, None, false);
+        }
         let line = CsvLine {
-            time: time as u64,
-            data: Some(InetDiagMsgExtra::parse(data)),
+            time: Timestamp::new(self.time),
+            data: Some(InetDiagMsgExtra {
+                offset: self.offset.map(|offset| offset.as_micros() as u32),
+                ..InetDiagMsgExtra::parse(data)
+            }),
         };
-        CsvLine::write(&line, &(), &mut self.writer);
-        write!(&mut self.writer, "").unwrap();
-        self.trailer = " _\n";
+        self.write_line(&line);
+        self.pending = true;
     }
 
     fn end(&mut self, duration: Duration) {
-        if self.trailer.is_empty() {
+        self.write_header();
+        if !self.pending {
             let line = CsvLine {
-                time: self.time.duration_since(UNIX_EPOCH).unwrap().as_micros() as u64,
+                time: Timestamp::new(self.time),
                 data: None,
             };
-            CsvLine::write(&line, &(), &mut self.writer);
-            write!(&mut self.writer, "").unwrap();
+            self.write_line(&line);
+        }
+        let period = self.period.map(|p| p.as_micros() as u64);
+        self.write_trailer(Some(duration.as_micros() as u64), period, self.unscheduled);
+        self.pending = false;
+        self.in_sample = false;
+        for comment in std::mem::take(&mut self.comments) {
+            self.write_comment(&comment);
         }
-        writeln!(&mut self.writer, " {}", duration.as_micros()).unwrap();
         self.writer.flush().unwrap();
     }
+
+    fn period(&mut self, period: Duration) {
+        self.period = Some(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.unscheduled = true;
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.stats = Some(*stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.offset = Some(offset);
+    }
+
+    fn source(&mut self, source: &str) {
+        self.source = Some(source.into());
+    }
+
+    /// Written as comment lines, `# key: value`.
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.write_header();
+        for (key, value) in metadata {
+            writeln!(&mut self.writer, "# {key}: {}", value.replace('\n', " ")).unwrap();
+        }
+    }
 }
 
-pub fn read_csv(mut reader: BufReader<StdinLock>, mut writer: Box<dyn Collector>) {
-    let mut header = String::new();
-    loop {
-        reader.read_line(&mut header).unwrap();
-        if header.starts_with('#') {
-            header = Default::default();
-        } else {
-            break;
-        }
-    }
-    let header = header.strip_suffix('\n').unwrap();
-    let mut reorder = None;
-    if !header.starts_with(CSV_HEADER) {
-        let header_map: HashMap<_, _> = header.split(" ").zip(0usize..).collect();
-        reorder = Some(
-            CSV_HEADER
-                .split(" ")
-                .map(|k| header_map.get(k).copied())
-                .collect::<Vec<_>>(),
-        );
+/// Positions of the space-separated fields of a line (like `split(' ')`, but
+/// searching with memchr).
+fn field_ranges(line: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut start = 0;
+    memchr::memchr_iter(b' ', line.as_bytes())
+        .chain([line.len()])
+        .map(move |end| {
+            let range = start..end;
+            start = end + 1;
+            range
+        })
+}
+
+/// Marks the optional column groups of `desc` (starting at column `offset`)
+/// that lack columns in `present` for clearing. Returns whether the
+/// required columns are present.
+fn incomplete_options(
+    desc: &csv::Desc,
+    offset: usize,
+    present: &[bool],
+    clear: &mut Vec<Range<usize>>,
+) -> bool {
+    match *desc {
+        csv::Desc::Atom => present[offset],
+        csv::Desc::Option(inner) => {
+            if !incomplete_options(inner, offset, present, clear) {
+                clear.push(offset..offset + inner.len());
+            }
+            true
+        }
+        csv::Desc::Array(n, inner) => {
+            let mut complete = true;
+            for i in 0..n {
+                complete &= incomplete_options(inner, offset + i * inner.len(), present, clear);
+            }
+            complete
+        }
+        csv::Desc::Struct(members) => {
+            let mut offset = offset;
+            let mut complete = true;
+            for (_, member) in members {
+                complete &= incomplete_options(member, offset, present, clear);
+                offset += member.len();
+            }
+            complete
+        }
+    }
+}
+
+/// Maps the columns of CSV_HEADER to the columns of `header`. Unknown columns
+/// are skipped. Optional columns (like `tcp_info.*`) that are partly absent
+/// are read as absent altogether.
+fn reorder(header: &str) -> std::io::Result<Vec<Option<usize>>> {
+    let header_map: HashMap<_, _> = header.split(' ').zip(0usize..).collect();
+    let mut reorder: Vec<_> = CSV_HEADER
+        .split_terminator(' ')
+        .map(|k| header_map.get(k).copied())
+        .collect();
+    let present: Vec<_> = reorder.iter().map(Option::is_some).collect();
+    let mut clear = Vec::new();
+    if !incomplete_options(&CsvLineOwned::DESC, 0, &present, &mut clear) {
+        let missing: Vec<_> = CSV_HEADER
+            .split_terminator(' ')
+            .zip(&present)
+            .filter(|(_, &present)| !present)
+            .map(|(name, _)| name)
+            .collect();
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("csv header lacks required columns: {}", missing.join(" ")),
+        ));
+    }
+    for range in clear {
+        reorder[range].fill(None);
     }
+    Ok(reorder)
+}
+
+/// Reads a csv capture. Malformed lines are reported on stderr and skipped.
+pub fn read_csv<R: BufRead, C: Collector>(reader: R, writer: C) {
+    read_csv_with_header(reader, None, writer);
+}
+
+/// Reads the metadata entry of a comment line. The units of the columns are
+/// skipped, as each csv output writes its own.
+pub(crate) fn metadata_entry(line: &str) -> Option<(String, String)> {
+    let line = line.strip_prefix("# ")?.trim_end_matches('\n');
+    let (key, value) = line.split_once(": ")?;
+    (key != "units").then(|| (key.into(), value.into()))
+}
+
+/// Reads a csv capture, which has no header line if `header` (the columns)
+/// is given. An empty `header` stands for the columns of this version.
+/// Comment lines of `key: value` are passed on as metadata.
+pub fn read_csv_with_header<R: BufRead, C: Collector>(reader: R, header: Option<&str>, writer: C) {
+    try_read_csv_with_header(reader, header, writer).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Like `read_csv_with_header`, but returns read errors and a header that
+/// lacks required columns (as `InvalidData`).
+pub fn try_read_csv_with_header<R: BufRead, C: Collector>(
+    mut reader: R,
+    header: Option<&str>,
+    mut writer: C,
+) -> std::io::Result<()> {
+    let mut lineno = 0;
+    let mut line = String::new();
+    // Metadata of the comment lines, passed on before the next sample
+    let mut metadata = Vec::new();
+    let header = match header {
+        Some("") => CSV_HEADER,
+        Some(header) => header,
+        None => loop {
+            line.clear();
+            lineno += 1;
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            if !line.starts_with('#') {
+                break line.strip_suffix('\n').unwrap_or(&line);
+            }
+            metadata.extend(metadata_entry(&line));
+        },
+    };
+    let reorder = match header.starts_with(CSV_HEADER) {
+        true => None,
+        false => Some(reorder(header)?),
+    };
+    let columns: Vec<_> = CSV_HEADER.split_terminator(' ').collect();
     let mut buf = String::new();
+    let mut msg = Vec::new();
+    // Positions of the fields in the line, for reordering
+    let mut fields = Vec::new();
     let mut time = UNIX_EPOCH;
+    // Whether the current sample still lacks its end (as the line with the
+    // duration was malformed)
+    let mut open = false;
+    let mut source = None;
+    // Receive offset of the previous socket
+    let mut offset = None;
     loop {
         buf.clear();
         loop {
-            reader.read_line(&mut buf).unwrap();
+            lineno += 1;
+            reader.read_line(&mut buf)?;
             if buf.is_empty() {
-                return;
+                return Ok(());
             }
             if buf.starts_with('#') {
-                buf = Default::default();
+                metadata.extend(metadata_entry(&buf));
+                buf.clear();
             } else {
                 break;
             }
         }
-        let buf = buf.strip_suffix("\n").unwrap();
-        let mut iter = buf.split(' ');
-        let mut fields = Vec::new();
+        let buf = buf.strip_suffix('\n').unwrap_or(&buf);
+        if !metadata.is_empty() {
+            writer.metadata(&metadata);
+            metadata.clear();
+        }
 
+        // Number of fields taken, to name the column of an error
+        let mut taken = 0;
         let line = if let Some(reorder) = &reorder {
             fields.clear();
-            fields.extend(iter);
+            fields.extend(field_ranges(buf));
             let mut iter = reorder
                 .iter()
-                .map(|i| i.and_then(|i| fields.get(i)).copied().unwrap_or("_"));
+                .map_while(|i| match i {
+                    Some(i) => Some(&buf[fields.get(*i)?.clone()]),
+                    None => Some("_"),
+                })
+                .inspect(|_| taken += 1);
             CsvLineOwned::read(&mut iter)
         } else {
+            let mut iter = field_ranges(buf)
+                .map(|range| &buf[range])
+                .inspect(|_| taken += 1);
             CsvLineOwned::read(&mut iter)
         };
-        let time_new = UNIX_EPOCH + Duration::from_micros(line.time);
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                let column = match err {
+                    csv::Error::Missing => columns.get(taken),
+                    csv::Error::Invalid(_) => columns.get(taken - 1),
+                };
+                crate::diagnostic!("line {lineno}: {err} in column {}", column.unwrap());
+                continue;
+            }
+        };
+        let time_new = line.time.system_time();
         if time != time_new {
             time = time_new;
+            if open {
+                writer.end(Duration::ZERO);
+            }
+            if line.source.is_some() && line.source != source {
+                source = line.source.clone();
+                writer.source(source.as_deref().unwrap());
+            }
             writer.start(time);
+            open = true;
+            offset = None;
         }
         if let Some(data) = &line.data {
-            writer.out(&data.to_vec());
+            if let Some(micros) = data.offset.filter(|&micros| offset != Some(micros)) {
+                offset = Some(micros);
+                writer.offset(Duration::from_micros(micros.into()));
+            }
+            msg.clear();
+            data.write_to(&mut msg);
+            writer.out(&msg);
+        }
+        if let Some(period) = line.period {
+            writer.period(Duration::from_micros(period));
+        }
+        if line.unscheduled.is_some() {
+            writer.unscheduled();
+        }
+        if let Some(sockets) = line.sockets {
+            writer.stats(&SampleStats {
+                bytes: line.netlink_bytes.unwrap_or(0),
+                sockets,
+                inet4: line.sockets_inet4.unwrap_or(0),
+                inet6: line.sockets_inet6.unwrap_or(0),
+                boottime: line.boottime.unwrap_or(0),
+                messages: line.netlink_messages.unwrap_or(0),
+                cpu_time: line.cpu_time.unwrap_or(0),
+                errors: line.netlink_errors.unwrap_or(0),
+                dropped: line.dropped_samples.unwrap_or(0),
+            });
         }
         if let Some(end) = line.duration {
             writer.end(Duration::from_micros(end));
+            open = false;
             continue;
         }
     }
@@ -0,0 +1,83 @@
+use std::{
+    fs::File,
+    io::Write,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+/// File with the process ID of the daemon, locked and removed when dropped.
+pub struct Pidfile {
+    file: File,
+    path: PathBuf,
+}
+
+impl Pidfile {
+    /// Creates and locks the pidfile, failing if another process holds it.
+    /// The ID is written by `write`, after daemonizing.
+    pub fn lock(path: &Path) -> Self {
+        // Absolute, as the daemon changes its working directory.
+        let path = std::path::absolute(path).unwrap();
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            panic!("{}: locked by a running tcpdiag", path.display());
+        }
+        Self { file, path }
+    }
+
+    pub fn write(&mut self) {
+        self.file.set_len(0).unwrap();
+        writeln!(self.file, "{}", std::process::id()).unwrap();
+    }
+}
+
+impl Drop for Pidfile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Detaches from the terminal and session (fork, setsid, fork). The parent
+/// exits. stdin is redirected to /dev/null; stdout (the capture) and stderr
+/// (the log) are kept. Must be called before any threads are started.
+pub fn daemonize() {
+    fn fork() {
+        match unsafe { libc::fork() } {
+            -1 => panic!("fork: {}", std::io::Error::last_os_error()),
+            0 => (),
+            _ => unsafe { libc::_exit(0) },
+        }
+    }
+    fork();
+    if unsafe { libc::setsid() } == -1 {
+        panic!("setsid: {}", std::io::Error::last_os_error());
+    }
+    // Not being a session leader, the daemon can't acquire a terminal.
+    fork();
+    std::env::set_current_dir("/").unwrap();
+    let null = File::open("/dev/null").unwrap();
+    unsafe { libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO) };
+    std::panic::set_hook(Box::new(|info| {
+        let location = info.location().unwrap();
+        let message = info.payload_as_str().unwrap_or("panic");
+        log("error", &format!("{message} (at {location})"));
+    }));
+}
+
+/// Writes a log message in logfmt to stderr, e.g.
+/// `time=2024-05-01T12:00:00.000000Z level=info pid=42 msg="capture started"`.
+/// Messages other than errors are silenced by `set_quiet`.
+pub fn log(level: &str, msg: &str) {
+    if level != "error" && crate::quiet() {
+        return;
+    }
+    let time = crate::timestamp::Timestamp::new(std::time::SystemTime::now()).display(
+        &crate::timestamp::TimeFormat::Rfc3339(jiff::tz::TimeZone::UTC),
+    );
+    let pid = std::process::id();
+    eprintln!("time={time} level={level} pid={pid} msg={msg:?}");
+}
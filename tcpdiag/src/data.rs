@@ -1,5 +1,3 @@
-use std::str::FromStr;
-
 use serde::{Deserialize, Serialize};
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
 
@@ -23,9 +21,59 @@ pub const INET_DIAG_INFO: u16 = 2;
 pub const INET_DIAG_VEGASINFO: u16 = 3;
 pub const INET_DIAG_CONG: u16 = 4;
 pub const INET_DIAG_BBRINFO: u16 = 16;
+/* annotations added by tcpdiag, outside of the kernel's attribute types */
+pub const TCPDIAG_TAGS: u16 = 0x3f00;
+/* attribute type of requests for protocols beyond 255 */
+pub const INET_DIAG_REQ_PROTOCOL: u16 = 3;
 
 pub const TCP_ESTABLISHED: u8 = 1;
 pub const TCPF_ESTABLISHED: u32 = 1 << TCP_ESTABLISHED;
+pub const TCP_SYN_SENT: u8 = 2;
+pub const TCP_CLOSE: u8 = 7;
+pub const TCP_LISTEN: u8 = 10;
+pub const TCPF_LISTEN: u32 = 1 << TCP_LISTEN;
+
+/* state names as used by ss, indexed by the kernel's TCP state */
+pub const TCP_STATE_NAMES: [&str; 13] = [
+    "UNKNOWN",
+    "ESTAB",
+    "SYN-SENT",
+    "SYN-RECV",
+    "FIN-WAIT-1",
+    "FIN-WAIT-2",
+    "TIME-WAIT",
+    "UNCONN",
+    "CLOSE-WAIT",
+    "LAST-ACK",
+    "LISTEN",
+    "CLOSING",
+    "NEW-SYN-RECV",
+];
+
+pub fn tcp_state_name(state: u8) -> &'static str {
+    TCP_STATE_NAMES
+        .get(usize::from(state))
+        .copied()
+        .unwrap_or("UNKNOWN")
+}
+
+/* timer names as used by ss, indexed by the kernel's idiag_timer */
+pub const TIMER_NAMES: [&str; 5] = ["off", "on", "keepalive", "timewait", "persist"];
+
+pub fn timer_name(timer: u8) -> &'static str {
+    TIMER_NAMES
+        .get(usize::from(timer))
+        .copied()
+        .unwrap_or("unknown")
+}
+
+pub fn family_name(family: u8) -> &'static str {
+    match family {
+        2 => "inet",
+        10 => "inet6",
+        _ => "unknown",
+    }
+}
 
 pub const fn request_as(extension: u16) -> u8 {
     match extension {
@@ -40,6 +88,19 @@ pub const fn request_as(extension: u16) -> u8 {
 )]
 pub struct IpAddrUnspec([u8; 16]);
 
+impl IpAddrUnspec {
+    pub fn get(self, family: u8) -> std::net::IpAddr {
+        match family {
+            2 => {
+                let [a, b, c, d, ..] = self.0;
+                std::net::Ipv4Addr::new(a, b, c, d).into()
+            }
+            10 => std::net::Ipv6Addr::from(self.0).into(),
+            _ => panic!(),
+        }
+    }
+}
+
 impl SerializeWithContext for IpAddrUnspec {
     type Context = u8;
     fn serialize<S: serde::Serializer>(
@@ -107,15 +168,8 @@ impl csv::CsvWrite for IpAddrUnspec {
     }
 }
 impl csv::Csv for IpAddrUnspec {
-    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Self {
-        match std::net::IpAddr::from_str(r.next().unwrap()).unwrap() {
-            std::net::IpAddr::V6(addr) => Self(addr.octets()),
-            std::net::IpAddr::V4(addr) => Self({
-                let mut octets = [0; 16];
-                octets[..4].copy_from_slice(&addr.octets());
-                octets
-            }),
-        }
+    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<Self, csv::Error> {
+        Ok(csv::parse::<std::net::IpAddr, _>(r)?.into())
     }
 }
 
@@ -196,19 +250,14 @@ impl<'a> Iterator for NlattrIter<'a> {
     type Item = &'a NlAttribute;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.0.is_empty() {
-            return None;
-        }
-        let (hdr, _) = nlattr::ref_from_prefix(self.0).unwrap();
+        // A malformed attribute ends the iteration.
+        let bytes = std::mem::take(&mut self.0);
+        let (hdr, _) = nlattr::ref_from_prefix(bytes).ok()?;
         let len = usize::from(hdr.nla_len);
-        let payload_len = len - std::mem::size_of_val(hdr);
-        let (current, remaining) = self.0.split_at((len + 3) & !3);
-        self.0 = remaining;
-        Some(
-            NlAttribute::ref_from_prefix_with_elems(current, payload_len)
-                .unwrap()
-                .0,
-        )
+        let payload_len = len.checked_sub(std::mem::size_of_val(hdr))?;
+        let (attribute, _) = NlAttribute::ref_from_prefix_with_elems(bytes, payload_len).ok()?;
+        self.0 = bytes.get((len + 3) & !3..).unwrap_or_default();
+        Some(attribute)
     }
 }
 
@@ -278,8 +327,8 @@ impl csv::CsvWrite for Wscale {
     }
 }
 impl csv::Csv for Wscale {
-    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Self {
-        Self::new(WscaleExp::read(r))
+    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<Self, csv::Error> {
+        WscaleExp::read(r).map(Self::new)
     }
 }
 
@@ -397,7 +446,348 @@ pub struct Bbr3Info {
     pub extra_acked: u32, /* max excess packets ACKed in epoch */
 }
 
-#[derive(Debug, Serialize, CsvWrite)]
+/// For listening sockets, the kernel reports the accept queue in rqueue and wqueue.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Csv)]
+pub struct AcceptQueue {
+    pub backlog: u32,
+    pub max_backlog: u32,
+}
+
+impl AcceptQueue {
+    pub fn new(msg: &InetDiagMsg) -> Option<Self> {
+        (msg.state == TCP_LISTEN).then_some(Self {
+            backlog: msg.rqueue,
+            max_backlog: msg.wqueue,
+        })
+    }
+}
+
+/// Bytes written as hex string, like the tail of tcp_info that newer kernels
+/// report beyond `TcpInfo`, so that it can be decoded later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hex<T: AsRef<[u8]>>(pub T);
+
+impl<T: AsRef<[u8]>> std::fmt::Display for Hex<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0
+            .as_ref()
+            .iter()
+            .try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+impl std::str::FromStr for Hex<Vec<u8>> {
+    type Err = std::num::ParseIntError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // With an odd length (or non-ASCII), `get` fails and so does parsing "".
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(s.get(i..i + 2).unwrap_or(""), 16))
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl<T: AsRef<[u8]>> Serialize for Hex<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Hex<Vec<u8>> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = std::borrow::Cow::<str>::deserialize(deserializer)?;
+        hex.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl<T: AsRef<[u8]>> csv::CsvWrite for Hex<T> {
+    type Context = ();
+    const DESC: csv::Desc = csv::Desc::Atom;
+    fn write<W: std::io::Write>(obj: &Self, (): &Self::Context, w: &mut W) {
+        write!(w, "{obj}").unwrap();
+    }
+}
+impl csv::Csv for Hex<Vec<u8>> {
+    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<Self, csv::Error> {
+        csv::parse(r)
+    }
+}
+
+/// Annotations of a socket (like the script tags or the country of the peer)
+/// as `key=value` C strings in the `TCPDIAG_TAGS` attribute. Written as JSON
+/// object and as csv column of `key=value` pairs separated by `,` (with `%`,
+/// whitespace, `,`, and `=` percent-encoded).
+#[derive(Debug, Clone, Copy)]
+pub struct Tags<'a>(pub &'a [u8]);
+
+impl<'a> Tags<'a> {
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.0
+            .split(|&b| b == 0)
+            .filter_map(|tag| std::str::from_utf8(tag).ok()?.split_once('='))
+    }
+}
+
+impl Serialize for Tags<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+impl csv::CsvWrite for Tags<'_> {
+    type Context = ();
+    const DESC: csv::Desc = csv::Desc::Atom;
+    fn write<W: std::io::Write>(obj: &Self, (): &Self::Context, w: &mut W) {
+        write_csv_tags(obj.iter(), w);
+    }
+}
+
+fn write_csv_tags<'a, W: std::io::Write>(
+    tags: impl Iterator<Item = (&'a str, &'a str)>,
+    w: &mut W,
+) {
+    let encode = |s: &str, w: &mut W| {
+        for b in s.bytes() {
+            match b {
+                b'%' | b',' | b'=' => write!(w, "%{b:02X}"),
+                _ if b.is_ascii_whitespace() => write!(w, "%{b:02X}"),
+                _ => w.write_all(&[b]),
+            }
+            .unwrap();
+        }
+    };
+    for (i, (key, value)) in tags.enumerate() {
+        if i > 0 {
+            w.write_all(b",").unwrap();
+        }
+        encode(key, w);
+        w.write_all(b"=").unwrap();
+        encode(value, w);
+    }
+}
+
+/// Owned `Tags`, in their order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagsOwned(pub Vec<(String, String)>);
+
+impl TagsOwned {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Sets a tag, replacing an existing one with the same key.
+    pub fn insert(&mut self, key: &str, value: &str) {
+        match self.0.iter_mut().find(|(k, _)| k == key) {
+            Some((_, v)) => *v = value.into(),
+            None => self.0.push((key.into(), value.into())),
+        }
+    }
+
+    /// Appends the `TCPDIAG_TAGS` attribute payload (without padding).
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        for (key, value) in &self.0 {
+            buf.extend(key.as_bytes());
+            buf.push(b'=');
+            buf.extend(value.as_bytes());
+            buf.push(0);
+        }
+    }
+
+    /// Appends the `TCPDIAG_TAGS` attribute, with padding.
+    fn push_attribute(&self, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        InetDiagMsgExtraOwned::push_header(buf, TCPDIAG_TAGS, 0);
+        self.write_to(buf);
+        let len = u16::try_from(buf.len() - start).unwrap();
+        buf[start..start + 2].copy_from_slice(&len.to_ne_bytes());
+        while buf.len() & 3 != 0 {
+            buf.push(0);
+        }
+    }
+}
+
+/// Copies the record `data` to `buf` (which is cleared first) with `tags`
+/// added to its tags, replacing those with the same key. The other attributes
+/// are copied as they are.
+pub fn add_tags(data: &[u8], tags: &[(&str, &str)], buf: &mut Vec<u8>) {
+    let (msg, attributes) = InetDiagMsg::ref_from_prefix(data).unwrap();
+    buf.clear();
+    buf.extend(msg.as_bytes());
+    let mut merged = TagsOwned::default();
+    for attribute in NlattrIter::new(attributes) {
+        if attribute.hdr.nla_type == TCPDIAG_TAGS {
+            merged = Tags(&attribute.data).into();
+            continue;
+        }
+        buf.extend(attribute.hdr.as_bytes());
+        buf.extend(&attribute.data);
+        while buf.len() & 3 != 0 {
+            buf.push(0);
+        }
+    }
+    for (key, value) in tags {
+        merged.insert(key, value);
+    }
+    merged.push_attribute(buf);
+}
+
+/// Copies the record `data` to `buf` (which is cleared first) with only
+/// tcp_info and the tags of its attributes.
+pub fn shorten(data: &[u8], buf: &mut Vec<u8>) {
+    let (msg, attributes) = InetDiagMsg::ref_from_prefix(data).unwrap();
+    buf.clear();
+    buf.extend(msg.as_bytes());
+    for attribute in NlattrIter::new(attributes)
+        .filter(|a| a.hdr.nla_type == INET_DIAG_INFO || a.hdr.nla_type == TCPDIAG_TAGS)
+    {
+        buf.extend(attribute.hdr.as_bytes());
+        buf.extend(&attribute.data);
+        while buf.len() & 3 != 0 {
+            buf.push(0);
+        }
+    }
+}
+
+impl From<Tags<'_>> for TagsOwned {
+    fn from(tags: Tags) -> Self {
+        Self(tags.iter().map(|(k, v)| (k.into(), v.into())).collect())
+    }
+}
+
+impl Serialize for TagsOwned {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+    }
+}
+
+impl<'de> Deserialize<'de> for TagsOwned {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = TagsOwned;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an object of string tags")
+            }
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<TagsOwned, A::Error> {
+                let mut tags = Vec::new();
+                while let Some((key, value)) = map.next_entry::<String, String>()? {
+                    if key.is_empty() || key.contains(['=', '\0']) || value.contains('\0') {
+                        return Err(serde::de::Error::custom(format!("invalid tag {key:?}")));
+                    }
+                    tags.push((key, value));
+                }
+                Ok(TagsOwned(tags))
+            }
+        }
+        deserializer.deserialize_map(Visitor)
+    }
+}
+
+impl csv::CsvWrite for TagsOwned {
+    type Context = ();
+    const DESC: csv::Desc = csv::Desc::Atom;
+    fn write<W: std::io::Write>(obj: &Self, (): &Self::Context, w: &mut W) {
+        write_csv_tags(obj.0.iter().map(|(k, v)| (k.as_str(), v.as_str())), w);
+    }
+}
+
+impl csv::Csv for TagsOwned {
+    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<Self, csv::Error> {
+        let decode = |s: &str| {
+            let mut out = Vec::with_capacity(s.len());
+            let mut bytes = s.bytes();
+            while let Some(b) = bytes.next() {
+                if b != b'%' {
+                    out.push(b);
+                    continue;
+                }
+                let hex = [bytes.next()?, bytes.next()?];
+                out.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            String::from_utf8(out).ok().filter(|s| !s.contains('\0'))
+        };
+        let column = csv::next(r)?;
+        column
+            .split(',')
+            .map(|tag| {
+                let (key, value) = tag.split_once('=')?;
+                Some((decode(key).filter(|key| !key.is_empty())?, decode(value)?))
+            })
+            .collect::<Option<_>>()
+            .map(Self)
+            .ok_or_else(|| csv::Error::Invalid(column.into()))
+    }
+}
+
+/// Kind of a socket timer, written by its name (see `TIMER_NAMES`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerKind(pub u8);
+
+impl Serialize for TimerKind {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(timer_name(self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for TimerKind {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = std::borrow::Cow::<str>::deserialize(deserializer)?;
+        Ok(Self::from_name(&name))
+    }
+}
+
+impl TimerKind {
+    /// Unknown names map to an index past `TIMER_NAMES`.
+    fn from_name(name: &str) -> Self {
+        let index = TIMER_NAMES.iter().position(|&known| known == name);
+        Self(index.unwrap_or(TIMER_NAMES.len()) as u8)
+    }
+}
+
+impl csv::CsvWrite for TimerKind {
+    type Context = ();
+    const DESC: csv::Desc = csv::Desc::Atom;
+    fn write<W: std::io::Write>(obj: &Self, (): &Self::Context, w: &mut W) {
+        w.write_all(timer_name(obj.0).as_bytes()).unwrap();
+    }
+}
+impl csv::Csv for TimerKind {
+    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<Self, csv::Error> {
+        csv::next(r).map(Self::from_name)
+    }
+}
+
+/// Pending timer of a socket, decoded from the timer, retrans, and expires
+/// fields of `InetDiagMsg` like `ss -o` does (`timer:(on,200ms,0)`).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Csv)]
+pub struct Timer {
+    /// `on` (retransmission or loss probe), `keepalive`, `timewait`, or
+    /// `persist` (zero window probe)
+    pub kind: TimerKind,
+    /// Time until the timer fires in milliseconds
+    pub expires: u32,
+    /// Retransmissions (`on`) or unanswered probes (`keepalive`, `persist`)
+    pub retrans: u8,
+}
+
+impl Timer {
+    pub fn new(msg: &InetDiagMsg) -> Option<Self> {
+        (msg.timer != 0).then_some(Self {
+            kind: TimerKind(msg.timer),
+            expires: msg.expires,
+            retrans: msg.retrans,
+        })
+    }
+}
+
+#[derive(Debug, SerializeWithContext, CsvWrite)]
 #[non_exhaustive]
 pub struct InetDiagMsgExtra<'a> {
     pub base: &'a InetDiagMsg,
@@ -405,20 +795,109 @@ pub struct InetDiagMsgExtra<'a> {
     pub cong: Option<&'a str>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tcp_info: Option<&'a TcpInfo>,
+    /// Bytes of tcp_info beyond `TcpInfo`, from newer kernels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_info_tail: Option<Hex<&'a [u8]>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bbr: Option<&'a BbrInfo>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub bbr3: Option<&'a Bbr3Info>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accept_queue: Option<AcceptQueue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timer: Option<Timer>,
+    /// Microseconds since the start of the sample at which the socket was
+    /// received (set by the outputs, as it is not part of the message)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Tags<'a>>,
+}
+
+/// Congestion control algorithms of Linux, which are interned by `Cong`.
+const CONG_NAMES: &[&str] = &[
+    "bbr",
+    "bic",
+    "cdg",
+    "cubic",
+    "dctcp",
+    "highspeed",
+    "htcp",
+    "hybla",
+    "illinois",
+    "lp",
+    "nv",
+    "reno",
+    "scalable",
+    "vegas",
+    "veno",
+    "westwood",
+    "yeah",
+];
+
+/// Name of a congestion control algorithm. Known names are not allocated.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Cong(std::borrow::Cow<'static, str>);
+
+impl Cong {
+    pub fn new(name: &str) -> Self {
+        match CONG_NAMES.iter().find(|&&known| known == name) {
+            Some(known) => Self(std::borrow::Cow::Borrowed(known)),
+            None => Self(std::borrow::Cow::Owned(name.into())),
+        }
+    }
+}
+
+impl std::ops::Deref for Cong {
+    type Target = str;
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<'de> Deserialize<'de> for Cong {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Cong;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a congestion control name")
+            }
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Cong, E> {
+                Ok(Cong::new(v))
+            }
+        }
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+impl csv::CsvWrite for Cong {
+    type Context = ();
+    const DESC: csv::Desc = csv::Desc::Atom;
+    fn write<W: std::io::Write>(obj: &Self, (): &Self::Context, w: &mut W) {
+        w.write_all(obj.as_bytes()).unwrap();
+    }
+}
+impl csv::Csv for Cong {
+    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<Self, csv::Error> {
+        csv::next(r).map(Self::new)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Csv)]
 #[non_exhaustive]
 pub struct InetDiagMsgExtraOwned {
     pub base: InetDiagMsg,
-    pub cong: Option<String>,
+    pub cong: Option<Cong>,
     pub tcp_info: Option<TcpInfo>,
+    pub tcp_info_tail: Option<Hex<Vec<u8>>>,
     pub bbr: Option<BbrInfo>,
     pub bbr3: Option<Bbr3Info>,
+    pub accept_queue: Option<AcceptQueue>,
+    pub timer: Option<Timer>,
+    pub offset: Option<u32>,
+    pub tags: Option<TagsOwned>,
 }
 
 impl InetDiagMsgExtraOwned {
@@ -432,10 +911,17 @@ impl InetDiagMsgExtraOwned {
         )
     }
     pub fn to_vec(&self) -> Vec<u8> {
-        let mut buf: Vec<u8> = vec![];
+        let mut buf = Vec::new();
+        self.write_to(&mut buf);
+        buf
+    }
+
+    /// Appends the netlink message to `buf`, which can be reused for the
+    /// next message (instead of allocating with `to_vec`).
+    pub fn write_to(&self, buf: &mut Vec<u8>) {
         buf.extend(self.base.as_bytes());
         if let Some(cong) = &self.cong {
-            Self::push_header(&mut buf, INET_DIAG_CONG, cong.len() + 1);
+            Self::push_header(buf, INET_DIAG_CONG, cong.len() + 1);
             buf.extend(cong.as_bytes());
             buf.push(0);
             while buf.len() & 3 != 0 {
@@ -443,24 +929,31 @@ impl InetDiagMsgExtraOwned {
             }
         }
         if let Some(tcp_info) = &self.tcp_info {
-            Self::push_header(&mut buf, INET_DIAG_INFO, std::mem::size_of_val(tcp_info));
+            let tail = self.tcp_info_tail.as_ref().map_or(&[][..], |tail| &tail.0);
+            Self::push_header(
+                buf,
+                INET_DIAG_INFO,
+                std::mem::size_of_val(tcp_info) + tail.len(),
+            );
             buf.extend(tcp_info.as_bytes());
+            buf.extend(tail);
+            while buf.len() & 3 != 0 {
+                buf.push(0);
+            }
         }
         if let Some(bbr) = &self.bbr {
             let parts = [
                 bbr.as_bytes(),
                 self.bbr3.as_ref().map(|x| x.as_bytes()).unwrap_or(&[]),
             ];
-            Self::push_header(
-                &mut buf,
-                INET_DIAG_BBRINFO,
-                parts.iter().map(|p| p.len()).sum(),
-            );
+            Self::push_header(buf, INET_DIAG_BBRINFO, parts.iter().map(|p| p.len()).sum());
             for part in parts {
                 buf.extend(part);
             }
         }
-        buf
+        if let Some(tags) = &self.tags {
+            tags.push_attribute(buf);
+        }
     }
 }
 
@@ -470,28 +963,39 @@ impl<'a> InetDiagMsgExtra<'a> {
             base,
             cong: None,
             tcp_info: None,
+            tcp_info_tail: None,
             bbr: None,
             bbr3: None,
+            accept_queue: AcceptQueue::new(base),
+            timer: Timer::new(base),
+            offset: None,
+            tags: None,
         }
     }
 
     pub fn parse(data: &'a [u8]) -> Self {
-        let (diag, extra) = InetDiagMsg::ref_from_prefix(data).unwrap();
+        Self::try_parse(data).unwrap()
+    }
+
+    /// Like `parse`, but returns `None` for data shorter than an
+    /// `inet_diag_msg`. Malformed attributes are skipped by both.
+    pub fn try_parse(data: &'a [u8]) -> Option<Self> {
+        let (diag, extra) = InetDiagMsg::ref_from_prefix(data).ok()?;
         let mut extras = InetDiagMsgExtra::new(diag);
 
         for attribute in NlattrIter::new(extra) {
             use crate::data;
             match attribute.hdr.nla_type {
                 data::INET_DIAG_INFO => {
-                    extras.tcp_info = Some(TcpInfo::ref_from_prefix(&attribute.data).unwrap().0)
+                    if let Ok((tcp_info, tail)) = TcpInfo::ref_from_prefix(&attribute.data) {
+                        extras.tcp_info = Some(tcp_info);
+                        extras.tcp_info_tail = (!tail.is_empty()).then_some(Hex(tail));
+                    }
                 }
                 data::INET_DIAG_CONG => {
-                    extras.cong = Some(
-                        std::str::from_utf8(&attribute.data)
-                            .unwrap()
-                            .strip_suffix('\0')
-                            .unwrap(),
-                    )
+                    extras.cong = std::str::from_utf8(&attribute.data)
+                        .ok()
+                        .and_then(|cong| cong.strip_suffix('\0'))
                 }
                 data::INET_DIAG_BBRINFO => {
                     if let Ok((bbr, tail)) = BbrInfo::ref_from_prefix(&attribute.data) {
@@ -499,10 +1003,11 @@ impl<'a> InetDiagMsgExtra<'a> {
                         extras.bbr3 = Bbr3Info::ref_from_prefix(tail).ok().map(|(bbr3, _)| bbr3);
                     }
                 }
+                data::TCPDIAG_TAGS => extras.tags = Some(Tags(&attribute.data)),
                 _ => (),
             }
         }
 
-        extras
+        Some(extras)
     }
 }
@@ -0,0 +1,117 @@
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    time::{Duration, SystemTime},
+};
+
+use zerocopy::FromBytes;
+
+use crate::data::*;
+use crate::filter::Tuple;
+use crate::human::{self, Unit};
+
+use crate::Collector;
+
+/// Last observation of each connection in a capture, keyed by its 4-tuple.
+#[derive(Default, Debug)]
+pub struct Snapshot {
+    pub connections: BTreeMap<Tuple, Vec<u8>>,
+}
+
+impl Collector for Snapshot {
+    fn out(&mut self, data: &[u8]) {
+        let (msg, _) = InetDiagMsg::ref_from_prefix(data).unwrap();
+        self.connections
+            .insert(Tuple::new(msg).canonical(), data.to_vec());
+    }
+
+    fn start(&mut self, _time: SystemTime) {}
+
+    fn end(&mut self, _duration: Duration) {}
+}
+
+type Metric = (&'static str, Option<Unit>, fn(&TcpInfo) -> u64);
+
+/// Compared tcp_info fields with their unit.
+const METRICS: [Metric; 7] = [
+    ("rtt", Some(Unit::Micros), |i| i.rtt.into()),
+    ("rttvar", Some(Unit::Micros), |i| i.rttvar.into()),
+    ("min_rtt", Some(Unit::Micros), |i| i.min_rtt.into()),
+    ("cwnd", None, |i| i.snd_cwnd.into()),
+    ("retrans", None, |i| i.total_retrans.into()),
+    ("pacing", Some(Unit::BytesPerSec), |i| i.pacing_rate.get()),
+    ("delivery", Some(Unit::BytesPerSec), |i| {
+        i.delivery_rate.get()
+    }),
+];
+
+/// Compares two snapshots and writes connections that appeared or
+/// disappeared, and metrics that changed by at least `threshold` (relative).
+///
+/// A connection whose 4-tuple was reused (with a different cookie) counts as
+/// disappeared and appeared.
+pub fn write_diff<W: Write>(a: &Snapshot, b: &Snapshot, threshold: f64, human: bool, mut w: W) {
+    let value = |unit: Option<Unit>, value: u64| match unit {
+        Some(unit) if human => human::format(unit, value),
+        _ => value.to_string(),
+    };
+    let (mut appeared, mut disappeared, mut changed) = (0, 0, 0);
+    let tuples: std::collections::BTreeSet<_> =
+        a.connections.keys().chain(b.connections.keys()).collect();
+    for tuple in tuples {
+        let old = a.connections.get(tuple).map(|d| InetDiagMsgExtra::parse(d));
+        let new = b.connections.get(tuple).map(|d| InetDiagMsgExtra::parse(d));
+        let (old, new) = match (old, new) {
+            (Some(old), Some(new)) if old.base.id.cookie.get() == new.base.id.cookie.get() => {
+                (old, new)
+            }
+            (old, new) => {
+                if let Some(old) = old {
+                    writeln!(w, "- {tuple} {}", tcp_state_name(old.base.state)).unwrap();
+                    disappeared += 1;
+                }
+                if let Some(new) = new {
+                    writeln!(w, "+ {tuple} {}", tcp_state_name(new.base.state)).unwrap();
+                    appeared += 1;
+                }
+                continue;
+            }
+        };
+        let mut changes = Vec::new();
+        if old.base.state != new.base.state {
+            changes.push(format!(
+                "state {} -> {}",
+                tcp_state_name(old.base.state),
+                tcp_state_name(new.base.state)
+            ));
+        }
+        if old.cong != new.cong {
+            let cong = |c: Option<&str>| c.unwrap_or("-").to_string();
+            changes.push(format!("cong {} -> {}", cong(old.cong), cong(new.cong)));
+        }
+        if let (Some(old), Some(new)) = (old.tcp_info, new.tcp_info) {
+            for (name, unit, get) in METRICS {
+                let (old, new) = (get(old), get(new));
+                let change = (new as f64 - old as f64) / (old.max(1) as f64);
+                if old != new && change.abs() >= threshold {
+                    changes.push(format!(
+                        "{name} {} -> {} ({:+.0}%)",
+                        value(unit, old),
+                        value(unit, new),
+                        change * 100.0
+                    ));
+                }
+            }
+        }
+        if !changes.is_empty() {
+            writeln!(w, "~ {tuple} {}", changes.join(", ")).unwrap();
+            changed += 1;
+        }
+    }
+    writeln!(
+        w,
+        "{appeared} appeared, {disappeared} disappeared, {changed} changed"
+    )
+    .unwrap();
+    w.flush().unwrap();
+}
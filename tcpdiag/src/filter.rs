@@ -0,0 +1,306 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use zerocopy::FromBytes;
+
+use crate::data::InetDiagMsg;
+use crate::timestamp::Timestamp;
+use crate::Collector;
+
+/// Connection 4-tuple, written as `src:port-dst:port` (IPv6 addresses in brackets).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tuple {
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+}
+
+impl FromStr for Tuple {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (src, dst) = s
+            .split_once('-')
+            .ok_or_else(|| format!("expected src:port-dst:port, got {s:?}"))?;
+        let parse =
+            |addr: &str| SocketAddr::from_str(addr).map_err(|err| format!("{addr:?}: {err}"));
+        let (src, dst) = (parse(src)?, parse(dst)?);
+        if src.is_ipv4() != dst.is_ipv4() {
+            return Err("addresses must be of the same family".into());
+        }
+        Ok(Self { src, dst })
+    }
+}
+
+impl fmt::Display for Tuple {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.src, self.dst)
+    }
+}
+
+impl Tuple {
+    pub fn new(msg: &InetDiagMsg) -> Self {
+        Self {
+            src: SocketAddr::new(msg.id.src.get(msg.family), msg.id.sport.get()),
+            dst: SocketAddr::new(msg.id.dst.get(msg.family), msg.id.dport.get()),
+        }
+    }
+
+    /// Converts IPv4-mapped IPv6 addresses to IPv4 addresses.
+    pub fn canonical(self) -> Self {
+        let canonical = |addr: SocketAddr| SocketAddr::new(addr.ip().to_canonical(), addr.port());
+        Self {
+            src: canonical(self.src),
+            dst: canonical(self.dst),
+        }
+    }
+
+    /// Matches IPv4 tuples against IPv4-mapped addresses of IPv6 sockets, too.
+    pub fn matches(&self, msg: &InetDiagMsg) -> bool {
+        Self::new(msg).canonical() == self.canonical()
+    }
+}
+
+/// Address range in CIDR notation, like `10.0.0.0/8`. A plain address matches
+/// only itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cidr {
+    pub addr: IpAddr,
+    pub prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (addr, prefix) = s.split_once('/').map_or((s, None), |(a, p)| (a, Some(p)));
+        let addr = IpAddr::from_str(addr)
+            .map_err(|err| format!("{addr:?}: {err}"))?
+            .to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|&p| p <= max)
+                .ok_or_else(|| format!("invalid prefix length {prefix:?}"))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl Cidr {
+    /// Range of the first `prefix` bits of `addr` (IPv4-mapped addresses as
+    /// IPv4 addresses), with `prefix` capped at the address length.
+    pub fn network(addr: IpAddr, prefix: u8) -> Self {
+        match addr.to_canonical() {
+            IpAddr::V4(addr) => {
+                let prefix = prefix.min(32);
+                let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+                let addr = IpAddr::V4((addr.to_bits() & mask).into());
+                Self { addr, prefix }
+            }
+            IpAddr::V6(addr) => {
+                let prefix = prefix.min(128);
+                let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+                let addr = IpAddr::V6((addr.to_bits() & mask).into());
+                Self { addr, prefix }
+            }
+        }
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let bits = |addr: IpAddr| match addr {
+            IpAddr::V4(addr) => (u128::from(addr.to_bits()) << 96, 32),
+            IpAddr::V6(addr) => (addr.to_bits(), 128),
+        };
+        let ((net, len), (addr, addr_len)) = (bits(self.addr), bits(addr.to_canonical()));
+        let mask = u128::MAX
+            .checked_shl(128 - u32::from(self.prefix))
+            .unwrap_or(0);
+        len == addr_len && net & mask == addr & mask
+    }
+}
+
+/// Connection filter for conversions: a 4-tuple, or a CIDR range that matches
+/// if either address is in it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Match {
+    Tuple(Tuple),
+    Cidr(Cidr),
+}
+
+impl FromStr for Match {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        if s.contains('-') {
+            s.parse().map(Self::Tuple)
+        } else {
+            s.parse().map(Self::Cidr)
+        }
+    }
+}
+
+impl Match {
+    pub fn matches(&self, msg: &InetDiagMsg) -> bool {
+        match self {
+            Self::Tuple(tuple) => tuple.matches(msg),
+            Self::Cidr(cidr) => {
+                let tuple = Tuple::new(msg);
+                cidr.contains(tuple.src.ip()) || cidr.contains(tuple.dst.ip())
+            }
+        }
+    }
+}
+
+/* state names as used by ss filters, indexed by the kernel's TCP state */
+const STATE_FILTER_NAMES: [&str; 13] = [
+    "unknown",
+    "established",
+    "syn-sent",
+    "syn-recv",
+    "fin-wait-1",
+    "fin-wait-2",
+    "time-wait",
+    "closed",
+    "close-wait",
+    "last-ack",
+    "listening",
+    "closing",
+    "new-syn-recv",
+];
+
+/// Set of TCP states, written as comma-separated names as for `ss state`
+/// (e.g. `established,time-wait`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct States(u32);
+
+impl FromStr for States {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut states = 0;
+        for name in s.split(',') {
+            let state = STATE_FILTER_NAMES[1..]
+                .iter()
+                .position(|&n| n.eq_ignore_ascii_case(name))
+                .ok_or_else(|| {
+                    format!(
+                        "unknown state {name:?}, expected {}",
+                        STATE_FILTER_NAMES[1..].join(", ")
+                    )
+                })?;
+            states |= 1 << (state + 1);
+        }
+        Ok(Self(states))
+    }
+}
+
+impl States {
+    pub fn contains(&self, state: u8) -> bool {
+        self.0.checked_shr(state.into()).unwrap_or(0) & 1 != 0
+    }
+}
+
+/// Collector that forwards samples within a time range (`from` inclusive,
+/// `to` exclusive) and, if any matches are given, only matching connections,
+/// and, if states are given, only connections in these states.
+pub struct Filter<C: Collector> {
+    inner: C,
+    from: Option<Timestamp>,
+    to: Option<Timestamp>,
+    matches: Vec<Match>,
+    states: Option<States>,
+    active: bool,
+}
+
+impl<C: Collector> Filter<C> {
+    pub fn new(
+        inner: C,
+        from: Option<Timestamp>,
+        to: Option<Timestamp>,
+        matches: Vec<Match>,
+        states: Option<States>,
+    ) -> Self {
+        Self {
+            inner,
+            from,
+            to,
+            matches,
+            states,
+            active: false,
+        }
+    }
+}
+
+impl<C: Collector> Collector for Filter<C> {
+    fn source(&mut self, source: &str) {
+        self.inner.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.inner.metadata(metadata);
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        let time_us = Timestamp::new(time);
+        self.active =
+            self.from.is_none_or(|from| time_us >= from) && self.to.is_none_or(|to| time_us < to);
+        if self.active {
+            self.inner.start(time);
+        }
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        if !self.active {
+            return;
+        }
+        let (msg, _) = InetDiagMsg::ref_from_prefix(data).unwrap();
+        if (self.matches.is_empty() || self.matches.iter().any(|m| m.matches(msg)))
+            && self.states.is_none_or(|states| states.contains(msg.state))
+        {
+            self.inner.out(data);
+        }
+    }
+
+    fn period(&mut self, period: Duration) {
+        if self.active {
+            self.inner.period(period);
+        }
+    }
+
+    fn unscheduled(&mut self) {
+        if self.active {
+            self.inner.unscheduled();
+        }
+    }
+
+    fn stats(&mut self, stats: &crate::SampleStats) {
+        if self.active {
+            self.inner.stats(stats);
+        }
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        if self.active {
+            self.inner.offset(offset);
+        }
+    }
+
+    fn end(&mut self, duration: Duration) {
+        if self.active {
+            self.inner.end(duration);
+        }
+    }
+}
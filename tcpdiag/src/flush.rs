@@ -0,0 +1,95 @@
+use std::{
+    io::Write,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// When the outputs, which flush at the end of every sample, actually write.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    #[default]
+    EverySample,
+    /// At the end of the first sample after the interval since the last flush
+    Interval(Duration),
+    /// Only when the buffer is full and at exit
+    Never,
+}
+
+impl FromStr for FlushPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "every-sample" => Ok(Self::EverySample),
+            "never" => Ok(Self::Never),
+            _ => {
+                let interval = s.strip_prefix("interval:").ok_or_else(|| {
+                    format!("expected every-sample, interval:DURATION, or never, got {s:?}")
+                })?;
+                humantime::parse_duration(interval)
+                    .map(Self::Interval)
+                    .map_err(|err| format!("{interval:?}: {err}"))
+            }
+        }
+    }
+}
+
+impl FlushPolicy {
+    /// Whether to write at the end of a sample, given the time of the last
+    /// write, which is updated if so.
+    pub fn due(self, last: &mut Instant) -> bool {
+        match self {
+            Self::EverySample => true,
+            Self::Interval(interval) if last.elapsed() >= interval => {
+                *last = Instant::now();
+                true
+            }
+            Self::Interval(_) | Self::Never => false,
+        }
+    }
+}
+
+/// Writer that passes flushes to `inner` according to a `FlushPolicy`.
+/// `inner` should buffer, so that skipped flushes save system calls.
+pub struct Flush<W: Write> {
+    inner: W,
+    policy: FlushPolicy,
+    last: Instant,
+}
+
+impl<W: Write> Flush<W> {
+    pub fn new(inner: W, policy: FlushPolicy) -> Self {
+        Self {
+            inner,
+            policy,
+            last: Instant::now(),
+        }
+    }
+}
+
+impl<W: Write> Write for Flush<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.inner.write_all(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.policy.due(&mut self.last) {
+            true => self.inner.flush(),
+            false => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for Flush<W> {
+    fn drop(&mut self) {
+        // Report errors of the final write, unless unwinding already.
+        let result = self.inner.flush();
+        if !std::thread::panicking() {
+            result.unwrap();
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    net::IpAddr,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use zerocopy::FromBytes;
+
+use crate::data::*;
+use crate::{Collector, SampleStats};
+
+/// Collector that tags each socket with the `country` (ISO code) and `city`
+/// (English name) of the peer address, looked up in a MaxMind DB like
+/// GeoLite2-City or GeoLite2-Country. Sockets with peers that aren't in the
+/// database (like listening sockets and private addresses) are passed on
+/// unchanged.
+pub struct GeoIp<C: Collector> {
+    inner: C,
+    path: String,
+    reader: Reader<Vec<u8>>,
+    buf: Vec<u8>,
+}
+
+impl<C: Collector> GeoIp<C> {
+    pub fn new(path: &Path, inner: C) -> Self {
+        let reader =
+            Reader::open_readfile(path).unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+        Self {
+            inner,
+            path: path.display().to_string(),
+            reader,
+            buf: Vec::new(),
+        }
+    }
+}
+
+/// Country and city of an address
+fn lookup(
+    reader: &Reader<Vec<u8>>,
+    addr: IpAddr,
+) -> Result<(Option<&str>, Option<&str>), MaxMindDBError> {
+    let city: geoip2::City = reader.lookup(addr.to_canonical())?;
+    let country = city.country.and_then(|country| country.iso_code);
+    let city = city.city.and_then(|city| city.names?.get("en").copied());
+    Ok((country, city))
+}
+
+impl<C: Collector> Collector for GeoIp<C> {
+    fn out(&mut self, data: &[u8]) {
+        let (msg, _) = InetDiagMsg::ref_from_prefix(data).unwrap();
+        let (country, city) = match lookup(&self.reader, msg.id.dst.get(msg.family)) {
+            Ok(location) => location,
+            Err(MaxMindDBError::AddressNotFoundError(_)) => (None, None),
+            Err(err) => {
+                crate::diagnostic!("{}: {err}", self.path);
+                (None, None)
+            }
+        };
+        let tags: Vec<_> = [("country", country), ("city", city)]
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?)))
+            .collect();
+        if tags.is_empty() {
+            self.inner.out(data);
+        } else {
+            add_tags(data, &tags, &mut self.buf);
+            self.inner.out(&self.buf);
+        }
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.inner.start(time);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.inner.end(duration);
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.inner.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.inner.unscheduled();
+    }
+
+    fn source(&mut self, source: &str) {
+        self.inner.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.inner.metadata(metadata);
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.inner.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.inner.offset(offset);
+    }
+}
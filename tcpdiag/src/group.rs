@@ -0,0 +1,219 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::Write,
+    net::IpAddr,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::data::*;
+use crate::filter::Cidr;
+use crate::stats::Stats;
+use crate::timestamp::{TimeFormat, Timestamp};
+
+use crate::Collector;
+
+/// IPv6 prefix length of `dst/N` and `src/N` without a second length
+const DEFAULT_PREFIX6: u8 = 48;
+
+/// Key of `--group-by`: `dst` or `src` with optional prefix lengths
+/// (`dst/24` groups IPv4 addresses by /24 and IPv6 addresses by /48,
+/// `dst/24/64` by /24 and /64), `dport` or `sport`, or `tag:KEY` (like
+/// `tag:asn`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GroupBy {
+    Dst { prefix: u8, prefix6: u8 },
+    Src { prefix: u8, prefix6: u8 },
+    Dport,
+    Sport,
+    Tag(String),
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        if let Some(key) = s.strip_prefix("tag:") {
+            return Ok(Self::Tag(key.into()));
+        }
+        let mut parts = s.split('/');
+        let key = parts.next().unwrap();
+        let mut length = |max: u8| {
+            parts
+                .next()
+                .map(|length| {
+                    length
+                        .parse()
+                        .ok()
+                        .filter(|&length| length <= max)
+                        .ok_or_else(|| format!("invalid prefix length {length:?}"))
+                })
+                .transpose()
+        };
+        let group_by = match key {
+            "dst" | "src" => {
+                let (prefix, prefix6) = match length(32)? {
+                    Some(prefix) => (prefix, length(128)?.unwrap_or(DEFAULT_PREFIX6)),
+                    None => (32, 128),
+                };
+                match key {
+                    "dst" => Self::Dst { prefix, prefix6 },
+                    _ => Self::Src { prefix, prefix6 },
+                }
+            }
+            "dport" => Self::Dport,
+            "sport" => Self::Sport,
+            _ => {
+                return Err(format!(
+                    "expected dst, src, dport, sport, or tag:KEY, got {key:?}"
+                ))
+            }
+        };
+        if parts.next().is_some() {
+            return Err(format!("too many prefix lengths in {s:?}"));
+        }
+        Ok(group_by)
+    }
+}
+
+/// Group of a socket
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Group {
+    Network(Cidr),
+    Port(u16),
+    /// Value of the tag, `None` for sockets without it
+    Tag(Option<String>),
+}
+
+impl fmt::Display for Group {
+    /// Formats the group as json value.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Network(cidr) => write!(f, "\"{cidr}\""),
+            Self::Port(port) => write!(f, "\"{port}\""),
+            Self::Tag(Some(value)) => f.write_str(&serde_json::to_string(value).unwrap()),
+            Self::Tag(None) => f.write_str("null"),
+        }
+    }
+}
+
+impl GroupBy {
+    fn group(&self, extras: &InetDiagMsgExtra) -> Group {
+        let msg = extras.base;
+        let network = |addr: IpAddr, prefix, prefix6| {
+            let prefix = match addr.to_canonical() {
+                IpAddr::V4(_) => prefix,
+                IpAddr::V6(_) => prefix6,
+            };
+            Group::Network(Cidr::network(addr, prefix))
+        };
+        match self {
+            &Self::Dst { prefix, prefix6 } => network(msg.id.dst.get(msg.family), prefix, prefix6),
+            &Self::Src { prefix, prefix6 } => network(msg.id.src.get(msg.family), prefix, prefix6),
+            Self::Dport => Group::Port(msg.id.dport.get()),
+            Self::Sport => Group::Port(msg.id.sport.get()),
+            Self::Tag(key) => Group::Tag(
+                extras
+                    .tags
+                    .and_then(|tags| tags.iter().find(|&(k, _)| k == key))
+                    .map(|(_, value)| value.into()),
+            ),
+        }
+    }
+}
+
+/// Writes aggregate metrics per group of connections as json lines per
+/// sample instead of individual sockets, ordered by the retransmitted
+/// segments of the group (most first). Listening sockets have no peer and are
+/// not counted.
+pub struct GroupOutput<T: Write> {
+    writer: T,
+    group_by: GroupBy,
+    time: SystemTime,
+    time_format: TimeFormat,
+    source: Option<String>,
+    groups: BTreeMap<Group, Stats>,
+}
+
+crate::impl_output!(GroupOutput<T>);
+
+impl<T: Write> GroupOutput<T> {
+    pub fn new(writer: T, group_by: GroupBy) -> Self {
+        Self {
+            writer,
+            group_by,
+            time: UNIX_EPOCH,
+            time_format: TimeFormat::default(),
+            source: None,
+            groups: BTreeMap::new(),
+        }
+    }
+
+    pub fn set_time_format(&mut self, time_format: TimeFormat) {
+        self.time_format = time_format;
+    }
+}
+
+impl<T: Write> Collector for GroupOutput<T> {
+    fn start(&mut self, time: SystemTime) {
+        self.time = time;
+        self.groups.clear();
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        let extras = InetDiagMsgExtra::parse(data);
+        if extras.base.state == TCP_LISTEN {
+            return;
+        }
+        let group = self.group_by.group(&extras);
+        self.groups.entry(group).or_default().add(&extras);
+    }
+
+    fn end(&mut self, _duration: Duration) {
+        let time = Timestamp::new(self.time).display(&self.time_format);
+        let time = match self.time_format {
+            TimeFormat::Micros => time.to_string(),
+            TimeFormat::Rfc3339(_) => format!("\"{time}\""),
+        };
+        let source = self
+            .source
+            .as_ref()
+            .map(|source| format!(",\"source\":{}", serde_json::to_string(source).unwrap()))
+            .unwrap_or_default();
+        let mut groups: Vec<_> = std::mem::take(&mut self.groups).into_iter().collect();
+        groups.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.total_retrans));
+        for (group, stats) in groups {
+            write!(
+                &mut self.writer,
+                "{{\"time\":{time}{source},\"group\":{group},\"sockets\":{},\
+                 \"retrans\":{},\"segs_out\":{},\"retrans_rate\":{},\
+                 \"bytes_acked\":{},\"bytes_received\":{}",
+                stats.sockets,
+                stats.total_retrans,
+                stats.segs_out,
+                stats.retrans_rate(),
+                stats.bytes_acked,
+                stats.bytes_received,
+            )
+            .unwrap();
+            if let Some(mean) = stats.rtt_mean() {
+                let rtt = |p| stats.rtt_percentile(p).unwrap();
+                write!(
+                    &mut self.writer,
+                    ",\"rtt_mean\":{mean:.0},\"rtt_p50\":{},\"rtt_p95\":{},\"rtt_p99\":{}",
+                    rtt(50.0),
+                    rtt(95.0),
+                    rtt(99.0),
+                )
+                .unwrap();
+            }
+            writeln!(&mut self.writer, "}}").unwrap();
+        }
+        self.writer.flush().unwrap();
+    }
+
+    fn source(&mut self, source: &str) {
+        self.source = Some(source.into());
+    }
+}
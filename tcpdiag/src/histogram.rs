@@ -0,0 +1,254 @@
+use std::{
+    io::Write,
+    time::{Duration, SystemTime},
+};
+
+use crate::data::*;
+use crate::timestamp::{TimeFormat, Timestamp};
+
+use crate::Collector;
+
+/// Bits of a bucket index within a power of two: values below 2^SUB_BITS have
+/// a bucket each, and each higher power of two has 2^(SUB_BITS-1) buckets, so
+/// that a bucket spans at most 1/16 of its values.
+const SUB_BITS: u32 = 5;
+const HALF: u64 = 1 << (SUB_BITS - 1);
+
+/// Log-linear histogram of integers, like HdrHistogram with 2 significant
+/// bits less.
+#[derive(Default, Debug, Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    min: u64,
+    max: u64,
+    sum: u128,
+}
+
+fn bucket(value: u64) -> usize {
+    let shift = (u64::BITS - value.leading_zeros()).saturating_sub(SUB_BITS);
+    (u64::from(shift) * HALF + (value >> shift)) as usize
+}
+
+/// Lowest value of a bucket
+fn low(index: usize) -> u64 {
+    let index = index as u64;
+    match (index / HALF).checked_sub(1) {
+        Some(shift) if shift > 0 => (index - shift * HALF) << shift,
+        _ => index,
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: u64) {
+        let index = bucket(value);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.min = if self.count == 0 {
+            value
+        } else {
+            self.min.min(value)
+        };
+        self.max = self.max.max(value);
+        self.count += 1;
+        self.sum += u128::from(value);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.count == 0 {
+            return;
+        }
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.min = if self.count == 0 {
+            other.min
+        } else {
+            self.min.min(other.min)
+        };
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+        self.sum += other.sum;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum as f64 / self.count as f64)
+    }
+
+    /// Value at a percentile (0 to 100) by the nearest-rank method, as the
+    /// highest value of its bucket (within the minimum and maximum), so it is
+    /// exact below 32 and at most 1/16 too high above.
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((percentile / 100.0 * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        let index = self.counts.iter().position(|&count| {
+            seen += count;
+            seen >= rank
+        })?;
+        Some((low(index + 1) - 1).clamp(self.min, self.max))
+    }
+
+    /// Lowest value and count of the non-empty buckets
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(index, &count)| (low(index), count))
+    }
+
+    /// Writes `{"count":...,"min":...,"max":...,"sum":...,"buckets":[[low,count],...]}`.
+    fn write_json<W: Write>(&self, w: &mut W) {
+        write!(w, "{{\"count\":{}", self.count).unwrap();
+        if self.count > 0 {
+            write!(w, ",\"min\":{},\"max\":{}", self.min, self.max).unwrap();
+        }
+        write!(w, ",\"sum\":{},\"buckets\":[", self.sum).unwrap();
+        for (i, (low, count)) in self.buckets().enumerate() {
+            let comma = if i == 0 { "" } else { "," };
+            write!(w, "{comma}[{low},{count}]").unwrap();
+        }
+        write!(w, "]}}").unwrap();
+    }
+}
+
+/// Whether `HistogramOutput` writes the histograms of each sample or of the
+/// whole capture
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum HistogramScope {
+    #[default]
+    Sample,
+    Capture,
+}
+
+/// Histograms of the connected sockets with tcp_info
+#[derive(Default)]
+struct Histograms {
+    /// Smoothed RTT in microseconds
+    rtt: Histogram,
+    /// Congestion window in segments
+    cwnd: Histogram,
+    /// Retransmitted of sent segments, in parts per million
+    retrans_ppm: Histogram,
+}
+
+/// Writes histograms of the RTT, congestion window, and retransmission rate
+/// of the connections as a json line per sample (or, with
+/// `HistogramScope::Capture`, one line at the end), instead of the sockets.
+/// Each histogram has the count, minimum, maximum, and sum of the values, and
+/// the lowest value and count of each non-empty bucket. Buckets are exact
+/// below 32 and span at most 1/16 of their values above.
+pub struct HistogramOutput<T: Write> {
+    writer: T,
+    scope: HistogramScope,
+    time_format: TimeFormat,
+    /// Start of the sample, or of the first sample of the capture
+    time: Option<SystemTime>,
+    source: Option<String>,
+    samples: u64,
+    histograms: Histograms,
+}
+
+impl<T: Write> HistogramOutput<T> {
+    pub fn new(writer: T, scope: HistogramScope) -> Self {
+        Self {
+            writer,
+            scope,
+            time_format: TimeFormat::default(),
+            time: None,
+            source: None,
+            samples: 0,
+            histograms: Histograms::default(),
+        }
+    }
+
+    pub fn set_time_format(&mut self, time_format: TimeFormat) {
+        self.time_format = time_format;
+    }
+
+    fn write(&mut self) {
+        let Some(time) = self.time else {
+            return;
+        };
+        let time = Timestamp::new(time).display(&self.time_format);
+        match self.time_format {
+            TimeFormat::Micros => write!(&mut self.writer, "{{\"time\":{time}"),
+            TimeFormat::Rfc3339(_) => write!(&mut self.writer, "{{\"time\":\"{time}\""),
+        }
+        .unwrap();
+        match self.scope {
+            HistogramScope::Sample => {
+                if let Some(source) = &self.source {
+                    write!(&mut self.writer, ",\"source\":").unwrap();
+                    serde_json::to_writer(&mut self.writer, source).unwrap();
+                }
+            }
+            HistogramScope::Capture => {
+                write!(&mut self.writer, ",\"samples\":{}", self.samples).unwrap();
+            }
+        }
+        let histograms = std::mem::take(&mut self.histograms);
+        for (name, histogram) in [
+            ("rtt", &histograms.rtt),
+            ("cwnd", &histograms.cwnd),
+            ("retrans_ppm", &histograms.retrans_ppm),
+        ] {
+            write!(&mut self.writer, ",\"{name}\":").unwrap();
+            histogram.write_json(&mut self.writer);
+        }
+        writeln!(&mut self.writer, "}}").unwrap();
+        self.writer.flush().unwrap();
+        self.time = None;
+    }
+}
+
+impl<T: Write> Collector for HistogramOutput<T> {
+    fn source(&mut self, source: &str) {
+        self.source = Some(source.into());
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.time = self.time.or(Some(time));
+        self.samples += 1;
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        let extras = InetDiagMsgExtra::parse(data);
+        let Some(info) = extras.tcp_info.filter(|_| extras.base.state != TCP_LISTEN) else {
+            return;
+        };
+        let histograms = &mut self.histograms;
+        histograms.rtt.record(info.rtt.into());
+        histograms.cwnd.record(info.snd_cwnd.into());
+        let retrans = u64::from(info.total_retrans) * 1_000_000;
+        if let Some(ppm) = retrans.checked_div(info.segs_out.into()) {
+            histograms.retrans_ppm.record(ppm);
+        }
+    }
+
+    fn end(&mut self, _duration: Duration) {
+        if self.scope == HistogramScope::Sample {
+            self.write();
+        }
+    }
+}
+
+impl<T: Write> Drop for HistogramOutput<T> {
+    fn drop(&mut self) {
+        if self.scope == HistogramScope::Capture {
+            self.write();
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    net::{TcpStream, ToSocketAddrs},
+    str::FromStr,
+    time::Duration,
+};
+
+/// Time limit of connecting, and of each read and write of a request
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Splits `host[:port]` (with brackets around IPv6 addresses) of a URL.
+pub(crate) fn parse_authority(authority: &str, default_port: u16) -> Result<(String, u16), String> {
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => {
+            let port = port.parse().map_err(|_| format!("invalid port {port:?}"))?;
+            (host, port)
+        }
+        _ => (authority, default_port),
+    };
+    if host.is_empty() {
+        return Err(format!("missing host in {authority:?}"));
+    }
+    Ok((host.into(), port))
+}
+
+/// Connects to `host` (as in a URL) with the timeouts of a request.
+pub(crate) fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let mut last = io::Error::new(io::ErrorKind::NotFound, "no address");
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, TIMEOUT) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(TIMEOUT))?;
+                stream.set_write_timeout(Some(TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(err) => last = err,
+        }
+    }
+    Err(last)
+}
+
+/// `http://host[:port][/path]` URL of an HTTP endpoint (TLS is not supported)
+#[derive(Debug, Clone)]
+pub struct HttpUrl {
+    /// Host as in the URL, with brackets around IPv6 addresses
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl FromStr for HttpUrl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let rest = s
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("expected an http:// URL, got {s:?}"))?;
+        let (authority, path) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+        let (host, port) = parse_authority(authority, 80)?;
+        Ok(Self {
+            host,
+            port,
+            path: if path.is_empty() { "/" } else { path }.into(),
+        })
+    }
+}
+
+impl fmt::Display for HttpUrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "http://{}:{}{}", self.host, self.port, self.path)
+    }
+}
+
+impl HttpUrl {
+    /// The URL with `path` appended to its path.
+    pub fn join(&self, path: &str) -> Self {
+        Self {
+            path: format!("{}/{}", self.path.trim_end_matches('/'), path),
+            ..self.clone()
+        }
+    }
+}
+
+/// Sends a POST request (on a new connection) and returns the status code of
+/// the response.
+pub fn post(url: &HttpUrl, content_type: &str, body: &[u8]) -> io::Result<u16> {
+    let mut stream = connect(&url.host, url.port)?;
+    let host = match url.port {
+        80 => url.host.clone(),
+        port => format!("{}:{port}", url.host),
+    };
+    let head = format!(
+        "POST {} HTTP/1.1\r\nHost: {host}\r\nContent-Type: {content_type}\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        url.path,
+        body.len(),
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status)?;
+    status
+        .split(' ')
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| {
+            let status = status.trim_end();
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("bad status line {status:?}"),
+            )
+        })
+}
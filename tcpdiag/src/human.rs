@@ -0,0 +1,98 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    Bytes,
+    BytesPerSec,
+    Micros,
+    Millis,
+}
+
+impl Unit {
+    /// Symbol of the unscaled unit, e.g. `us` for `Micros`
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Bytes => "B",
+            Unit::BytesPerSec => "B/s",
+            Unit::Micros => "us",
+            Unit::Millis => "ms",
+        }
+    }
+}
+
+/// Returns the unit of a column, named by its path as in the CSV header. For
+/// listening sockets, `base.rqueue` and `base.wqueue` are counts instead (see
+/// `socket_unit`).
+pub fn unit(column: &str) -> Option<Unit> {
+    let (group, field) = column.rsplit_once('.').unwrap_or(("", column));
+    match (group, field) {
+        ("base", "rqueue" | "wqueue") => Some(Unit::Bytes),
+        (
+            "tcp_info",
+            "bytes_acked" | "bytes_received" | "bytes_sent" | "bytes_retrans" | "notsent_bytes"
+            | "rcv_space" | "rcv_ssthresh" | "snd_wnd",
+        ) => Some(Unit::Bytes),
+        ("tcp_info", "pacing_rate" | "max_pacing_rate" | "delivery_rate") => {
+            Some(Unit::BytesPerSec)
+        }
+        ("bbr", "bw") | ("bbr3", "bw_hi" | "bw_lo") => Some(Unit::BytesPerSec),
+        (
+            "tcp_info",
+            "rto" | "ato" | "rtt" | "rttvar" | "rcv_rtt" | "min_rtt" | "busy_time" | "rwnd_limited"
+            | "sndbuf_limited",
+        ) => Some(Unit::Micros),
+        ("bbr", "min_rtt") | ("", "offset") => Some(Unit::Micros),
+        ("tcp_info", "last_data_sent" | "last_ack_sent" | "last_data_recv" | "last_ack_recv")
+        | ("timer", "expires") => Some(Unit::Millis),
+        _ => None,
+    }
+}
+
+/// Returns the unit of a column for a socket in `state`: the queues of
+/// listening sockets are the length and limit of the accept queue, without
+/// unit.
+pub fn socket_unit(column: &str, state: u8) -> Option<Unit> {
+    match column {
+        "base.rqueue" | "base.wqueue" if state == crate::data::TCP_LISTEN => None,
+        _ => unit(column),
+    }
+}
+
+/// Formats with three significant digits (but never in exponent notation).
+fn significant(value: f64) -> String {
+    match value.abs() {
+        v if v >= 100.0 => format!("{value:.0}"),
+        v if v >= 10.0 => format!("{value:.1}"),
+        _ => format!("{value:.2}"),
+    }
+}
+
+pub fn format(unit: Unit, value: u64) -> String {
+    match unit {
+        _ if value == u64::MAX => "inf".into(),
+        Unit::Bytes => {
+            const PREFIXES: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+            if value < 1024 {
+                return format!("{value}B");
+            }
+            let mut scaled = value as f64;
+            let mut prefix = 0;
+            while scaled >= 1024.0 && prefix < PREFIXES.len() - 1 {
+                scaled /= 1024.0;
+                prefix += 1;
+            }
+            format!("{}{}", significant(scaled), PREFIXES[prefix])
+        }
+        Unit::BytesPerSec => format!("{}Mbit/s", significant(value as f64 * 8e-6)),
+        Unit::Micros => format!("{}ms", significant(value as f64 * 1e-3)),
+        Unit::Millis => format!("{value}ms"),
+    }
+}
+
+/// Scales a raw value of the given column of a socket in `state`, or returns
+/// it unchanged if the column has no unit or the value is not numeric (e.g.
+/// `_`).
+pub fn format_column<'a>(column: &str, state: u8, value: &'a str) -> std::borrow::Cow<'a, str> {
+    match (socket_unit(column, state), value.parse()) {
+        (Some(unit), Ok(value)) => format(unit, value).into(),
+        _ => value.into(),
+    }
+}
@@ -31,8 +31,8 @@ impl csv::CsvWrite for NlU64 {
     }
 }
 impl csv::Csv for NlU64 {
-    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Self {
-        Self::new(u64::read(r))
+    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<Self, csv::Error> {
+        u64::read(r).map(Self::new)
     }
 }
 
@@ -79,8 +79,8 @@ macro_rules! wrapper {
             }
         }
         impl csv::Csv for $name {
-            fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Self {
-                Self::new(<$raw>::read(r))
+            fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<Self, csv::Error> {
+                <$raw>::read(r).map(Self::new)
             }
         }
 
@@ -1,69 +1,676 @@
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize};
 use std::{
-    io::{BufRead, BufReader, StdinLock, Write},
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    io::{BufRead, Read, Write},
+    time::{Duration, SystemTime},
 };
 
 use crate::data::*;
+use crate::filter::Tuple;
+use crate::integer::{NlU64, U16BE, U64NE};
+use crate::timestamp::{TimeFormat, Timestamp};
 
-use crate::Collector;
+use crate::{Collector, SampleStats};
+
+/// Key of the sockets in the `samples` of a sample, which is then an object
+/// instead of an array.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum JsonKey {
+    /// Socket cookie
+    Cookie,
+    /// 4-tuple as `src:port-dst:port`, which is not unique for listening sockets
+    Tuple,
+}
 
 pub struct JsonOutput<T: Write> {
     writer: T,
     comma: &'static str,
+    key: Option<JsonKey>,
+    period: Option<Duration>,
+    unscheduled: bool,
+    stats: Option<SampleStats>,
+    offset: Option<Duration>,
+    source: Option<String>,
+    time_format: TimeFormat,
+    buf: Vec<u8>,
 }
 
 crate::impl_output!(JsonOutput<T>);
 
 impl<T: Write> JsonOutput<T> {
     pub fn new(writer: T) -> Self {
-        Self { writer, comma: "" }
+        Self {
+            writer,
+            comma: "",
+            key: None,
+            period: None,
+            unscheduled: false,
+            stats: None,
+            offset: None,
+            source: None,
+            time_format: TimeFormat::default(),
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn set_time_format(&mut self, time_format: TimeFormat) {
+        self.time_format = time_format;
+    }
+
+    /// Writes the sockets of each sample as object with the given key.
+    pub fn set_key(&mut self, key: Option<JsonKey>) {
+        self.key = key;
     }
 }
 
 impl<T: Write> Collector for JsonOutput<T> {
     fn start(&mut self, time: SystemTime) {
-        let time = time.duration_since(UNIX_EPOCH).unwrap().as_micros() as u64;
-        write!(&mut self.writer, "{{\"time\":{time},\"samples\":[").unwrap();
+        let time = Timestamp::new(time).display(&self.time_format);
+        match self.time_format {
+            TimeFormat::Micros => write!(&mut self.writer, "{{\"time\":{time}"),
+            TimeFormat::Rfc3339(_) => write!(&mut self.writer, "{{\"time\":\"{time}\""),
+        }
+        .unwrap();
+        if let Some(source) = &self.source {
+            write!(&mut self.writer, ",\"source\":").unwrap();
+            serde_json::to_writer(&mut self.writer, source).unwrap();
+        }
+        let open = if self.key.is_some() { "{" } else { "[" };
+        write!(&mut self.writer, ",\"samples\":{open}").unwrap();
         self.comma = "";
+        self.period = None;
+        self.unscheduled = false;
+        self.stats = None;
+        self.offset = None;
     }
 
     fn end(&mut self, duration: Duration) {
         let time = duration.as_micros() as u64;
-        writeln!(&mut self.writer, "],\"duration\":{time}}}").unwrap();
+        let close = if self.key.is_some() { "}" } else { "]" };
+        write!(&mut self.writer, "{close},\"duration\":{time}").unwrap();
+        if let Some(period) = self.period {
+            write!(&mut self.writer, ",\"period\":{}", period.as_micros()).unwrap();
+        }
+        if self.unscheduled {
+            write!(&mut self.writer, ",\"unscheduled\":true").unwrap();
+        }
+        if let Some(stats) = &self.stats {
+            write!(&mut self.writer, ",\"stats\":").unwrap();
+            serde_json::to_writer(&mut self.writer, stats).unwrap();
+        }
+        writeln!(&mut self.writer, "}}").unwrap();
         self.writer.flush().unwrap();
     }
 
+    fn period(&mut self, period: Duration) {
+        self.period = Some(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.unscheduled = true;
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.stats = Some(*stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.offset = Some(offset);
+    }
+
+    fn source(&mut self, source: &str) {
+        self.source = Some(source.into());
+    }
+
+    /// Written as a line of its own, `{"metadata":{"key":"value",...}}`.
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        write!(&mut self.writer, "{{\"metadata\":{{").unwrap();
+        for (i, (key, value)) in metadata.iter().enumerate() {
+            if i > 0 {
+                write!(&mut self.writer, ",").unwrap();
+            }
+            serde_json::to_writer(&mut self.writer, key).unwrap();
+            write!(&mut self.writer, ":").unwrap();
+            serde_json::to_writer(&mut self.writer, value).unwrap();
+        }
+        writeln!(&mut self.writer, "}}}}").unwrap();
+    }
+
     fn out(&mut self, data: &[u8]) {
-        let extras = InetDiagMsgExtra::parse(data);
-        write!(&mut self.writer, "{}", self.comma).unwrap();
-        serde_json::to_writer(&mut self.writer, &extras).unwrap();
+        let mut extras = InetDiagMsgExtra::parse(data);
+        extras.offset = self.offset.map(|offset| offset.as_micros() as u32);
+        self.buf.clear();
+        self.buf.extend_from_slice(self.comma.as_bytes());
+        match self.key {
+            Some(JsonKey::Cookie) => write!(self.buf, "\"{}\":", extras.base.id.cookie.get()),
+            Some(JsonKey::Tuple) => write!(self.buf, "\"{}\":", Tuple::new(extras.base)),
+            None => Ok(()),
+        }
+        .unwrap();
+        write_socket(&extras, &mut self.buf);
+        self.writer.write_all(&self.buf).unwrap();
         self.comma = ",";
     }
 }
 
+/// Values formatted by the built-in JSON writer, which produces the same output
+/// as serde_json, but formats directly into the output buffer (with itoa).
+trait JsonValue {
+    fn write_json(&self, buf: &mut Vec<u8>);
+}
+
+macro_rules! json_int {
+    ($($ty:ty),*) => {$(
+        impl JsonValue for $ty {
+            fn write_json(&self, buf: &mut Vec<u8>) {
+                buf.extend_from_slice(itoa::Buffer::new().format(*self).as_bytes());
+            }
+        }
+    )*};
+}
+
+json_int!(u8, u16, u32, u64);
+
+macro_rules! json_wrapper {
+    ($($ty:ty),*) => {$(
+        impl JsonValue for $ty {
+            fn write_json(&self, buf: &mut Vec<u8>) {
+                self.get().write_json(buf);
+            }
+        }
+    )*};
+}
+
+json_wrapper!(U16BE, U64NE, NlU64);
+
+/// Writes `"name":value,`, the trailing comma is replaced by `close`.
+fn field(buf: &mut Vec<u8>, name: &str, value: &impl JsonValue) {
+    buf.push(b'"');
+    buf.extend_from_slice(name.as_bytes());
+    buf.extend_from_slice(b"\":");
+    value.write_json(buf);
+    buf.push(b',');
+}
+
+fn close(buf: &mut Vec<u8>) {
+    match buf.last_mut() {
+        Some(last @ b',') => *last = b'}',
+        _ => buf.push(b'}'),
+    }
+}
+
+/// Writes the given fields of a struct as JSON object (in declaration order,
+/// like the derived Serialize implementations).
+macro_rules! json_object {
+    ($buf:expr, $obj:expr; $($field:ident),* $(,)?) => {{
+        let (buf, obj): (&mut Vec<u8>, _) = ($buf, $obj);
+        buf.push(b'{');
+        $(field(buf, stringify!($field), &obj.$field);)*
+        close(buf);
+    }};
+}
+
+impl JsonValue for Wscale {
+    fn write_json(&self, buf: &mut Vec<u8>) {
+        json_object!(buf, &self.get(); snd, rcv);
+    }
+}
+
+impl JsonValue for std::net::IpAddr {
+    fn write_json(&self, buf: &mut Vec<u8>) {
+        write!(buf, "\"{self}\"").unwrap();
+    }
+}
+
+/// Congestion control names are written by `write_extras` only if they need
+/// no escaping.
+impl JsonValue for &str {
+    fn write_json(&self, buf: &mut Vec<u8>) {
+        buf.push(b'"');
+        buf.extend_from_slice(self.as_bytes());
+        buf.push(b'"');
+    }
+}
+
+impl JsonValue for InetDiagMsg {
+    fn write_json(&self, buf: &mut Vec<u8>) {
+        buf.push(b'{');
+        field(buf, "family", &self.family);
+        field(buf, "state", &self.state);
+        field(buf, "timer", &self.timer);
+        field(buf, "retrans", &self.retrans);
+        buf.extend_from_slice(b"\"id\":{");
+        field(buf, "sport", &self.id.sport);
+        field(buf, "dport", &self.id.dport);
+        field(buf, "src", &self.id.src.get(self.family));
+        field(buf, "dst", &self.id.dst.get(self.family));
+        field(buf, "ifindex", &self.id.ifindex);
+        field(buf, "cookie", &self.id.cookie);
+        close(buf);
+        buf.push(b',');
+        field(buf, "expires", &self.expires);
+        field(buf, "rqueue", &self.rqueue);
+        field(buf, "wqueue", &self.wqueue);
+        field(buf, "uid", &self.uid);
+        field(buf, "inode", &self.inode);
+        close(buf);
+    }
+}
+
+impl JsonValue for TcpInfo {
+    fn write_json(&self, buf: &mut Vec<u8>) {
+        json_object!(buf, self;
+            state, ca_state, retransmits, probes, backoff, options, wscale, flags,
+            rto, ato, snd_mss, rcv_mss, unacked, sacked, lost, retrans, fackets,
+            last_data_sent, last_ack_sent, last_data_recv, last_ack_recv, pmtu,
+            rcv_ssthresh, rtt, rttvar, snd_ssthresh, snd_cwnd, advmss, reordering,
+            rcv_rtt, rcv_space, total_retrans, pacing_rate, max_pacing_rate,
+            bytes_acked, bytes_received, segs_out, segs_in, notsent_bytes, min_rtt,
+            data_segs_in, data_segs_out, delivery_rate, busy_time, rwnd_limited,
+            sndbuf_limited, delivered, delivered_ce, bytes_sent, bytes_retrans,
+            dsack_dups, reord_seen, rcv_ooopack, snd_wnd,
+        );
+    }
+}
+
+impl JsonValue for BbrInfo {
+    fn write_json(&self, buf: &mut Vec<u8>) {
+        json_object!(buf, self; bw, min_rtt, pacing_gain, cwnd_gain);
+    }
+}
+
+impl JsonValue for Bbr3Info {
+    fn write_json(&self, buf: &mut Vec<u8>) {
+        json_object!(buf, self;
+            bw_hi, bw_lo, mode, phase, version, inflight_lo, inflight_hi, extra_acked,
+        );
+    }
+}
+
+impl JsonValue for AcceptQueue {
+    fn write_json(&self, buf: &mut Vec<u8>) {
+        json_object!(buf, self; backlog, max_backlog);
+    }
+}
+
+impl<T: AsRef<[u8]>> JsonValue for Hex<T> {
+    fn write_json(&self, buf: &mut Vec<u8>) {
+        write!(buf, "\"{self}\"").unwrap();
+    }
+}
+
+impl JsonValue for TimerKind {
+    fn write_json(&self, buf: &mut Vec<u8>) {
+        timer_name(self.0).write_json(buf);
+    }
+}
+
+impl JsonValue for Timer {
+    fn write_json(&self, buf: &mut Vec<u8>) {
+        json_object!(buf, self; kind, expires, retrans);
+    }
+}
+
+impl JsonValue for Tags<'_> {
+    fn write_json(&self, buf: &mut Vec<u8>) {
+        buf.push(b'{');
+        for (key, value) in self.iter() {
+            serde_json::to_writer(&mut *buf, key).unwrap();
+            buf.push(b':');
+            serde_json::to_writer(&mut *buf, value).unwrap();
+            buf.push(b',');
+        }
+        close(buf);
+    }
+}
+
+impl<T: JsonValue + ?Sized> JsonValue for &T {
+    fn write_json(&self, buf: &mut Vec<u8>) {
+        (**self).write_json(buf);
+    }
+}
+
+/// Writes a socket like `serde_json::to_writer`, but faster. Returns false
+/// (for the serde fallback) if the congestion control name needs escaping.
+fn write_extras(extras: &InetDiagMsgExtra, buf: &mut Vec<u8>) -> bool {
+    let plain = |s: &str| {
+        s.bytes()
+            .all(|b| b.is_ascii_graphic() && b != b'"' && b != b'\\')
+    };
+    if extras.cong.is_some_and(|cong| !plain(cong)) {
+        return false;
+    }
+    buf.push(b'{');
+    field(buf, "base", extras.base);
+    if let Some(cong) = &extras.cong {
+        field(buf, "cong", cong);
+    }
+    if let Some(tcp_info) = &extras.tcp_info {
+        field(buf, "tcp_info", tcp_info);
+    }
+    if let Some(tail) = &extras.tcp_info_tail {
+        field(buf, "tcp_info_tail", tail);
+    }
+    if let Some(bbr) = &extras.bbr {
+        field(buf, "bbr", bbr);
+    }
+    if let Some(bbr3) = &extras.bbr3 {
+        field(buf, "bbr3", bbr3);
+    }
+    if let Some(accept_queue) = &extras.accept_queue {
+        field(buf, "accept_queue", accept_queue);
+    }
+    if let Some(timer) = &extras.timer {
+        field(buf, "timer", timer);
+    }
+    if let Some(offset) = &extras.offset {
+        field(buf, "offset", offset);
+    }
+    if let Some(tags) = &extras.tags {
+        field(buf, "tags", tags);
+    }
+    close(buf);
+    true
+}
+
+/// Appends a socket as JSON object to `buf`.
+pub(crate) fn write_socket(extras: &InetDiagMsgExtra, buf: &mut Vec<u8>) {
+    let prefix = buf.len();
+    if !write_extras(extras, buf) {
+        buf.truncate(prefix);
+        serde_json::to_writer(buf, extras).unwrap();
+    }
+}
+
 #[derive(Serialize, Deserialize)]
-struct JsonFormat {
-    time: u64,
-    samples: Vec<InetDiagMsgExtraOwned>,
-    duration: u32,
+pub(crate) struct JsonFormat {
+    pub(crate) time: Timestamp,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) source: Option<String>,
+    #[serde(deserialize_with = "deserialize_samples")]
+    pub(crate) samples: Vec<InetDiagMsgExtraOwned>,
+    pub(crate) duration: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) period: Option<u64>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) unscheduled: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) stats: Option<SampleStats>,
 }
 
-pub fn read_json(mut reader: BufReader<StdinLock>, mut writer: Box<dyn Collector>) {
-    let mut buf = String::new();
+/// Passes each socket of a sample to a function while it is parsed, from an
+/// array or (with `JsonKey`) object.
+struct Samples<F: FnMut(InetDiagMsgExtraOwned)>(F);
+
+impl<'de, F: FnMut(InetDiagMsgExtraOwned)> de::DeserializeSeed<'de> for Samples<F> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, F: FnMut(InetDiagMsgExtraOwned)> de::Visitor<'de> for Samples<F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an array or object of sockets")
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(sample) = seq.next_element()? {
+            self.0(sample);
+        }
+        Ok(())
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        while let Some((de::IgnoredAny, sample)) = map.next_entry()? {
+            self.0(sample);
+        }
+        Ok(())
+    }
+}
+
+fn deserialize_samples<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<InetDiagMsgExtraOwned>, D::Error> {
+    let mut samples = Vec::new();
+    de::DeserializeSeed::deserialize(Samples(|sample| samples.push(sample)), deserializer)?;
+    Ok(samples)
+}
+
+/// Reads the entries of a json object of strings, in their order.
+struct Metadata;
+
+impl<'de> de::DeserializeSeed<'de> for Metadata {
+    type Value = Vec<(String, String)>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> de::Visitor<'de> for Metadata {
+    type Value = Vec<(String, String)>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("an object of strings")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut metadata = Vec::new();
+        while let Some(entry) = map.next_entry()? {
+            metadata.push(entry);
+        }
+        Ok(metadata)
+    }
+}
+
+fn deserialize_metadata<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(String, String)>, D::Error> {
+    de::DeserializeSeed::deserialize(Metadata, deserializer)
+}
+
+/// Line of a json capture before its samples, see `Collector::metadata`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct JsonMetadata {
+    #[serde(deserialize_with = "deserialize_metadata")]
+    pub(crate) metadata: Vec<(String, String)>,
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum Field {
+    Metadata,
+    Time,
+    Source,
+    Samples,
+    Duration,
+    Period,
+    Unscheduled,
+    Stats,
+    #[serde(other)]
+    Other,
+}
+
+/// Reads a line of `JsonFormat` (or `JsonMetadata`) and passes it to the collector, the sockets
+/// while they are parsed (if they follow the time, as written by
+/// `JsonOutput`).
+struct Line<'a, C> {
+    writer: &'a mut C,
+    msg: &'a mut Vec<u8>,
+    /// Whether `start` was called, so an error must end the sample
+    started: &'a mut bool,
+    /// Receive offset of the previous socket
+    offset: Option<u32>,
+}
+
+impl<C: Collector> Line<'_, C> {
+    fn start(&mut self, time: Timestamp, source: &Option<String>) {
+        if let Some(source) = source {
+            self.writer.source(source);
+        }
+        self.writer.start(time.system_time());
+        *self.started = true;
+    }
+
+    fn out(
+        writer: &mut C,
+        msg: &mut Vec<u8>,
+        offset: &mut Option<u32>,
+        sample: InetDiagMsgExtraOwned,
+    ) {
+        if let Some(micros) = sample.offset.filter(|&micros| *offset != Some(micros)) {
+            *offset = Some(micros);
+            writer.offset(Duration::from_micros(micros.into()));
+        }
+        msg.clear();
+        sample.write_to(msg);
+        writer.out(msg);
+    }
+}
+
+impl<'de, C: Collector> de::DeserializeSeed<'de> for Line<'_, C> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, C: Collector> de::Visitor<'de> for Line<'_, C> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a sample")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        let mut time = None;
+        let mut source = None;
+        // Sockets that preceded the time
+        let mut samples = None;
+        let mut duration = None;
+        let mut period = None;
+        let mut unscheduled = false;
+        let mut stats = None;
+        let mut metadata = false;
+        while let Some(key) = map.next_key()? {
+            match key {
+                Field::Metadata => {
+                    self.writer.metadata(&map.next_value_seed(Metadata)?);
+                    metadata = true;
+                }
+                Field::Time => time = Some(map.next_value::<Timestamp>()?),
+                Field::Source => source = map.next_value()?,
+                Field::Samples => match time {
+                    Some(time) => {
+                        self.start(time, &source);
+                        let (writer, msg, offset) =
+                            (&mut *self.writer, &mut *self.msg, &mut self.offset);
+                        map.next_value_seed(Samples(|sample| {
+                            Self::out(writer, msg, offset, sample)
+                        }))?;
+                    }
+                    None => {
+                        let mut buffer = Vec::new();
+                        map.next_value_seed(Samples(|sample| buffer.push(sample)))?;
+                        samples = Some(buffer);
+                    }
+                },
+                Field::Duration => duration = Some(map.next_value::<u64>()?),
+                Field::Period => period = map.next_value::<Option<u64>>()?,
+                Field::Unscheduled => unscheduled = map.next_value()?,
+                Field::Stats => stats = map.next_value::<Option<SampleStats>>()?,
+                Field::Other => {
+                    map.next_value::<de::IgnoredAny>()?;
+                }
+            }
+        }
+        if metadata && time.is_none() {
+            return Ok(());
+        }
+        let time = time.ok_or_else(|| de::Error::missing_field("time"))?;
+        let duration = duration.ok_or_else(|| de::Error::missing_field("duration"))?;
+        if !*self.started {
+            let samples = samples.ok_or_else(|| de::Error::missing_field("samples"))?;
+            self.start(time, &source);
+            for sample in samples {
+                Self::out(self.writer, self.msg, &mut self.offset, sample);
+            }
+        }
+        if let Some(period) = period {
+            self.writer.period(Duration::from_micros(period));
+        }
+        if unscheduled {
+            self.writer.unscheduled();
+        }
+        if let Some(stats) = &stats {
+            self.writer.stats(stats);
+        }
+        self.writer.end(Duration::from_micros(duration));
+        Ok(())
+    }
+}
+
+/// Length up to which lines are read as a whole, which parses faster than
+/// streaming them from the reader.
+const MAX_LINE: u64 = 1 << 24;
+
+/// Reads a json capture. The sockets are passed to the collector while they
+/// are parsed, and lines longer than `MAX_LINE` are streamed, so memory stays
+/// bounded for samples of any size. Malformed lines are skipped; a sample
+/// that was already started ends with a duration of zero.
+pub fn read_json<R: BufRead, C: Collector>(reader: R, writer: C) {
+    try_read_json(reader, writer).unwrap()
+}
+
+/// Like `read_json`, but returns read errors.
+pub fn try_read_json<R: BufRead, C: Collector>(
+    mut reader: R,
+    mut writer: C,
+) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut msg = Vec::new();
     loop {
         buf.clear();
-        reader.read_line(&mut buf).unwrap();
+        (&mut reader).take(MAX_LINE).read_until(b'\n', &mut buf)?;
         if buf.is_empty() {
-            return;
+            return Ok(());
         }
-        let Ok(json): Result<JsonFormat, _> = serde_json::from_str(&buf) else {
-            continue;
+        let mut started = false;
+        let line = Line {
+            writer: &mut writer,
+            msg: &mut msg,
+            started: &mut started,
+            offset: None,
+        };
+        let result = if buf.ends_with(b"\n") {
+            // Validating the whole line upfront is faster than each string.
+            match std::str::from_utf8(&buf) {
+                Ok(text) => de::DeserializeSeed::deserialize(
+                    line,
+                    &mut serde_json::Deserializer::from_str(text),
+                ),
+                Err(_) => de::DeserializeSeed::deserialize(
+                    line,
+                    &mut serde_json::Deserializer::from_slice(&buf),
+                ),
+            }
+        } else {
+            let rest = std::io::Cursor::new(&buf).chain(&mut reader);
+            let result = de::DeserializeSeed::deserialize(
+                line,
+                &mut serde_json::Deserializer::from_reader(rest),
+            );
+            // The rest of the line (its newline or, after an error, the remains)
+            reader.skip_until(b'\n')?;
+            result
         };
-        writer.start(UNIX_EPOCH + Duration::from_micros(json.time));
-        for x in json.samples {
-            writer.out(&x.to_vec());
+        if result.is_err() && started {
+            writer.end(Duration::ZERO);
         }
-        writer.end(Duration::from_micros(json.duration.into()));
     }
 }
@@ -0,0 +1,287 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use rustls_pki_types::{pem::PemObject, CertificateDer};
+use serde::Deserialize;
+
+use crate::data::*;
+use crate::{Collector, SampleStats};
+
+/// Service account mounted into pods, for the in-cluster configuration
+const SERVICE_ACCOUNT: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+const TIMEOUT: Duration = Duration::from_secs(10);
+/// Minimum time between pod list requests for unknown containers
+const REFRESH: Duration = Duration::from_secs(10);
+/// Maximum age of the pod list, so that label changes are picked up
+const MAX_AGE: Duration = Duration::from_secs(60);
+
+#[derive(Deserialize)]
+struct PodList {
+    items: Vec<Pod>,
+}
+
+#[derive(Deserialize)]
+struct Pod {
+    metadata: PodMetadata,
+    #[serde(default)]
+    status: PodStatus,
+}
+
+#[derive(Deserialize)]
+struct PodMetadata {
+    name: String,
+    namespace: String,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct PodStatus {
+    #[serde(default)]
+    container_statuses: Vec<ContainerStatus>,
+    #[serde(default)]
+    init_container_statuses: Vec<ContainerStatus>,
+    #[serde(default)]
+    ephemeral_container_statuses: Vec<ContainerStatus>,
+}
+
+#[derive(Deserialize)]
+struct ContainerStatus {
+    /// Container ID with the runtime, like `containerd://ID`
+    #[serde(rename = "containerID")]
+    container_id: Option<String>,
+}
+
+/// Tags of the pods by container ID
+type Containers = HashMap<String, Arc<Vec<(String, String)>>>;
+
+/// Client for the pod list of the node
+struct PodLister {
+    agent: ureq::Agent,
+    /// URL of the pod list of the node
+    url: String,
+    node: String,
+    token: Option<String>,
+}
+
+/// Collector that tags each socket with the `pod`, `namespace`, and labels
+/// (as `label.KEY`) of the pod of its container, from the `container` tag of
+/// `Containers`. The pods of the node are listed from the Kubernetes API
+/// server when created, and then by a background thread (at most every 10
+/// seconds when a container isn't known, and at least every minute), whose
+/// latest list applies from the next sample on. Sockets of other containers
+/// are passed on unchanged.
+pub struct Kubernetes<C: Collector> {
+    inner: C,
+    /// Latest list of the refreshing thread
+    latest: Arc<Mutex<Arc<Containers>>>,
+    /// List for the current sample
+    containers: Arc<Containers>,
+    /// Asks the refreshing thread for a list with an unknown container
+    unknown: SyncSender<()>,
+    buf: Vec<u8>,
+}
+
+impl<C: Collector> Kubernetes<C> {
+    /// Uses the API server at `api` (like `http://127.0.0.1:8001` of `kubectl
+    /// proxy`) or, without it, the in-cluster configuration of the service
+    /// account. The node defaults to `NODE_NAME` from the environment (set
+    /// with the downward API), and else to the host name.
+    pub fn new(api: Option<&str>, node: Option<&str>, inner: C) -> Self {
+        let mut agent = ureq::AgentBuilder::new().timeout(TIMEOUT);
+        let (api, token) = match api {
+            Some(api) => (api.trim_end_matches('/').to_string(), None),
+            None => {
+                let env = |name| {
+                    std::env::var(name).unwrap_or_else(|_| {
+                        panic!("{name} is not set; outside of a cluster, use --kube-api")
+                    })
+                };
+                let read = |name: &str| {
+                    let path = format!("{SERVICE_ACCOUNT}/{name}");
+                    std::fs::read(&path).unwrap_or_else(|err| panic!("{path}: {err}"))
+                };
+                let host = env("KUBERNETES_SERVICE_HOST");
+                let host = match host.contains(':') {
+                    true => format!("[{host}]"),
+                    false => host,
+                };
+                let api = format!("https://{host}:{}", env("KUBERNETES_SERVICE_PORT"));
+                let mut roots = rustls::RootCertStore::empty();
+                for cert in CertificateDer::pem_slice_iter(&read("ca.crt")) {
+                    let cert = cert.unwrap_or_else(|err| panic!("ca.crt: {err}"));
+                    roots
+                        .add(cert)
+                        .unwrap_or_else(|err| panic!("ca.crt: {err}"));
+                }
+                let config = rustls::ClientConfig::builder()
+                    .with_root_certificates(roots)
+                    .with_no_client_auth();
+                agent = agent.tls_config(Arc::new(config));
+                let token = String::from_utf8_lossy(&read("token")).trim().to_string();
+                (api, Some(token))
+            }
+        };
+        let node = match node {
+            Some(node) => node.into(),
+            None => std::env::var("NODE_NAME").unwrap_or_else(|_| {
+                std::fs::read_to_string("/proc/sys/kernel/hostname")
+                    .unwrap()
+                    .trim()
+                    .into()
+            }),
+        };
+        let lister = PodLister {
+            agent: agent.build(),
+            url: format!("{api}/api/v1/pods"),
+            node,
+            token,
+        };
+        let containers = lister.list().unwrap_or_else(|err| {
+            crate::diagnostic!("kubernetes: {err}");
+            Containers::new()
+        });
+        let containers = Arc::new(containers);
+        let latest = Arc::new(Mutex::new(containers.clone()));
+        let (unknown, requests) = sync_channel(1);
+        let shared = latest.clone();
+        std::thread::spawn(move || lister.refresh(&shared, &requests));
+        Self {
+            inner,
+            latest,
+            containers,
+            unknown,
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl PodLister {
+    /// Lists the pods again at least every `MAX_AGE`, or `REFRESH` after the
+    /// last list when asked for an unknown container, until the collector is
+    /// dropped.
+    fn refresh(&self, latest: &Mutex<Arc<Containers>>, requests: &Receiver<()>) {
+        // Signals are for the sampling thread.
+        crate::signal::block_in_thread(true);
+        let mut refreshed = Instant::now();
+        loop {
+            match requests.recv_timeout(MAX_AGE.saturating_sub(refreshed.elapsed())) {
+                Ok(()) => std::thread::sleep(REFRESH.saturating_sub(refreshed.elapsed())),
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            refreshed = Instant::now();
+            match self.list() {
+                Ok(containers) => *latest.lock().unwrap() = Arc::new(containers),
+                Err(err) => crate::diagnostic!("kubernetes: {err}"),
+            }
+        }
+    }
+
+    /// Lists the pods of the node and maps their containers to their tags.
+    fn list(&self) -> Result<Containers, String> {
+        let mut request = self
+            .agent
+            .get(&self.url)
+            .query("fieldSelector", &format!("spec.nodeName={}", self.node));
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {token}"));
+        }
+        let pods: PodList = request
+            .call()
+            .map_err(|err| err.to_string())?
+            .into_json()
+            .map_err(|err| err.to_string())?;
+        let mut containers = Containers::new();
+        for pod in pods.items {
+            let metadata = pod.metadata;
+            let mut tags = vec![
+                ("pod".to_string(), metadata.name),
+                ("namespace".to_string(), metadata.namespace),
+            ];
+            tags.extend(
+                (metadata.labels.into_iter()).map(|(key, value)| (format!("label.{key}"), value)),
+            );
+            let tags = Arc::new(tags);
+            let status = pod.status;
+            let statuses = [
+                status.container_statuses,
+                status.init_container_statuses,
+                status.ephemeral_container_statuses,
+            ];
+            for id in statuses
+                .into_iter()
+                .flatten()
+                .filter_map(|s| s.container_id)
+            {
+                let id = id.split_once("://").map_or(&id[..], |(_, id)| id);
+                containers.insert(id.into(), tags.clone());
+            }
+        }
+        Ok(containers)
+    }
+}
+
+impl<C: Collector> Collector for Kubernetes<C> {
+    fn out(&mut self, data: &[u8]) {
+        let extras = InetDiagMsgExtra::parse(data);
+        let Some((_, container)) = extras
+            .tags
+            .and_then(|tags| tags.iter().find(|&(key, _)| key == "container"))
+        else {
+            return self.inner.out(data);
+        };
+        match self.containers.get(container) {
+            Some(tags) => {
+                let tags: Vec<_> = tags.iter().map(|(k, v)| (&k[..], &v[..])).collect();
+                add_tags(data, &tags, &mut self.buf);
+                self.inner.out(&self.buf);
+            }
+            None => {
+                // A request is pending already if the channel is full.
+                let _ = self.unknown.try_send(());
+                self.inner.out(data)
+            }
+        }
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.containers = self.latest.lock().unwrap().clone();
+        self.inner.start(time);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.inner.end(duration);
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.inner.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.inner.unscheduled();
+    }
+
+    fn source(&mut self, source: &str) {
+        self.inner.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.inner.metadata(metadata);
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.inner.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.inner.offset(offset);
+    }
+}
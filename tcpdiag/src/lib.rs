@@ -1,19 +1,68 @@
-use clap::Parser;
+use clap::{ArgGroup, Parser};
 
+pub mod anonymize;
+pub mod asn;
 pub mod binary;
+pub mod changes;
+pub mod check;
+pub mod compare;
+pub mod container;
 pub mod csv;
+pub mod daemon;
 pub mod data;
+pub mod diff;
+pub mod filter;
+pub mod flush;
+#[cfg(feature = "geoip")]
+pub mod geoip;
+pub mod group;
+pub mod histogram;
+pub mod http;
+pub mod human;
 pub mod integer;
 pub mod json;
+#[cfg(feature = "kubernetes")]
+pub mod kubernetes;
+pub mod loki;
+pub mod merge;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod notify;
+pub mod pipeline;
+pub mod plugin;
+pub mod redis;
+pub mod resample;
+pub mod sandbox;
+#[cfg(feature = "script")]
+pub mod script;
+pub mod serve;
+pub mod signal;
+pub mod smooth;
+pub mod socket;
+pub mod split;
+pub mod stats;
+pub mod summary;
+pub mod table;
 pub mod timespec;
+pub mod timestamp;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "io-uring")]
+pub mod uring;
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod webhook;
 
-use netlink_sys::{protocols::NETLINK_SOCK_DIAG, Socket, SocketAddr};
+use netlink_sys::{protocols::NETLINK_SOCK_DIAG, Socket};
+use socket::DiagSocket;
 use std::{
+    io::{self, BufRead},
     num::NonZeroU32,
     time::{Duration, Instant, SystemTime},
 };
-use timespec::Timespec;
-use zerocopy::IntoBytes;
+use timespec::{Clock, Timespec};
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout};
 
 use data::*;
 use integer::U16BE;
@@ -22,6 +71,72 @@ pub trait Collector {
     fn out(&mut self, data: &[u8]);
     fn start(&mut self, time: SystemTime);
     fn end(&mut self, duration: Duration);
+    /// Records the effective sampling period, called before `end` in adaptive
+    /// mode and with `--burst`.
+    fn period(&mut self, _period: Duration) {}
+    /// Marks the sample as taken on demand (outside of the period), called
+    /// before `end`.
+    fn unscheduled(&mut self) {}
+    /// Names the origin of the following samples, called before `start` when
+    /// captures are merged.
+    fn source(&mut self, _source: &str) {}
+    /// Describes the capture of the following samples (see
+    /// `capture_metadata`), called once before its first `source` or `start`.
+    fn metadata(&mut self, _metadata: &[(String, String)]) {}
+    /// Summarizes the dump of the sample, called before `end` when capturing.
+    fn stats(&mut self, _stats: &SampleStats) {}
+    /// Time since the start of the sample at which the following sockets
+    /// were received, called before their `out` when capturing.
+    fn offset(&mut self, _offset: Duration) {}
+}
+
+/// Summary of the dump of a sample, to detect truncated or unexpectedly
+/// growing dumps. The sockets are counted as returned by the kernel, before
+/// the filters of tcpdiag (like `--watch` and `--exclude-loopback`).
+#[derive(
+    KnownLayout,
+    Immutable,
+    FromBytes,
+    IntoBytes,
+    Default,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[repr(C)]
+pub struct SampleStats {
+    /// Bytes of the netlink datagrams received
+    pub bytes: u64,
+    pub sockets: u32,
+    pub inet4: u32,
+    pub inet6: u32,
+    /// Netlink messages received (with `--self-stats`)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub messages: u32,
+    /// CLOCK_BOOTTIME at the start of the sample in microseconds, which isn't
+    /// stepped like the wall time of the sample (0 in captures of earlier
+    /// versions)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub boottime: u64,
+    /// CPU time of tcpdiag (all threads) since the previous sample in
+    /// microseconds (with `--self-stats`)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub cpu_time: u64,
+    /// Netlink messages that could not be used: error replies and sockets
+    /// too short to parse
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub errors: u32,
+    /// Samples dropped by `--queue` since the previous written sample
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub dropped: u32,
+}
+
+fn is_zero<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
 }
 
 pub trait Output<T>: Collector {
@@ -43,8 +158,59 @@ macro_rules! impl_output {
 }
 pub(crate) use impl_output;
 
+static QUIET: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// Silences diagnostics on stderr (failed assertions, warnings, and malformed
+/// input lines). Errors still abort with a message.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, std::sync::atomic::Ordering::Relaxed);
+}
+
+pub(crate) fn quiet() -> bool {
+    QUIET.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// Like `eprintln!`, unless diagnostics are silenced with `set_quiet`.
+macro_rules! diagnostic {
+    ($($arg:tt)*) => {
+        if !crate::quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+pub(crate) use diagnostic;
+
+/// Transport protocol of dumped sockets
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Protocol {
+    Tcp,
+    Udp,
+    /// MPTCP connections, without tcp_info (their subflows are TCP sockets)
+    Mptcp,
+}
+
+impl Protocol {
+    pub fn name(self) -> &'static str {
+        match self {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+            Protocol::Mptcp => "mptcp",
+        }
+    }
+
+    pub fn number(self) -> u32 {
+        let number = match self {
+            Protocol::Tcp => libc::IPPROTO_TCP,
+            Protocol::Udp => libc::IPPROTO_UDP,
+            Protocol::Mptcp => libc::IPPROTO_MPTCP,
+        };
+        number as u32
+    }
+}
+
 #[derive(Parser, Debug, Clone)]
 #[group(id = "netlink")]
+#[command(group = ArgGroup::new("burst_trigger").args(["burst_retrans", "burst_syn_sent"]).multiple(true))]
 pub struct NetlinkArgs {
     #[arg(conflicts_with = "inet6", short = '4')]
     pub inet4: bool,
@@ -56,53 +222,306 @@ pub struct NetlinkArgs {
     pub dport: u16,
     #[arg(short = 'a', long)]
     pub all_states: bool,
+    /// Dump listening sockets, reporting their accept queue
+    #[arg(conflicts_with = "all_states", short = 'l', long)]
+    pub listening: bool,
     #[arg(short = 'x', long)]
     pub all_extensions: bool,
+    /// Dump the sockets of these protocols (comma-separated), each record
+    /// tagged with its protocol unless only tcp is dumped
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "tcp")]
+    pub protocol: Vec<Protocol>,
     #[arg(short = 'p')]
     pub period: Option<f64>,
     #[arg(requires = "period", short = 'c')]
     pub count: Option<std::num::NonZeroU32>,
+    /// Stop sampling after this wall time (e.g. 10m)
+    #[arg(long, value_parser = humantime::parse_duration)]
+    pub duration: Option<Duration>,
+    /// Stretch the period while a sample contains more sockets than this
+    #[arg(requires = "period", long)]
+    pub adapt_sockets: Option<u32>,
+    /// Stretch the period while a dump takes longer than this (e.g. 50ms)
+    #[arg(requires = "period", long, value_parser = humantime::parse_duration)]
+    pub adapt_duration: Option<Duration>,
+    /// Upper bound for the adaptive period in seconds [default: 16 * period]
+    #[arg(requires = "period", long)]
+    pub max_period: Option<f64>,
+    /// Delay each sample by a random part of this share of the period (e.g.
+    /// 10%), so that agents started at the same time spread their samples
+    #[arg(requires = "period", long, value_name = "PERCENT", value_parser = parse_percent)]
+    pub jitter: Option<f64>,
+    /// Sample with this period (e.g. 100ms) for `--burst-window` after a
+    /// trigger (`--burst-retrans`, `--burst-syn-sent`) fired
+    #[arg(requires_all = ["period", "burst_trigger"], conflicts_with_all = ["adapt_sockets", "adapt_duration"], long, value_parser = humantime::parse_duration)]
+    pub burst: Option<Duration>,
+    /// How long to keep the burst period after the last trigger
+    #[arg(requires = "burst", long, default_value = "10s", value_parser = humantime::parse_duration)]
+    pub burst_window: Duration,
+    /// Trigger a burst on more than this many retransmissions per second
+    /// (summed over the reported sockets)
+    #[arg(requires = "burst", long, value_name = "N")]
+    pub burst_retrans: Option<u64>,
+    /// Trigger a burst on more than this many reported sockets in SYN-SENT
+    #[arg(requires_all = ["burst", "all_states"], long, value_name = "N")]
+    pub burst_syn_sent: Option<u32>,
+    /// Drop connections with a loopback address (127.0.0.0/8, ::1)
+    #[arg(long)]
+    pub exclude_loopback: bool,
+    /// Drop connections whose source and destination address are equal
+    #[arg(long)]
+    pub exclude_self: bool,
+    /// Only report the connection with this 4-tuple (src:port-dst:port)
+    #[arg(long, conflicts_with_all = ["sport", "dport", "cookie"])]
+    pub watch: Option<filter::Tuple>,
+    /// Only report the connection with this socket cookie
+    #[arg(long)]
+    pub cookie: Option<u64>,
+    /// Receive buffer size of the netlink sockets in bytes (beyond
+    /// net.core.rmem_max if permitted)
+    #[arg(long, value_name = "BYTES")]
+    pub rcvbuf: Option<u32>,
+    /// Clock that schedules the samples with -p
+    #[arg(long, value_enum, default_value_t)]
+    pub clock: Clock,
+    /// Restrict tcpdiag to the system calls of the capture by a seccomp
+    /// filter, once its sockets and files are open
+    #[arg(long)]
+    pub sandbox: bool,
+    /// Record the costs of tcpdiag with each sample: the netlink messages
+    /// and the CPU time since the previous sample
+    #[arg(long)]
+    pub self_stats: bool,
+}
+
+/// Parses a percentage like `10%` (from 0% to 100%) as fraction.
+fn parse_percent(s: &str) -> Result<f64, String> {
+    let percent: f64 = s
+        .strip_suffix('%')
+        .ok_or("expected a percentage like 10%")?
+        .parse()
+        .map_err(|err| format!("{err}"))?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err("expected 0% to 100%".into());
+    }
+    Ok(percent / 100.0)
+}
+
+impl NetlinkArgs {
+    fn accept(&self, msg: &InetDiagMsg) -> bool {
+        if self
+            .cookie
+            .is_some_and(|cookie| msg.id.cookie.get() != cookie)
+            || self.watch.is_some_and(|watch| !watch.matches(msg))
+        {
+            return false;
+        }
+        if !self.exclude_loopback && !self.exclude_self {
+            return true;
+        }
+        let src = msg.id.src.get(msg.family).to_canonical();
+        let dst = msg.id.dst.get(msg.family).to_canonical();
+        !(self.exclude_loopback && (src.is_loopback() || dst.is_loopback())
+            || self.exclude_self && src == dst && !dst.is_unspecified())
+    }
+
+    /// The options that select the reported sockets, like `-4 --dport 443`.
+    pub fn filters(&self) -> String {
+        let mut filters = Vec::new();
+        let mut flag = |set: bool, name: &str| {
+            if set {
+                filters.push(name.to_string());
+            }
+        };
+        flag(self.inet4, "-4");
+        flag(self.inet6, "-6");
+        flag(self.all_states, "--all-states");
+        flag(self.listening, "--listening");
+        flag(self.exclude_loopback, "--exclude-loopback");
+        flag(self.exclude_self, "--exclude-self");
+        if self.sport != 0 {
+            filters.push(format!("--sport {}", self.sport));
+        }
+        if self.dport != 0 {
+            filters.push(format!("--dport {}", self.dport));
+        }
+        if let Some(watch) = self.watch {
+            filters.push(format!("--watch {watch}"));
+        }
+        if let Some(cookie) = self.cookie {
+            filters.push(format!("--cookie {cookie}"));
+        }
+        if self.protocol != [Protocol::Tcp] {
+            let protocols: Vec<_> = self.protocol.iter().map(|p| p.name()).collect();
+            filters.push(format!("--protocol {}", protocols.join(",")));
+        }
+        filters.join(" ")
+    }
+
+    /// Counts the trigger metrics of `--burst` for a reported socket.
+    fn count_activity(&self, data: &[u8], activity: &mut Activity) {
+        if self.burst_retrans.is_some() {
+            let extras = InetDiagMsgExtra::parse(data);
+            if let Some(info) = extras.tcp_info {
+                activity.retrans += u64::from(info.total_retrans);
+            }
+        }
+        let (msg, _) = InetDiagMsg::ref_from_prefix(data).unwrap();
+        if msg.state == TCP_SYN_SENT {
+            activity.syn_sent += 1;
+        }
+    }
+
+    fn is_adaptive(&self) -> bool {
+        self.adapt_sockets.is_some() || self.adapt_duration.is_some()
+    }
+
+    /// Doubles the period if a threshold was exceeded and halves it (down to
+    /// the configured period) once the load is below half of the thresholds.
+    fn adapt_period(&self, period: Duration, sockets: u32, dump: Duration) -> Duration {
+        let base = Duration::from_secs_f64(self.period.unwrap());
+        let max = self
+            .max_period
+            .map(Duration::from_secs_f64)
+            .unwrap_or(base * 16);
+        let exceeded = self.adapt_sockets.is_some_and(|t| sockets > t)
+            || self.adapt_duration.is_some_and(|t| dump > t);
+        let relaxed = self.adapt_sockets.is_none_or(|t| sockets <= t / 2)
+            && self.adapt_duration.is_none_or(|t| dump <= t / 2);
+        if exceeded {
+            (period * 2).min(max)
+        } else if relaxed {
+            (period / 2).max(base)
+        } else {
+            period
+        }
+    }
+}
+
+/// Trigger metrics of the reported sockets of a sample, for `--burst`
+#[derive(Default)]
+pub(crate) struct Activity {
+    retrans: u64,
+    syn_sent: u32,
 }
 
-fn send_request(sock: &Socket, args: &NetlinkArgs, family: u8) {
-    let msg = Encap {
+/// State of `--burst`: the activity of the previous scheduled sample and
+/// until when the burst period applies
+struct Burst {
+    previous: Option<(Instant, u64)>,
+    until: Option<Instant>,
+}
+
+impl Burst {
+    /// Returns the period after a sample with `activity`, extending the
+    /// burst window if a trigger fired.
+    fn period(&mut self, args: &NetlinkArgs, now: Instant, activity: &Activity) -> Duration {
+        let previous = self.previous.replace((now, activity.retrans));
+        let retrans = previous.and_then(|(time, retrans)| {
+            let elapsed = now.duration_since(time).as_secs_f64();
+            let increase = activity.retrans.saturating_sub(retrans) as f64;
+            (elapsed > 0.0).then(|| increase / elapsed)
+        });
+        let triggered = args
+            .burst_retrans
+            .is_some_and(|t| retrans.is_some_and(|r| r > t as f64))
+            || args.burst_syn_sent.is_some_and(|t| activity.syn_sent > t);
+        if triggered {
+            if self.until.is_none_or(|until| now >= until) {
+                diagnostic!("burst sampling for {:?}", args.burst_window);
+            }
+            self.until = Some(now + args.burst_window);
+        }
+        match self.until {
+            Some(until) if now < until => args.burst.unwrap(),
+            _ => Duration::from_secs_f64(args.period.unwrap()),
+        }
+    }
+}
+
+/// Sets the receive buffer size with SO_RCVBUFFORCE, which requires
+/// CAP_NET_ADMIN, falling back to SO_RCVBUF (limited by net.core.rmem_max).
+fn set_rcvbuf(sock: &Socket, size: u32) {
+    let size = libc::c_int::try_from(size).unwrap_or(libc::c_int::MAX);
+    let ret = unsafe {
+        libc::setsockopt(
+            std::os::fd::AsRawFd::as_raw_fd(sock),
+            libc::SOL_SOCKET,
+            libc::SO_RCVBUFFORCE,
+            (&size as *const libc::c_int).cast(),
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret != 0 {
+        sock.set_rx_buf_sz(size).unwrap();
+    }
+}
+
+/// Dump request of a sample. Protocols beyond 255 (MPTCP) are requested with
+/// an attribute, and the low byte of their number in the request (as by ss).
+pub(crate) fn request(args: &NetlinkArgs, (family, protocol): Dump, seq: u32) -> Vec<u8> {
+    let number = protocol.number();
+    let extended = u8::try_from(number).is_err();
+    let attribute_len = if extended { 8 } else { 0 };
+    let ext = if args.all_extensions {
+        u8::MAX
+    } else {
+        const {
+            data::request_as(data::INET_DIAG_INFO)
+                | data::request_as(data::INET_DIAG_CONG)
+                | data::request_as(data::INET_DIAG_BBRINFO)
+        }
+    };
+    let encap = Encap {
         hdr: nlmsghdr {
-            nlmsg_len: std::mem::size_of::<Encap>().try_into().unwrap(),
+            nlmsg_len: (std::mem::size_of::<Encap>() + attribute_len)
+                .try_into()
+                .unwrap(),
             nlmsg_flags: NLM_F_DUMP | NLM_F_REQUEST,
             nlmsg_type: SOCK_DIAG_BY_FAMILY,
+            nlmsg_seq: seq,
             ..Default::default()
         },
         data: InetDiagReqV2 {
             family,
-            protocol: libc::IPPROTO_TCP.try_into().unwrap(),
-            ext: if args.all_extensions {
-                u8::MAX
-            } else {
-                const {
-                    data::request_as(data::INET_DIAG_INFO)
-                        | data::request_as(data::INET_DIAG_CONG)
-                        | data::request_as(data::INET_DIAG_BBRINFO)
-                }
+            protocol: number as u8,
+            // The info of MPTCP is no tcp_info.
+            ext: match protocol {
+                Protocol::Mptcp => ext & !data::request_as(data::INET_DIAG_INFO),
+                _ => ext,
             },
             pad: 0,
             states: if args.all_states {
                 u32::MAX
+            } else if args.listening {
+                data::TCPF_LISTEN
             } else {
                 data::TCPF_ESTABLISHED
             },
             id: InetDiagSockid {
-                sport: U16BE::new(args.sport),
-                dport: U16BE::new(args.dport),
+                sport: U16BE::new(args.watch.map_or(args.sport, |w| w.src.port())),
+                dport: U16BE::new(args.watch.map_or(args.dport, |w| w.dst.port())),
                 ..Default::default() // kernel ignores src, dst, and ifindex
             },
         },
     };
-    sock.send_to(msg.as_bytes(), &SocketAddr::new(0, 0), 0)
-        .unwrap();
+    let mut request = encap.as_bytes().to_vec();
+    if extended {
+        request.extend(
+            nlattr {
+                nla_len: 8,
+                nla_type: data::INET_DIAG_REQ_PROTOCOL,
+            }
+            .as_bytes(),
+        );
+        request.extend(number.to_ne_bytes());
+    }
+    request
 }
+
 use std::ops::DerefMut;
 
-impl Collector for Box<dyn Collector> {
+impl Collector for Box<dyn Collector + '_> {
     fn out(&mut self, data: &[u8]) {
         self.deref_mut().out(data)
     }
@@ -114,43 +533,478 @@ impl Collector for Box<dyn Collector> {
     fn end(&mut self, duration: Duration) {
         self.deref_mut().end(duration)
     }
+
+    fn period(&mut self, period: Duration) {
+        self.deref_mut().period(period)
+    }
+
+    fn unscheduled(&mut self) {
+        self.deref_mut().unscheduled()
+    }
+
+    fn source(&mut self, source: &str) {
+        self.deref_mut().source(source)
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.deref_mut().metadata(metadata)
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.deref_mut().stats(stats)
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.deref_mut().offset(offset)
+    }
 }
 
-pub fn read_netlink<C: Collector>(args: &NetlinkArgs, mut writer: C) {
-    let s = Socket::new(NETLINK_SOCK_DIAG).unwrap();
+impl<C: Collector + ?Sized> Collector for &mut C {
+    fn out(&mut self, data: &[u8]) {
+        (**self).out(data)
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        (**self).start(time)
+    }
+
+    fn end(&mut self, duration: Duration) {
+        (**self).end(duration)
+    }
+
+    fn period(&mut self, period: Duration) {
+        (**self).period(period)
+    }
+
+    fn unscheduled(&mut self) {
+        (**self).unscheduled()
+    }
 
+    fn source(&mut self, source: &str) {
+        (**self).source(source)
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        (**self).metadata(metadata)
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        (**self).stats(stats)
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        (**self).offset(offset)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureFormat {
+    Binary,
+    Json,
+    Csv,
+}
+
+/// Detects the format of a capture from its first bytes (`None` if empty).
+pub fn detect_format(peek: &[u8]) -> Result<Option<CaptureFormat>, &'static str> {
+    // Binary captures start with the start time (type 1), the source (type 4),
+    // the metadata (type 6), or the checksum of a sample (type 9)
+    const A: u8 = 1u16.to_ne_bytes()[0];
+    const B: u8 = 1u16.to_ne_bytes()[1];
+    const C: u8 = 4u16.to_ne_bytes()[0];
+    const D: u8 = 4u16.to_ne_bytes()[1];
+    const E: u8 = 6u16.to_ne_bytes()[0];
+    const F: u8 = 6u16.to_ne_bytes()[1];
+    const G: u8 = 9u16.to_ne_bytes()[0];
+    const H: u8 = 9u16.to_ne_bytes()[1];
+    match *peek {
+        [_, _, A, B, ..] | [_, _, C, D, ..] | [_, _, E, F, ..] | [_, _, G, H, ..] => {
+            Ok(Some(CaptureFormat::Binary))
+        }
+        [_, _, B, A, ..] | [_, _, D, C, ..] | [_, _, F, E, ..] | [_, _, H, G, ..] => {
+            Err("foreign endianness")
+        }
+        [b'{', b'"', ..] => Ok(Some(CaptureFormat::Json)),
+        [b'#' | b'a'..=b'z', ..] => Ok(Some(CaptureFormat::Csv)),
+        [] => Ok(None),
+        _ => Err("unrecognized format"),
+    }
+}
+
+/// Reads an existing capture, detecting its format from the first bytes. The
+/// reader can be any `BufRead`, like a `BufReader` of a file or a `&[u8]` of a
+/// capture in memory.
+pub fn read_capture<R: BufRead, C: Collector>(reader: R, writer: C) {
+    try_read_capture(reader, writer).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Like `read_capture`, but returns read errors, an unrecognized format, and
+/// malformed binary records (as `InvalidData`), e.g. for bindings that must
+/// not panic.
+pub fn try_read_capture<R: BufRead, C: Collector>(mut reader: R, writer: C) -> io::Result<()> {
+    let invalid = |err| io::Error::new(io::ErrorKind::InvalidData, err);
+    match detect_format(reader.fill_buf()?).map_err(invalid)? {
+        Some(CaptureFormat::Binary) => binary::try_read_binary(reader, writer),
+        Some(CaptureFormat::Json) => json::try_read_json(reader, writer),
+        Some(CaptureFormat::Csv) => csv::try_read_csv_with_header(reader, None, writer),
+        None => Ok(()),
+    }
+}
+
+/// Decompresses gzip and zstd input, detected by its magic bytes, so that
+/// compressed captures (like `capture.bin.zst`) can be read like others.
+/// Other input is returned unchanged.
+pub fn decompress<'a, R: BufRead + Send + 'a>(reader: R) -> Box<dyn BufRead + Send + 'a> {
+    try_decompress(reader).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Like `decompress`, but returns read errors and compressed input without
+/// the compression feature (as `Unsupported`).
+pub fn try_decompress<'a, R: BufRead + Send + 'a>(
+    mut reader: R,
+) -> io::Result<Box<dyn BufRead + Send + 'a>> {
+    let compression = match reader.fill_buf()? {
+        [0x1f, 0x8b, ..] => "gzip",
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => "zstd",
+        _ => return Ok(Box::new(reader)),
+    };
+    #[cfg(feature = "compression")]
+    match compression {
+        "gzip" => Ok(Box::new(io::BufReader::new(
+            flate2::bufread::MultiGzDecoder::new(reader),
+        ))),
+        _ => Ok(Box::new(io::BufReader::new(zstd::Decoder::with_buffer(
+            reader,
+        )?))),
+    }
+    #[cfg(not(feature = "compression"))]
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{compression} input requires the compression feature"),
+    ))
+}
+
+/// Address family and protocol of a dump
+pub type Dump = (u8, Protocol);
+
+/// Dumps of each sample with `args`: each protocol in each address family,
+/// with a netlink socket each
+pub fn dumps(args: &NetlinkArgs) -> Vec<Dump> {
+    let families = address_families(args);
+    args.protocol
+        .iter()
+        .flat_map(|&protocol| families.iter().map(move |&family| (family, protocol)))
+        .collect()
+}
+
+/// Address families dumped with `args`
+pub fn address_families(args: &NetlinkArgs) -> &'static [u8] {
+    const INET: u8 = libc::AF_INET as u8;
+    const INET6: u8 = libc::AF_INET6 as u8;
+    match () {
+        _ if args.inet4 => &[INET],
+        _ if args.inet6 => &[INET6],
+        _ => &[INET, INET6],
+    }
+}
+
+pub(crate) fn diag_socket(args: &NetlinkArgs) -> io::Result<Socket> {
+    let s = Socket::new(NETLINK_SOCK_DIAG)?;
+    if let Some(size) = args.rcvbuf {
+        set_rcvbuf(&s, size);
+    }
+    Ok(s)
+}
+
+/// Fails if a datagram of `len` bytes did not fit into the buffer.
+pub(crate) fn check_truncation(len: usize, buf_len: usize) -> io::Result<()> {
+    if len > buf_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("netlink datagram truncated ({len} bytes, buffer has {buf_len})"),
+        ));
+    }
+    Ok(())
+}
+
+/// Passes the sockets of a datagram of a dump reply to the writer, with the
+/// `offset` since the start of the sample at which it was received, counting
+/// the datagram and its sockets in `stats` and the reported sockets in
+/// `activity`. The sockets are tagged with the protocol of the dump unless
+/// only TCP is dumped. Returns whether the dump is complete.
+pub(crate) fn handle_datagram<C: Collector>(
+    buf: &[u8],
+    (seq, protocol): (u32, Protocol),
+    args: &NetlinkArgs,
+    writer: &mut C,
+    stats: &mut SampleStats,
+    activity: &mut Activity,
+    offset: Duration,
+) -> bool {
+    stats.bytes += buf.len() as u64;
+    // Passed with the first socket, so datagrams without sockets cost nothing.
+    let mut offset = Some(offset);
+    let tag = (args.protocol != [Protocol::Tcp]).then(|| [("protocol", protocol.name())]);
+    let mut tagged = Vec::new();
+    // Skip replies to requests of previous samples
+    for nlmsg in NlmsgIter::new(buf).filter(|m| m.hdr.nlmsg_seq == seq) {
+        if args.self_stats {
+            stats.messages += 1;
+        }
+        if nlmsg.hdr.nlmsg_type == NLMSG_ERROR {
+            // An error code of 0 is an acknowledgement.
+            if nlmsg.data.get(..4).is_none_or(|error| error != [0; 4]) {
+                stats.errors += 1;
+            }
+            return true;
+        }
+        if nlmsg.hdr.nlmsg_type == NLMSG_DONE {
+            return true;
+        }
+        if nlmsg.hdr.nlmsg_type == SOCK_DIAG_BY_FAMILY {
+            let Ok((msg, _)) = InetDiagMsg::ref_from_prefix(&nlmsg.data) else {
+                stats.errors += 1;
+                continue;
+            };
+            stats.sockets += 1;
+            match i32::from(msg.family) {
+                libc::AF_INET => stats.inet4 += 1,
+                libc::AF_INET6 => stats.inet6 += 1,
+                _ => (),
+            }
+            if args.accept(msg) {
+                if let Some(offset) = offset.take() {
+                    writer.offset(offset);
+                }
+                if args.burst.is_some() {
+                    args.count_activity(&nlmsg.data, activity);
+                }
+                match &tag {
+                    Some(tag) => {
+                        data::add_tags(&nlmsg.data, tag, &mut tagged);
+                        writer.out(&tagged);
+                    }
+                    None => writer.out(&nlmsg.data),
+                }
+            }
+        }
+    }
+    false
+}
+
+pub fn read_netlink<C: Collector>(args: &NetlinkArgs, writer: C) {
+    try_read_netlink(args, writer).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Like `read_netlink`, but returns the errors of the netlink sockets, e.g.
+/// for bindings that must not panic.
+pub fn try_read_netlink<C: Collector>(args: &NetlinkArgs, writer: C) -> io::Result<()> {
+    // A netlink socket runs one dump at a time, so each dump gets its own
+    // socket to request all dumps up front.
+    let diag_sockets = dumps(args)
+        .iter()
+        .map(|_| diag_socket(args))
+        .collect::<io::Result<Vec<Socket>>>()
+        .map_err(|err| io::Error::new(err.kind(), format!("netlink socket: {err}")))?;
+    try_read_diag_sockets(args, &diag_sockets, writer)
+}
+
+/// Like `read_netlink`, with the given sockets (one per dump of `dumps`),
+/// e.g. `socket::Replay` for tests without root. Each sample sends one
+/// request to every socket, so the sockets must not be shared between dumps.
+pub fn read_diag_sockets<S: DiagSocket, C: Collector>(
+    args: &NetlinkArgs,
+    diag_sockets: &[S],
+    writer: C,
+) {
+    try_read_diag_sockets(args, diag_sockets, writer).unwrap_or_else(|err| panic!("{err}"))
+}
+
+/// Like `read_diag_sockets`, but returns the errors of the sockets.
+pub fn try_read_diag_sockets<S: DiagSocket, C: Collector>(
+    args: &NetlinkArgs,
+    diag_sockets: &[S],
+    writer: C,
+) -> io::Result<()> {
+    let dumps = dumps(args);
+    assert_eq!(diag_sockets.len(), dumps.len());
     let mut buf = Vec::with_capacity(1 << 18);
+    sample_loop(args, writer, |seq, start, writer, stats, activity| {
+        for (s, &dump) in diag_sockets.iter().zip(&dumps) {
+            s.send_request(&request(args, dump, seq))
+                .map_err(|err| io::Error::new(err.kind(), format!("send: {err}")))?;
+        }
+        for (s, &(_, protocol)) in diag_sockets.iter().zip(&dumps) {
+            loop {
+                let len = match s.recv_datagram(&mut buf) {
+                    Ok(len) => len,
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(io::Error::new(err.kind(), format!("recv: {err}"))),
+                };
+                check_truncation(len, buf.len())?;
+                if handle_datagram(
+                    &buf,
+                    (seq, protocol),
+                    args,
+                    writer,
+                    stats,
+                    activity,
+                    start.elapsed(),
+                ) {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Describes a capture, so that archived captures remain interpretable: the
+/// host, its kernel, the boot (to relate times of the kernel), the tcpdiag
+/// version, the filters, and when the capture started.
+pub fn capture_metadata(args: &NetlinkArgs) -> Vec<(String, String)> {
+    let read = |path| {
+        std::fs::read_to_string(path)
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default()
+    };
+    [
+        ("hostname", read("/proc/sys/kernel/hostname")),
+        ("kernel", read("/proc/sys/kernel/osrelease")),
+        ("boot_id", read("/proc/sys/kernel/random/boot_id")),
+        ("version", env!("CARGO_PKG_VERSION").to_string()),
+        ("filters", args.filters()),
+        (
+            "start",
+            timestamp::Timestamp::new(SystemTime::now())
+                .display(&timestamp::TimeFormat::Rfc3339(jiff::tz::TimeZone::UTC))
+                .to_string(),
+        ),
+    ]
+    .into_iter()
+    .map(|(key, value)| (key.to_string(), value))
+    .collect()
+}
+
+/// Random delays of the samples for `--jitter`, with a key from /dev/urandom.
+struct Jitter {
+    key: [u8; 16],
+    share: f64,
+    index: u64,
+}
+
+impl Jitter {
+    fn new(share: f64) -> Self {
+        let mut key = [0; 16];
+        let mut urandom = std::fs::File::open("/dev/urandom").unwrap();
+        std::io::Read::read_exact(&mut urandom, &mut key).unwrap();
+        Self {
+            key,
+            share,
+            index: 0,
+        }
+    }
+
+    /// Returns a delay below `share` of the period.
+    fn delay(&mut self, period: Duration) -> Duration {
+        let mut hasher = siphasher::sip::SipHasher24::new_with_key(&self.key);
+        std::hash::Hasher::write_u64(&mut hasher, self.index);
+        self.index += 1;
+        let random = std::hash::Hasher::finish(&hasher) as f64 / 2f64.powi(64);
+        period.mul_f64(self.share * random)
+    }
+}
+
+/// Calls `dump` for each sample, which passes the sockets of the dumps with
+/// the given sequence number to the writer (with their offsets from the
+/// given start) and counts them in the stats and activity. An error of
+/// `dump` ends the capture.
+pub(crate) fn sample_loop<C: Collector>(
+    args: &NetlinkArgs,
+    mut writer: C,
+    mut dump: impl FnMut(u32, Instant, &mut C, &mut SampleStats, &mut Activity) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut seq = 0u32;
     let mut count = args.count.map(NonZeroU32::get).unwrap_or(0);
+    writer.metadata(&capture_metadata(args));
+    let mut cpu_time = timespec::cpu_time();
+    let mut burst = args.burst.map(|_| Burst {
+        previous: None,
+        until: None,
+    });
 
-    let mut period_start = Timespec::now();
-    loop {
+    // Takes a sample. Extra samples (on SIGUSR1) neither adapt the period
+    // nor trigger bursts.
+    let mut sample = |writer: &mut C, period: Option<&mut Duration>, unscheduled: bool| {
         let start = Instant::now();
+        let mut stats = SampleStats {
+            boottime: Timespec::now(Clock::Boottime).as_duration().as_micros() as u64,
+            ..SampleStats::default()
+        };
         let time = SystemTime::now();
         writer.start(time);
-        let address_families: &[u8] = match () {
-            _ if args.inet4 => &[libc::AF_INET.try_into().unwrap()],
-            _ if args.inet6 => &[libc::AF_INET6.try_into().unwrap()],
-            _ => &[
-                libc::AF_INET.try_into().unwrap(),
-                libc::AF_INET6.try_into().unwrap(),
-            ],
-        };
-        for &address_family in address_families {
-            send_request(&s, args, address_family);
-            'a: loop {
-                buf.clear();
-                s.recv_from(&mut buf, 0).unwrap();
-                for nlmsg in NlmsgIter::new(&buf[..]) {
-                    if nlmsg.hdr.nlmsg_type == NLMSG_DONE || nlmsg.hdr.nlmsg_type == NLMSG_ERROR {
-                        break 'a;
-                    }
-                    if nlmsg.hdr.nlmsg_type == SOCK_DIAG_BY_FAMILY {
-                        writer.out(&nlmsg.data);
-                    }
-                }
+        seq = seq.wrapping_add(1);
+        let mut activity = Activity::default();
+        dump(seq, start, writer, &mut stats, &mut activity)?;
+        let dump = start.elapsed();
+        if let Some(p) = period {
+            if args.is_adaptive() {
+                *p = args.adapt_period(*p, stats.sockets, dump);
+                writer.period(*p);
+            } else if let Some(burst) = &mut burst {
+                *p = burst.period(args, start, &activity);
+                writer.period(*p);
             }
         }
-        writer.end(start.elapsed());
+        if unscheduled {
+            writer.unscheduled();
+        }
+        if args.self_stats {
+            let now = timespec::cpu_time();
+            stats.cpu_time = (now - cpu_time).as_micros() as u64;
+            cpu_time = now;
+        }
+        writer.stats(&stats);
+        writer.end(dump);
+        io::Result::Ok(())
+    };
+
+    let mut notifier = notify::Notifier::from_env();
+    if let Some((watchdog, period)) = notifier
+        .as_ref()
+        .and_then(|n| n.watchdog())
+        .zip(args.period)
+    {
+        if Duration::from_secs_f64(period) > watchdog / 2 {
+            diagnostic!("the period exceeds half of the watchdog interval ({watchdog:?})");
+        }
+    }
+
+    let mut period_start = Timespec::now(args.clock);
+    let mut offset = Duration::ZERO;
+    let mut period = args.period.map(Duration::from_secs_f64);
+    // Without a period, --duration samples back to back until the deadline.
+    let deadline = (args.duration)
+        .filter(|_| period.is_none())
+        .map(|duration| Instant::now() + duration);
+    if period.is_some() {
+        signal::handle_sigusr1();
+    }
+    // The first sample is delayed as well, as agents are often started at
+    // the same time. On a stop request, it is taken right away.
+    let mut jitter = args.jitter.map(Jitter::new);
+    if args.sandbox {
+        sandbox::sandbox();
+    }
+    if let Some((jitter, period)) = jitter.as_mut().zip(period) {
+        let mut wake = period_start;
+        wake += jitter.delay(period);
+        wake.sleep_until_or(args.clock, signal::stop_requested);
+    }
+    'capture: loop {
+        sample(&mut writer, period.as_mut(), false)?;
+        if let Some(notifier) = &mut notifier {
+            notifier.sampled();
+        }
         if count != 0 {
             count -= 1;
             if count == 0 {
@@ -158,12 +1012,31 @@ pub fn read_netlink<C: Collector>(args: &NetlinkArgs, mut writer: C) {
             }
         }
 
-        (if let Some(p) = args.period {
-            period_start += Duration::from_secs_f64(p);
-            period_start
-        } else {
+        if let Some(period) = period {
+            offset += period;
+            if args.duration.is_some_and(|d| offset >= d) {
+                break;
+            }
+            period_start += period;
+            let mut wake = period_start;
+            if let Some(jitter) = &mut jitter {
+                wake += jitter.delay(period);
+            }
+            while !wake.sleep_until_or(args.clock, signal::interrupted) {
+                if signal::stop_requested() {
+                    break 'capture;
+                }
+                signal::take_sample_request();
+                sample(&mut writer, None, true)?;
+            }
+        } else if deadline.is_none_or(|deadline| Instant::now() >= deadline)
+            || signal::stop_requested()
+        {
             break;
-        })
-        .sleep_until();
+        }
+    }
+    if let Some(notifier) = &notifier {
+        notifier.stopping();
     }
+    Ok(())
 }
@@ -0,0 +1,171 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::data::*;
+use crate::flush::FlushPolicy;
+use crate::http::{self, HttpUrl};
+use crate::json::write_socket;
+use crate::Collector;
+
+/// Path of the push API below the Loki URL
+const PUSH_PATH: &str = "loki/api/v1/push";
+/// Entries after which a push is due regardless of the flush policy
+const MAX_ENTRIES: usize = 10_000;
+/// Labels set per stream, which `--label` must not redefine
+const STREAM_LABELS: [&str; 3] = ["source", "state", "cong"];
+
+/// Parses a `KEY=VALUE` label with a valid Loki label name.
+pub fn parse_label(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got {s:?}"))?;
+    let valid = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(format!("invalid label name {key:?}"));
+    }
+    if STREAM_LABELS.contains(&key) {
+        return Err(format!("label {key:?} is set by tcpdiag"));
+    }
+    Ok((key.into(), value.into()))
+}
+
+/// Labels of a stream besides the `--label` ones: source, state, and
+/// congestion control
+type StreamKey = (Option<String>, u8, Option<String>);
+
+#[derive(Serialize)]
+struct Push<'a> {
+    streams: Vec<Stream<'a>>,
+}
+
+#[derive(Serialize)]
+struct Stream<'a> {
+    stream: BTreeMap<&'a str, &'a str>,
+    /// Timestamp in nanoseconds (as string) and line of each entry
+    values: &'a [(String, String)],
+}
+
+/// Collector that pushes each socket as a log entry (its JSON object, as
+/// written by `JsonOutput`) to Grafana Loki, in streams labeled with the
+/// `--label` labels, the state, the congestion control, and the source of
+/// merged captures. The entries are timestamped with the time at which the
+/// socket was received, and pushed according to the flush policy (and at
+/// exit). Failed pushes are reported and their entries dropped.
+pub struct LokiOutput {
+    url: HttpUrl,
+    labels: Vec<(String, String)>,
+    policy: FlushPolicy,
+    last: Instant,
+    time: SystemTime,
+    offset: Option<Duration>,
+    source: Option<String>,
+    streams: BTreeMap<StreamKey, Vec<(String, String)>>,
+    entries: usize,
+    buf: Vec<u8>,
+}
+
+impl LokiOutput {
+    /// Pushes to the Loki instance at `url` (e.g. `http://localhost:3100`).
+    pub fn new(url: &HttpUrl, labels: Vec<(String, String)>, policy: FlushPolicy) -> Self {
+        Self {
+            url: url.join(PUSH_PATH),
+            labels,
+            policy,
+            last: Instant::now(),
+            time: SystemTime::UNIX_EPOCH,
+            offset: None,
+            source: None,
+            streams: BTreeMap::new(),
+            entries: 0,
+            buf: Vec::new(),
+        }
+    }
+
+    fn push(&mut self) {
+        if self.streams.is_empty() {
+            return;
+        }
+        let streams = self
+            .streams
+            .iter()
+            .map(|((source, state, cong), values)| {
+                let mut stream: BTreeMap<_, _> = self
+                    .labels
+                    .iter()
+                    .map(|(key, value)| (key.as_str(), value.as_str()))
+                    .collect();
+                stream.insert("state", tcp_state_name(*state));
+                if let Some(source) = source {
+                    stream.insert("source", source);
+                }
+                if let Some(cong) = cong {
+                    stream.insert("cong", cong);
+                }
+                Stream { stream, values }
+            })
+            .collect();
+        self.buf.clear();
+        serde_json::to_writer(&mut self.buf, &Push { streams }).unwrap();
+        match http::post(&self.url, "application/json", &self.buf) {
+            Ok(status) if (200..300).contains(&status) => (),
+            Ok(status) => crate::diagnostic!("loki push to {}: HTTP {status}", self.url),
+            Err(err) => crate::diagnostic!("loki push to {}: {err}", self.url),
+        }
+        self.streams.clear();
+        self.entries = 0;
+    }
+}
+
+impl Collector for LokiOutput {
+    fn source(&mut self, source: &str) {
+        self.source = Some(source.into());
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.time = time;
+        self.offset = None;
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.offset = Some(offset);
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        let extras = InetDiagMsgExtra::parse(data);
+        let time = self.time + self.offset.unwrap_or_default();
+        let nanos = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        self.buf.clear();
+        write_socket(&extras, &mut self.buf);
+        let line = String::from_utf8(self.buf.clone()).unwrap();
+        let key = (
+            self.source.clone(),
+            extras.base.state,
+            extras.cong.map(String::from),
+        );
+        self.streams
+            .entry(key)
+            .or_default()
+            .push((nanos.to_string(), line));
+        self.entries += 1;
+    }
+
+    fn end(&mut self, _duration: Duration) {
+        if self.policy.due(&mut self.last) || self.entries >= MAX_ENTRIES {
+            self.push();
+        }
+    }
+}
+
+impl Drop for LokiOutput {
+    fn drop(&mut self) {
+        self.push();
+    }
+}
@@ -1,53 +1,1026 @@
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 
-use std::io::{BufRead, BufReader, BufWriter};
+use jiff::tz::TimeZone;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::net::{Ipv6Addr, SocketAddr, TcpListener};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tcpdiag::anonymize::{Anonymize, Anonymizer};
+use tcpdiag::asn::Asn;
 use tcpdiag::binary::{read_binary, BinaryOutput};
-use tcpdiag::csv::{read_csv, CsvOutput};
-use tcpdiag::json::{read_json, JsonOutput};
-use tcpdiag::{read_netlink, NetlinkArgs};
+use tcpdiag::changes::ChangedOnly;
+use tcpdiag::check::{Check, Checker};
+use tcpdiag::compare::{write_comparison, Profile};
+use tcpdiag::container::Containers;
+use tcpdiag::csv::{read_csv, read_csv_with_header, CsvOutput};
+use tcpdiag::daemon::{daemonize, log, Pidfile};
+use tcpdiag::diff::{write_diff, Snapshot};
+use tcpdiag::filter::{Filter, Match, States};
+use tcpdiag::flush::{Flush, FlushPolicy};
+#[cfg(feature = "geoip")]
+use tcpdiag::geoip::GeoIp;
+use tcpdiag::group::{GroupBy, GroupOutput};
+use tcpdiag::histogram::{HistogramOutput, HistogramScope};
+use tcpdiag::http::HttpUrl;
+use tcpdiag::json::{read_json, JsonKey, JsonOutput};
+#[cfg(feature = "kubernetes")]
+use tcpdiag::kubernetes::Kubernetes;
+use tcpdiag::loki::{parse_label, LokiOutput};
+use tcpdiag::merge::merge;
+#[cfg(feature = "mqtt")]
+use tcpdiag::mqtt::{Encoding, MqttOutput, MqttUrl};
+use tcpdiag::pipeline::{pipeline, queue, QueuePolicy};
+use tcpdiag::plugin::PluginOutput;
+use tcpdiag::redis::{RedisOutput, RedisUrl};
+use tcpdiag::resample::{Every, Resample};
+#[cfg(feature = "script")]
+use tcpdiag::script::Script;
+use tcpdiag::serve::serve;
+use tcpdiag::smooth::SmoothTime;
+use tcpdiag::split::Split;
+use tcpdiag::summary::SummaryOutput;
+use tcpdiag::table::TableOutput;
+use tcpdiag::timestamp::{parse_time_zone, TimeFormat, Timestamp};
+#[cfg(feature = "tui")]
+use tcpdiag::tui::TuiOutput;
+use tcpdiag::validate::validate;
+#[cfg(feature = "wasm")]
+use tcpdiag::wasm::Wasm;
+use tcpdiag::webhook::WebhookOutput;
 use tcpdiag::Collector;
+use tcpdiag::{decompress, read_capture, read_netlink, NetlinkArgs};
 
 #[derive(Clone, Copy, Debug, clap::ValueEnum)]
 pub enum Format {
     Binary,
     Json,
     Csv,
+    Table,
+    /// Interactive dashboard (requires the tui feature)
+    Tui,
+}
+
+/// Formats that can be read with `-i`
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+enum InputFormat {
+    Binary,
+    Json,
+    Csv,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum Timestamps {
+    /// Microseconds since the Unix epoch
+    Micros,
+    /// RFC 3339 date and time
+    Rfc3339,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Compare the connections of two captures (last observation of each)
+    Diff {
+        /// Relative change of a metric to be reported
+        #[arg(long, default_value_t = 0.2)]
+        threshold: f64,
+        before: PathBuf,
+        after: PathBuf,
+    },
+    /// Compare key metrics of a capture to a baseline capture (e.g. for A/B
+    /// tests of kernel or sysctl changes), exiting with code 1 on regressions
+    Compare {
+        /// Capture of the reference setup
+        #[arg(long)]
+        baseline: PathBuf,
+        /// Relative change of a metric to count as regression or improvement
+        #[arg(long, default_value_t = 0.05)]
+        tolerance: f64,
+        capture: PathBuf,
+    },
+    /// Interleave the samples of several captures by time, tagged with their source
+    Merge {
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+    /// Check a capture for corruption, exiting with code 1 if it is corrupt
+    Validate { input: PathBuf },
+    /// Open synthetic connections (like synconn) and capture them, as a
+    /// self-test or benchmark
+    Bench {
+        /// Number of connections
+        #[arg(short = 'n', long, default_value_t = 100)]
+        count: u32,
+        /// Sampling period in seconds
+        #[arg(long, default_value_t = 0.1)]
+        period: f64,
+        /// Length of the capture
+        #[arg(long, default_value = "10s", value_parser = humantime::parse_duration)]
+        duration: Duration,
+        #[command(flatten)]
+        conn: synconn::ConnArgs,
+    },
+    /// Write the time series of each connection to its own file, named by its cookie
+    Split {
+        input: PathBuf,
+        /// Directory for the files (created if missing)
+        #[arg(long)]
+        dir: PathBuf,
+    },
+    /// Receive binary captures streamed by remote agents over TCP and write
+    /// them as one capture, tagged with the address of each agent
+    Serve {
+        /// Address to listen on, e.g. :9033 for all interfaces [default: the
+        /// sockets passed by systemd socket activation]
+        #[arg(long, value_parser = parse_listen)]
+        listen: Option<SocketAddr>,
+    },
+}
+
+/// Parses a listening address, with only the port (`:9033`) for all interfaces.
+fn parse_listen(s: &str) -> Result<SocketAddr, String> {
+    match s.strip_prefix(':') {
+        Some(port) => port
+            .parse()
+            .map(|port| SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)))
+            .map_err(|err| format!("{err}")),
+        None => s.parse().map_err(|err| format!("{err}")),
+    }
+}
+
+/// Parses a resampling interval, which must be at least a microsecond (the
+/// resolution of the samples).
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    match humantime::parse_duration(s).map_err(|err| format!("{err}"))? {
+        interval if interval < Duration::from_micros(1) => Err("must be at least 1us".into()),
+        interval => Ok(interval),
+    }
 }
 
 #[derive(Parser, Debug)]
+#[command(args_override_self = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// TOML file with default values for the options (keys are long option names)
+    #[arg(long)]
+    config: Option<PathBuf>,
     #[command(flatten)]
     netlink: NetlinkArgs,
-    #[arg(short = 'o', default_value = "json")]
+    #[arg(short = 'o', default_value = "json", global = true)]
     output: Format,
+    /// Fail with exit code 1 unless each sample satisfies this check
+    /// (e.g. 'retrans_rate < 0.01 && rtt_p95 < 50ms')
+    #[arg(long = "assert", value_name = "EXPR", global = true)]
+    checks: Vec<Check>,
+    /// Fail with exit code 1 unless the whole capture satisfies this check,
+    /// with the RTTs of all observations, the retransmissions and segments
+    /// of the connections during the capture, and the connections as sockets
+    #[arg(long = "assert-capture", value_name = "EXPR", global = true)]
+    capture_checks: Vec<Check>,
+    /// Print aggregate statistics per sample instead of individual sockets
+    #[arg(short = 'S', long, conflicts_with = "output", global = true)]
+    summary: bool,
+    /// Print histograms of the RTT, congestion window, and retransmission
+    /// rate per sample (or, with =capture, of the whole capture) instead of
+    /// individual sockets
+    #[arg(
+        long,
+        value_name = "SCOPE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "sample",
+        conflicts_with_all = ["output", "summary"],
+        global = true
+    )]
+    histogram: Option<HistogramScope>,
+    /// Print aggregate metrics per group of connections instead of individual
+    /// sockets, grouped by dst or src (with prefix lengths like dst/24 or
+    /// dst/24/64 for IPv4 and IPv6), dport, sport, or tag:KEY (e.g. tag:asn)
+    #[arg(
+        long,
+        value_name = "KEY",
+        conflicts_with_all = ["output", "summary", "histogram"],
+        global = true
+    )]
+    group_by: Option<GroupBy>,
+    /// Push the sockets as log entries to the Grafana Loki instance at this
+    /// URL (e.g. http://localhost:3100), batched by --flush
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with_all = ["output", "summary", "histogram", "group_by", "sandbox"]
+    )]
+    loki: Option<HttpUrl>,
+    /// Label of the --loki streams (can be repeated), besides the state,
+    /// congestion control, and source of each socket
+    #[arg(long = "label", value_name = "KEY=VALUE", requires = "loki", value_parser = parse_label)]
+    labels: Vec<(String, String)>,
+    /// Write the output with this plugin (a shared library exporting
+    /// TCPDIAG_PLUGIN) instead
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["output", "summary", "histogram", "group_by", "sandbox", "loki", "webhook", "redis"]
+    )]
+    plugin: Option<PathBuf>,
+    /// Argument passed to the --plugin when it is created
+    #[arg(long, value_name = "ARG", requires = "plugin")]
+    plugin_arg: Option<String>,
+    /// POST the samples as JSON arrays to this URL (http://host[:port][/path])
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with_all = ["output", "summary", "histogram", "group_by", "sandbox", "loki"]
+    )]
+    webhook: Option<HttpUrl>,
+    /// Interval in which the --webhook batches are sent
+    #[arg(long, value_name = "DURATION", default_value = "10s", value_parser = humantime::parse_duration)]
+    webhook_interval: Duration,
+    /// Retries of a failed --webhook batch (after 1s, 2s, 4s, ...) before it
+    /// is dropped
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    webhook_retries: u32,
+    /// Add the sockets to a Redis stream on this server
+    /// (redis://host[:port][/db])
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with_all = ["output", "summary", "histogram", "group_by", "sandbox", "loki", "webhook"]
+    )]
+    redis: Option<RedisUrl>,
+    /// Key of the --redis stream
+    #[arg(long, value_name = "KEY", default_value = "tcpdiag")]
+    redis_stream: String,
+    /// Trim the --redis stream to about this many entries
+    #[arg(long, value_name = "N", requires = "redis")]
+    redis_maxlen: Option<u64>,
+    /// Publish each sample to the topic of this URL (mqtt://host[:port]/topic)
+    #[cfg(feature = "mqtt")]
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with_all = ["output", "summary", "histogram", "group_by", "sandbox", "loki", "redis", "webhook", "plugin"]
+    )]
+    mqtt: Option<MqttUrl>,
+    /// Quality of service of the --mqtt messages (0, 1, or 2)
+    #[cfg(feature = "mqtt")]
+    #[arg(
+        long,
+        value_name = "QOS",
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u8).range(0..=2)
+    )]
+    mqtt_qos: u8,
+    /// Encoding of the --mqtt messages
+    #[cfg(feature = "mqtt")]
+    #[arg(long, value_name = "ENCODING", default_value = "json")]
+    mqtt_encoding: Encoding,
+    /// Omit the header line of csv output (e.g. to append to a capture)
+    #[arg(long, global = true)]
+    no_header: bool,
+    /// Don't print diagnostics (failed assertions, warnings, and malformed
+    /// input lines) on stderr
+    #[arg(short = 'q', long, global = true)]
+    quiet: bool,
+    /// When to write the output: every-sample, interval:DURATION (e.g.
+    /// interval:5s), or never (only when the buffer is full and at exit)
+    #[arg(
+        long,
+        value_name = "POLICY",
+        default_value = "every-sample",
+        global = true
+    )]
+    flush: FlushPolicy,
+    /// Write the sockets of each json sample as object with this key (for
+    /// lookups like `jq '.samples["4711"]'`) instead of an array
+    #[arg(long, value_name = "KEY", global = true)]
+    json_key: Option<JsonKey>,
+    /// Scale values to human-readable units (table, csv, and diff output)
+    #[arg(long, global = true)]
+    human: bool,
+    /// Representation of the time of each sample (json, csv, and table output)
+    #[arg(long, default_value = "micros", global = true)]
+    timestamps: Timestamps,
+    /// Time zone of RFC 3339 timestamps (local, UTC, or Area/City)
+    #[arg(long, requires = "timestamps", value_parser = parse_time_zone, global = true)]
+    tz: Option<TimeZone>,
+    /// Map addresses to other addresses (prefix-preserving) in the output
+    #[arg(long, global = true)]
+    anonymize: bool,
+    /// Key for --anonymize, to get the same mapping in multiple runs [default: random]
+    #[arg(long, requires = "anonymize", global = true)]
+    anonymize_key: Option<String>,
+    /// Also permute ports with --anonymize
+    #[arg(long, requires = "anonymize", global = true)]
+    anonymize_ports: bool,
+    /// Tag each socket with the country and city of the peer, looked up in
+    /// this MaxMind DB (e.g. GeoLite2-City.mmdb)
+    #[cfg(feature = "geoip")]
+    #[arg(long, value_name = "FILE", global = true)]
+    geoip: Option<PathBuf>,
+    /// Tag each socket with the AS of the peer, looked up in this routeviews
+    /// prefix-to-AS dump (or, with the geoip feature, a MaxMind ASN DB)
+    #[arg(long, value_name = "FILE", global = true)]
+    asn: Option<PathBuf>,
+    /// Tag each socket with the ID of the container of its process (found in
+    /// /proc, which --sandbox denies), when capturing
+    #[arg(long, conflicts_with_all = ["convert", "sandbox"])]
+    containers: bool,
+    /// Tag the sockets of --containers with the name, namespace, and labels
+    /// of their pod, from the Kubernetes API server
+    #[cfg(feature = "kubernetes")]
+    #[arg(long, requires = "containers")]
+    kubernetes: bool,
+    /// URL of the API server for --kubernetes (e.g. http://127.0.0.1:8001 of
+    /// kubectl proxy) [default: in-cluster service account]
+    #[cfg(feature = "kubernetes")]
+    #[arg(long, value_name = "URL", requires = "kubernetes")]
+    kube_api: Option<String>,
+    /// Node of the pods for --kubernetes [default: $NODE_NAME or the host name]
+    #[cfg(feature = "kubernetes")]
+    #[arg(long, value_name = "NAME", requires = "kubernetes")]
+    kube_node: Option<String>,
+    /// Write only sockets whose record changed since the previous sample (by
+    /// cookie), and gone sockets once more in state CLOSE
+    #[arg(long, global = true)]
+    changed_only: bool,
+    /// Run this rhai script for each socket, which can drop (by evaluating to
+    /// false), tag (record.tags.KEY = VALUE), or rewrite it (record.FIELD = VALUE)
+    #[cfg(feature = "script")]
+    #[arg(long, value_name = "FILE", global = true)]
+    script: Option<PathBuf>,
+    /// Pass each socket through the transform function of this WebAssembly
+    /// module, which can drop or rewrite it
+    #[cfg(feature = "wasm")]
+    #[arg(long, value_name = "FILE", global = true)]
+    wasm: Option<PathBuf>,
+    /// Capture through io_uring, which batches the netlink requests and
+    /// receives with the output writes
+    #[cfg(feature = "io-uring")]
+    #[arg(long, conflicts_with_all = ["convert", "sandbox", "queue"])]
+    io_uring: bool,
+    /// Queue the samples for the output in another thread, holding at most
+    /// this many bytes, so that a stalling output doesn't delay the samples
+    #[arg(long, value_name = "BYTES", conflicts_with_all = ["convert", "sandbox"])]
+    queue: Option<usize>,
+    /// What to do with a sample while the queue is full: block, drop-oldest,
+    /// or shorten
+    #[arg(
+        long,
+        value_name = "POLICY",
+        value_enum,
+        default_value_t,
+        requires = "queue"
+    )]
+    queue_policy: QueuePolicy,
+    /// Run the capture in the background, logging start, stop, and errors to
+    /// stderr (the capture is written to stdout, which must be redirected)
+    #[arg(long, conflicts_with = "convert")]
+    daemonize: bool,
+    /// File with the process ID of the daemon, removed when it exits
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "daemonize",
+        conflicts_with = "sandbox"
+    )]
+    pidfile: Option<PathBuf>,
     #[arg(conflicts_with = "netlink", short = 'C', long)]
     convert: bool,
+    /// Keep only every Nth sample when converting
+    #[arg(
+        long,
+        value_name = "N",
+        requires = "convert",
+        conflicts_with = "resample"
+    )]
+    every: Option<NonZeroU32>,
+    /// Aggregate samples into intervals of this length when converting (e.g. 10s)
+    #[arg(long, value_name = "INTERVAL", requires = "convert", value_parser = parse_interval)]
+    resample: Option<Duration>,
+    /// Skip samples before this time when converting (microseconds or RFC 3339)
+    #[arg(long, requires = "convert")]
+    from: Option<Timestamp>,
+    /// Skip samples from this time on when converting (microseconds or RFC 3339)
+    #[arg(long, requires = "convert")]
+    to: Option<Timestamp>,
+    /// Replace the sample times with the boottime plus an offset that follows
+    /// the wall time slowly, so that steps of the wall time (e.g. by NTP)
+    /// don't make the times jump, when converting
+    #[arg(long, requires = "convert")]
+    smooth_time: bool,
+    /// Keep only connections with this 4-tuple (src:port-dst:port) or with an
+    /// address in this CIDR range when converting (can be repeated)
+    #[arg(long = "match", value_name = "TUPLE|CIDR", requires = "convert")]
+    matches: Vec<Match>,
+    /// Keep only connections in these states when converting (comma-separated,
+    /// e.g. established,time-wait)
+    #[arg(long = "state", value_name = "STATES", requires = "convert")]
+    states: Option<States>,
+    /// Parse the input and write the output in separate threads when converting
+    #[arg(long, requires = "convert")]
+    pipeline: bool,
+    /// Read csv input without header line, which has these columns
+    /// (space-separated) or, if empty, the columns of this version
+    #[arg(
+        long,
+        value_name = "COLUMNS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "",
+        requires = "convert",
+        conflicts_with = "input"
+    )]
+    input_header: Option<String>,
+    /// Input format (detected automatically if omitted)
+    #[arg(requires = "convert", short = 'i')]
+    input: Option<InputFormat>,
+    /// Capture to convert ("-" for stdin)
+    #[arg(requires = "convert", value_name = "INPUT")]
+    input_path: Option<PathBuf>,
+    /// Destination of the converted capture ("-" for stdout)
+    #[arg(requires = "input_path", value_name = "OUTPUT")]
+    output_path: Option<PathBuf>,
+}
+
+/// Finds --config before clap parses the arguments, as options that are
+/// required by the command line may only be present in the config file.
+fn find_config(argv: &[OsString]) -> Option<PathBuf> {
+    let mut iter = argv.iter().skip(1).map(|arg| arg.to_str());
+    while let Some(arg) = iter.next() {
+        match arg {
+            Some("--") => break,
+            Some("--config") => return iter.next().flatten().map(PathBuf::from),
+            Some(arg) => {
+                if let Some(path) = arg.strip_prefix("--config=") {
+                    return Some(path.into());
+                }
+            }
+            None => (),
+        }
+    }
+    None
+}
+
+/// Turns the config file into arguments that precede the command line
+/// arguments, so the command line overrides the config file. Keys are long
+/// option names, or field names of options with only a short name.
+fn config_args(path: &Path) -> Vec<OsString> {
+    let mut command = Args::command();
+    let mut error = |kind, message: String| -> ! {
+        command
+            .error(kind, format!("{}: {message}", path.display()))
+            .exit()
+    };
+    let text = std::fs::read_to_string(path)
+        .unwrap_or_else(|err| error(clap::error::ErrorKind::Io, err.to_string()));
+    let table: toml::Table = text.parse().unwrap_or_else(|err: toml::de::Error| {
+        error(clap::error::ErrorKind::InvalidValue, err.message().into())
+    });
+    let command = Args::command();
+    let mut args = Vec::new();
+    for (key, value) in table {
+        let arg = command.get_arguments().find(|arg| match arg.get_long() {
+            Some(long) => long == key && long != "config",
+            None => arg.get_short().is_some() && arg.get_id() == key.replace('-', "_").as_str(),
+        });
+        let Some(arg) = arg else {
+            error(
+                clap::error::ErrorKind::UnknownArgument,
+                format!("unknown option {key}"),
+            );
+        };
+        let flag = match (arg.get_long(), arg.get_short()) {
+            (Some(long), _) => format!("--{long}"),
+            (None, Some(short)) => format!("-{short}"),
+            (None, None) => unreachable!(),
+        };
+        let values = match value {
+            toml::Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                toml::Value::Boolean(true) => args.push(flag.clone().into()),
+                toml::Value::Boolean(false) => (),
+                toml::Value::String(s) => args.extend([flag.clone().into(), s.into()]),
+                toml::Value::Integer(_) | toml::Value::Float(_) => {
+                    args.extend([flag.clone().into(), value.to_string().into()])
+                }
+                value => error(
+                    clap::error::ErrorKind::InvalidValue,
+                    format!("invalid value for {key}: {value}"),
+                ),
+            }
+        }
+    }
+    args
+}
+
+/// Names a capture by its file name, without whitespace to keep csv intact.
+fn source_name(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy();
+    name.replace(char::is_whitespace, "_")
+}
+
+fn open_input(path: Option<&Path>) -> Box<dyn Read + Send> {
+    match path {
+        Some(path) if path != Path::new("-") => {
+            Box::new(File::open(path).unwrap_or_else(|err| panic!("{}: {err}", path.display())))
+        }
+        _ => Box::new(std::io::stdin()),
+    }
+}
+
+/// Opens a capture for reading, decompressing gzip and zstd.
+fn open_capture(path: Option<&Path>) -> Box<dyn BufRead + Send> {
+    decompress(BufReader::new(open_input(path)))
+}
+
+fn read_input<R: BufRead, C: Collector>(
+    format: Option<InputFormat>,
+    csv_header: Option<&str>,
+    reader: R,
+    writer: C,
+) {
+    if csv_header.is_some() {
+        return read_csv_with_header(reader, csv_header, writer);
+    }
+    match format {
+        Some(InputFormat::Binary) => read_binary(reader, writer),
+        Some(InputFormat::Json) => read_json(reader, writer),
+        Some(InputFormat::Csv) => read_csv(reader, writer),
+        None => read_capture(reader, writer),
+    }
+}
+
+fn open_output(path: Option<&Path>) -> Box<dyn Write> {
+    match path {
+        Some(path) if path != Path::new("-") => {
+            Box::new(File::create(path).unwrap_or_else(|err| panic!("{}: {err}", path.display())))
+        }
+        _ => Box::new(std::io::stdout().lock()),
+    }
+}
+
+/// Captures to `output`, through the queue of `--queue` if given.
+fn capture(args: &Args, output: impl Collector) {
+    let netlink = &args.netlink;
+    match args.queue {
+        Some(limit) => queue(
+            |queue| read_netlink(netlink, queue),
+            args.queue_policy,
+            limit,
+            output,
+        ),
+        None => read_netlink(netlink, output),
+    }
+}
+
+/// Creates the output of `args`, without a header if `append`ing to a file.
+fn make_output(
+    args: &Args,
+    time_format: &TimeFormat,
+    out: Box<dyn Write>,
+    append: bool,
+) -> Box<dyn Collector> {
+    let out = Flush::new(BufWriter::new(out), args.flush);
+    let output: Box<dyn Collector> = match args.output {
+        _ if args.loki.is_some() => Box::new(LokiOutput::new(
+            args.loki.as_ref().unwrap(),
+            args.labels.clone(),
+            args.flush,
+        )),
+        _ if args.plugin.is_some() => {
+            let path = args.plugin.as_ref().unwrap();
+            match PluginOutput::load(path, args.plugin_arg.as_deref()) {
+                Ok(output) => Box::new(output),
+                Err(err) => Args::command()
+                    .error(
+                        clap::error::ErrorKind::InvalidValue,
+                        format!("--plugin: {err}"),
+                    )
+                    .exit(),
+            }
+        }
+        _ if args.webhook.is_some() => {
+            let mut output = WebhookOutput::new(
+                args.webhook.as_ref().unwrap(),
+                args.webhook_interval,
+                args.webhook_retries,
+            );
+            output.set_time_format(time_format.clone());
+            output.set_key(args.json_key);
+            Box::new(output)
+        }
+        _ if args.redis.is_some() => Box::new(RedisOutput::new(
+            args.redis.as_ref().unwrap(),
+            &args.redis_stream,
+            args.redis_maxlen,
+        )),
+        #[cfg(feature = "mqtt")]
+        _ if args.mqtt.is_some() => Box::new(MqttOutput::new(
+            args.mqtt.as_ref().unwrap(),
+            args.mqtt_qos,
+            args.mqtt_encoding,
+        )),
+        _ if args.summary => {
+            let mut output = SummaryOutput::new(out);
+            output.set_time_format(time_format.clone());
+            Box::new(output)
+        }
+        _ if args.histogram.is_some() => {
+            let mut output = HistogramOutput::new(out, args.histogram.unwrap());
+            output.set_time_format(time_format.clone());
+            Box::new(output)
+        }
+        _ if args.group_by.is_some() => {
+            let mut output = GroupOutput::new(out, args.group_by.clone().unwrap());
+            output.set_time_format(time_format.clone());
+            Box::new(output)
+        }
+        Format::Json => {
+            let mut output = JsonOutput::new(out);
+            output.set_time_format(time_format.clone());
+            output.set_key(args.json_key);
+            Box::new(output)
+        }
+        Format::Binary => Box::new(BinaryOutput::new(out)),
+        Format::Csv => {
+            let mut output = match args.no_header || append {
+                true => CsvOutput::without_header(out),
+                false => CsvOutput::new(out),
+            };
+            output.set_human(args.human);
+            output.set_time_format(time_format.clone());
+            Box::new(output)
+        }
+        Format::Table => {
+            let mut output = TableOutput::new(out, args.human);
+            output.set_time_format(time_format.clone());
+            Box::new(output)
+        }
+        Format::Tui
+            if args.convert
+                || args.netlink.period.is_none()
+                    && !matches!(args.command, Some(Command::Bench { .. })) =>
+        {
+            Args::command()
+                .error(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    "-o tui requires -p and cannot be used with --convert",
+                )
+                .exit()
+        }
+        #[cfg(feature = "tui")]
+        Format::Tui => {
+            let mut output = TuiOutput::new(args.human);
+            output.set_time_format(time_format.clone());
+            Box::new(output)
+        }
+        #[cfg(not(feature = "tui"))]
+        Format::Tui => Args::command()
+            .error(
+                clap::error::ErrorKind::InvalidValue,
+                "tcpdiag was built without the tui feature",
+            )
+            .exit(),
+    };
+    let output = match args.changed_only {
+        true => Box::new(ChangedOnly::new(output)),
+        false => output,
+    };
+    #[cfg(feature = "wasm")]
+    let output: Box<dyn Collector> = match &args.wasm {
+        Some(path) => Box::new(Wasm::new(path, output)),
+        None => output,
+    };
+    #[cfg(feature = "script")]
+    if let Some(path) = &args.script {
+        return Box::new(Script::new(path, output));
+    }
+    output
+}
+
+/// Wraps the output with the collectors that tag sockets with information
+/// about their peers, which see the addresses before they are anonymized.
+fn enrich<'a>(args: &Args, output: Box<dyn Collector + 'a>) -> Box<dyn Collector + 'a> {
+    #[cfg(feature = "geoip")]
+    let output: Box<dyn Collector + 'a> = match &args.geoip {
+        Some(path) => Box::new(GeoIp::new(path, output)),
+        None => output,
+    };
+    let output: Box<dyn Collector + 'a> = match &args.asn {
+        Some(path) => Box::new(Asn::new(path, output)),
+        None => output,
+    };
+    #[cfg(feature = "kubernetes")]
+    let output: Box<dyn Collector + 'a> = match args.kubernetes {
+        true => Box::new(Kubernetes::new(
+            args.kube_api.as_deref(),
+            args.kube_node.as_deref(),
+            output,
+        )),
+        false => output,
+    };
+    match args.containers {
+        true => Box::new(Containers::new(output)),
+        false => output,
+    }
+}
+
+fn anonymizer(args: &Args) -> Option<Anonymizer> {
+    args.anonymize
+        .then(|| Anonymizer::new(args.anonymize_key.as_deref(), args.anonymize_ports))
+}
+
+/// Raises the soft limit of open files to the hard limit, as splitting keeps
+/// a file per connection open, and returns the resulting soft limit.
+fn raise_file_limit() -> usize {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    unsafe {
+        if libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) != 0 {
+            return 1024;
+        }
+        let soft = limit.rlim_cur;
+        limit.rlim_cur = limit.rlim_max;
+        if libc::setrlimit(libc::RLIMIT_NOFILE, &limit) != 0 {
+            limit.rlim_cur = soft;
+        }
+    }
+    usize::try_from(limit.rlim_cur).unwrap_or(usize::MAX)
 }
 
 fn main() {
-    let args = Args::parse();
-
-    let stdout = BufWriter::new(std::io::stdout().lock());
-    let writer: Box<dyn Collector> = match args.output {
-        Format::Json => Box::new(JsonOutput::new(stdout)),
-        Format::Binary => Box::new(BinaryOutput::new(stdout)),
-        Format::Csv => Box::new(CsvOutput::new(stdout)),
-    };
-
-    if args.convert {
-        let mut reader = BufReader::new(std::io::stdin().lock());
-        let peek = reader.fill_buf().unwrap();
-        const A: u8 = 1u16.to_ne_bytes()[0];
-        const B: u8 = 1u16.to_ne_bytes()[1];
-        match *peek {
-            [_, _, A, B, ..] => read_binary(reader, writer),
-            [_, _, B, A, ..] => unimplemented!("foreign endianness"),
-            [b'{', b'"', ..] => read_json(reader, writer),
-            [b'#' | b'a'..=b'z', ..] => read_csv(reader, writer),
-            [] => (),
-            _ => panic!("unrecognized format"),
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
+    if let Some(config) = find_config(&argv) {
+        argv.splice(1..1, config_args(&config));
+    }
+    let args = Args::parse_from(argv);
+    tcpdiag::set_quiet(args.quiet);
+    if args.daemonize && (args.command.is_some() || std::io::stdout().is_terminal()) {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--daemonize requires a capture without subcommand to a redirected stdout",
+            )
+            .exit()
+    }
+    if args.netlink.sandbox && (args.command.is_some() || matches!(args.output, Format::Tui)) {
+        Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--sandbox requires a capture without subcommand, other than -o tui",
+            )
+            .exit()
+    }
+
+    if let Some(Command::Diff {
+        threshold,
+        before,
+        after,
+    }) = &args.command
+    {
+        let [before, after] = [before, after].map(|path| {
+            let mut snapshot = Snapshot::default();
+            read_capture(open_capture(Some(path)), &mut snapshot);
+            snapshot
+        });
+        let out = BufWriter::new(std::io::stdout().lock());
+        write_diff(&before, &after, *threshold, args.human, out);
+        return;
+    }
+    if let Some(Command::Compare {
+        baseline,
+        tolerance,
+        capture,
+    }) = &args.command
+    {
+        let [baseline, capture] = [baseline, capture].map(|path| {
+            let mut profile = Profile::default();
+            read_capture(open_capture(Some(path)), &mut profile);
+            profile
+        });
+        let out = BufWriter::new(std::io::stdout().lock());
+        if write_comparison(&baseline, &capture, *tolerance, args.human, out) > 0 {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let time_format = match args.timestamps {
+        Timestamps::Micros if args.tz.is_some() => Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--tz requires --timestamps rfc3339",
+            )
+            .exit(),
+        Timestamps::Micros => TimeFormat::Micros,
+        Timestamps::Rfc3339 => TimeFormat::Rfc3339(args.tz.clone().unwrap_or(TimeZone::UTC)),
+    };
+    if let Some(Command::Validate { input }) = &args.command {
+        let report = validate(open_capture(Some(input)));
+        report.write(std::io::stdout().lock(), &time_format);
+        if !report.is_ok() {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(Command::Split { input, dir }) = &args.command {
+        std::fs::create_dir_all(dir).unwrap_or_else(|err| panic!("{}: {err}", dir.display()));
+        // Leaves some files for the capture and the standard streams.
+        let max_open = raise_file_limit().saturating_sub(16);
+        let extension = match args.output {
+            Format::Binary => "bin",
+            Format::Json => "json",
+            Format::Csv => "csv",
+            Format::Table | Format::Tui => "txt",
+        };
+        let split = Split::new(max_open, |cookie, append| {
+            let path = dir.join(format!("{cookie}.{extension}"));
+            let out = match append {
+                true => Box::new(
+                    OpenOptions::new()
+                        .append(true)
+                        .open(&path)
+                        .unwrap_or_else(|err| panic!("{}: {err}", path.display())),
+                ),
+                false => open_output(Some(&path)),
+            };
+            make_output(&args, &time_format, out, append)
+        });
+        let reader = open_capture(Some(input));
+        let output: Box<dyn Collector> = match anonymizer(&args) {
+            Some(anonymizer) => Box::new(Anonymize::new(split, anonymizer)),
+            None => Box::new(split),
+        };
+        read_capture(reader, enrich(&args, output));
+        return;
+    }
+
+    let mut pidfile = args.pidfile.as_deref().map(Pidfile::lock);
+    if args.daemonize {
+        daemonize();
+        if let Some(pidfile) = &mut pidfile {
+            pidfile.write();
         }
+        let period = match args.netlink.period {
+            Some(period) => format!("every {period}s"),
+            None => "once".into(),
+        };
+        log("info", &format!("capture started, sampling {period}"));
+    }
+
+    #[cfg(feature = "io-uring")]
+    let uring = (args.io_uring && args.command.is_none()).then(tcpdiag::uring::Uring::new);
+    #[cfg(feature = "io-uring")]
+    let out: Box<dyn Write> = match &uring {
+        Some(uring) => Box::new(tcpdiag::uring::UringWriter::new(
+            uring.clone(),
+            std::os::fd::AsFd::as_fd(&std::io::stdout())
+                .try_clone_to_owned()
+                .unwrap(),
+        )),
+        None => open_output(args.output_path.as_deref()),
+    };
+    #[cfg(not(feature = "io-uring"))]
+    let out = open_output(args.output_path.as_deref());
+    let writer = make_output(&args, &time_format, out, false);
+
+    let anonymizer = anonymizer(&args);
+    let mut checker = Checker::new(writer, args.checks.clone(), args.capture_checks.clone());
+    let output: Box<dyn Collector + '_> = match anonymizer {
+        Some(anonymizer) => Box::new(Anonymize::new(&mut checker, anonymizer)),
+        None => Box::new(&mut checker),
+    };
+    let mut output = enrich(&args, output);
+    if let Some(Command::Merge { inputs }) = &args.command {
+        let captures = inputs
+            .iter()
+            .map(|path| (source_name(path), open_capture(Some(path))))
+            .collect();
+        merge(
+            captures,
+            |reader, stream| read_capture(reader, stream),
+            &mut output,
+        );
+    } else if let Some(Command::Serve { listen }) = &args.command {
+        let mut listeners: Vec<TcpListener> = tcpdiag::notify::listen_fds()
+            .into_iter()
+            .map(TcpListener::from)
+            .collect();
+        match listen {
+            Some(addr) => listeners
+                .push(TcpListener::bind(addr).unwrap_or_else(|err| panic!("listen {addr}: {err}"))),
+            None if listeners.is_empty() => Args::command()
+                .error(
+                    clap::error::ErrorKind::MissingRequiredArgument,
+                    "serve requires --listen unless started by socket activation",
+                )
+                .exit(),
+            None => (),
+        }
+        tcpdiag::signal::handle_termination();
+        serve(listeners, &mut output);
+    } else if args.convert {
+        let reader = open_capture(args.input_path.as_deref());
+        let mut writer: Box<dyn Collector + '_> = match (args.every, args.resample) {
+            (Some(n), _) => Box::new(Every::new(&mut output, n)),
+            (_, Some(interval)) => Box::new(Resample::new(&mut output, interval)),
+            (None, None) => Box::new(&mut output),
+        };
+        if args.from.is_some()
+            || args.to.is_some()
+            || !args.matches.is_empty()
+            || args.states.is_some()
+        {
+            writer = Box::new(Filter::new(
+                writer,
+                args.from,
+                args.to,
+                args.matches,
+                args.states,
+            ));
+        }
+        if args.smooth_time {
+            writer = Box::new(SmoothTime::new(writer));
+        }
+        let input = args.input;
+        if args.pipeline {
+            let header = args.input_header.clone();
+            pipeline(
+                move |pipe| read_input(input, header.as_deref(), reader, pipe),
+                &mut writer,
+            );
+        } else {
+            read_input(input, args.input_header.as_deref(), reader, &mut writer);
+        }
+    } else if let Some(Command::Bench {
+        count,
+        period,
+        duration,
+        conn,
+    }) = &args.command
+    {
+        let endpoint = synconn::Endpoint::new(conn).unwrap();
+        let netlink = NetlinkArgs {
+            inet4: endpoint.addr.is_ipv4(),
+            inet6: endpoint.addr.is_ipv6(),
+            dport: endpoint.addr.port(),
+            period: Some(*period),
+            duration: Some(*duration),
+            ..args.netlink.clone()
+        };
+        // When ramping up, the capture starts with the first connection.
+        let conns = match conn.rate {
+            Some(_) => None,
+            None => Some(endpoint.open(conn, *count).unwrap()),
+        };
+        tcpdiag::signal::handle_termination();
+        std::thread::scope(|scope| {
+            let ramp = conns
+                .is_none()
+                .then(|| scope.spawn(|| endpoint.open(conn, *count).unwrap()));
+            read_netlink(&netlink, &mut output);
+            drop(ramp.map(|ramp| ramp.join().unwrap()));
+        });
+        drop(conns);
     } else {
-        read_netlink(&args.netlink, writer);
+        tcpdiag::signal::handle_termination();
+        #[cfg(feature = "io-uring")]
+        if let Some(uring) = &uring {
+            tcpdiag::uring::read_netlink_uring(&args.netlink, uring, &mut output);
+        } else {
+            capture(&args, &mut output);
+        }
+        #[cfg(not(feature = "io-uring"))]
+        capture(&args, &mut output);
+    }
+    drop(output);
+    checker.finish();
+    let (failures, no_data) = (checker.failures(), checker.no_data());
+    drop(checker);
+    if args.daemonize {
+        let reason = match tcpdiag::signal::stop_requested() {
+            true => "on signal",
+            false => "after the last sample",
+        };
+        log("info", &format!("capture stopped {reason}"));
+    }
+    drop(pidfile);
+    if failures > 0 {
+        std::process::exit(1);
+    } else if no_data > 0 {
+        std::process::exit(3);
     }
 }
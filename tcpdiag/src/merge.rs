@@ -0,0 +1,210 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    sync::mpsc::{sync_channel, SyncSender},
+    time::{Duration, SystemTime},
+};
+
+use crate::{Collector, SampleStats};
+
+/// Sample of a capture, kept in memory.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub time: SystemTime,
+    pub source: Option<String>,
+    /// Metadata of the capture, on its first sample
+    pub metadata: Option<Vec<(String, String)>>,
+    pub messages: Vec<Vec<u8>>,
+    /// Receive offsets, each of the messages from the given index on
+    pub offsets: Vec<(usize, Duration)>,
+    pub duration: Duration,
+    pub period: Option<Duration>,
+    pub unscheduled: bool,
+    pub stats: Option<SampleStats>,
+}
+
+impl Sample {
+    /// Passes the sample to a collector, in the order of the readers.
+    pub fn replay<C: Collector>(&self, mut writer: C) {
+        if let Some(metadata) = &self.metadata {
+            writer.metadata(metadata);
+        }
+        if let Some(source) = &self.source {
+            writer.source(source);
+        }
+        writer.start(self.time);
+        let mut offsets = self.offsets.iter().peekable();
+        for (i, message) in self.messages.iter().enumerate() {
+            if let Some((_, offset)) = offsets.next_if(|(start, _)| *start == i) {
+                writer.offset(*offset);
+            }
+            writer.out(message);
+        }
+        if let Some(period) = self.period {
+            writer.period(period);
+        }
+        if self.unscheduled {
+            writer.unscheduled();
+        }
+        if let Some(stats) = &self.stats {
+            writer.stats(stats);
+        }
+        writer.end(self.duration);
+    }
+}
+
+/// Collects all samples of a capture.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    pub samples: Vec<Sample>,
+    source: Option<String>,
+    metadata: Option<Vec<(String, String)>>,
+    current: Option<Sample>,
+}
+
+impl Collector for Recorder {
+    fn source(&mut self, source: &str) {
+        self.source = Some(source.into());
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.metadata = Some(metadata.to_vec());
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.current = Some(Sample {
+            time,
+            source: self.source.clone(),
+            metadata: self.metadata.take(),
+            messages: Vec::new(),
+            offsets: Vec::new(),
+            duration: Duration::ZERO,
+            period: None,
+            unscheduled: false,
+            stats: None,
+        });
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        self.current.as_mut().unwrap().messages.push(data.to_vec());
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.current.as_mut().unwrap().period = Some(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.current.as_mut().unwrap().unscheduled = true;
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.current.as_mut().unwrap().stats = Some(*stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        let sample = self.current.as_mut().unwrap();
+        sample.offsets.push((sample.messages.len(), offset));
+    }
+
+    fn end(&mut self, duration: Duration) {
+        let mut sample = self.current.take().unwrap();
+        sample.duration = duration;
+        self.samples.push(sample);
+    }
+}
+
+/// Collector that sends each sample of a capture to `merge`, tagged with the
+/// name of the capture unless it has a source.
+struct Stream {
+    recorder: Recorder,
+    name: String,
+    tx: SyncSender<Sample>,
+}
+
+impl Collector for Stream {
+    fn source(&mut self, source: &str) {
+        self.recorder.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.recorder.metadata(metadata);
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.recorder.start(time);
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        self.recorder.out(data);
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.recorder.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.recorder.unscheduled();
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.recorder.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.recorder.offset(offset);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.recorder.end(duration);
+        let mut sample = self.recorder.samples.pop().unwrap();
+        sample.source.get_or_insert_with(|| self.name.clone());
+        // The receiver only hangs up if the writer panicked.
+        let _ = self.tx.send(sample);
+    }
+}
+
+/// Interleaves the samples of several captures (each in time order) by their
+/// time, passing them to `writer`. Each capture is read by `read` in its own
+/// thread, and only its next sample is kept in memory. Samples without a
+/// source (i.e. not from a previous merge) are tagged with the name of their
+/// capture. Samples with the same time are passed in the order of the
+/// captures.
+pub fn merge<I: Send, C: Collector>(
+    captures: Vec<(String, I)>,
+    read: impl Fn(I, &mut dyn Collector) + Sync,
+    mut writer: C,
+) {
+    std::thread::scope(|scope| {
+        let read = &read;
+        let (readers, receivers): (Vec<_>, Vec<_>) = captures
+            .into_iter()
+            .map(|(name, input)| {
+                let (tx, rx) = sync_channel(1);
+                let mut stream = Stream {
+                    recorder: Recorder::default(),
+                    name,
+                    tx,
+                };
+                (scope.spawn(move || read(input, &mut stream)), rx)
+            })
+            .unzip();
+        let mut next: Vec<Option<Sample>> = receivers.iter().map(|rx| rx.recv().ok()).collect();
+        let mut heads: BinaryHeap<_> = next
+            .iter()
+            .enumerate()
+            .filter_map(|(i, sample)| Some(Reverse((sample.as_ref()?.time, i))))
+            .collect();
+        while let Some(Reverse((_, i))) = heads.pop() {
+            next[i].take().unwrap().replay(&mut writer);
+            if let Ok(sample) = receivers[i].recv() {
+                heads.push(Reverse((sample.time, i)));
+                next[i] = Some(sample);
+            }
+        }
+        for reader in readers {
+            if let Err(err) = reader.join() {
+                std::panic::resume_unwind(err);
+            }
+        }
+    });
+}
@@ -0,0 +1,310 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    net::TcpStream,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::data::*;
+use crate::http::{connect, parse_authority};
+use crate::timestamp::Timestamp;
+use crate::{Collector, SampleStats};
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const PUBREC: u8 = 0x50;
+/// PUBREL with its reserved flags
+const PUBREL: u8 = 0x62;
+const PUBCOMP: u8 = 0x70;
+const DISCONNECT: u8 = 0xe0;
+/// Largest remaining length of an MQTT packet
+const MAX_LENGTH: usize = 268_435_455;
+
+/// `mqtt://host[:port]/topic` URL of the topic to publish to
+#[derive(Debug, Clone)]
+pub struct MqttUrl {
+    host: String,
+    port: u16,
+    topic: String,
+}
+
+impl FromStr for MqttUrl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let rest = s
+            .strip_prefix("mqtt://")
+            .ok_or_else(|| format!("expected an mqtt:// URL, got {s:?}"))?;
+        let (authority, topic) = rest
+            .split_once('/')
+            .filter(|(_, topic)| !topic.is_empty())
+            .ok_or_else(|| format!("missing topic in {s:?}"))?;
+        if topic.contains(['+', '#']) {
+            return Err(format!("wildcards are not allowed in the topic {topic:?}"));
+        }
+        let (host, port) = parse_authority(authority, 1883)?;
+        Ok(Self {
+            host,
+            port,
+            topic: topic.into(),
+        })
+    }
+}
+
+impl fmt::Display for MqttUrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "mqtt://{}:{}/{}", self.host, self.port, self.topic)
+    }
+}
+
+/// Encoding of the published samples
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Encoding {
+    /// Object like a line of json output
+    Json,
+    /// The same object in CBOR
+    Cbor,
+}
+
+/// Sample with the fields of a line of json output
+#[derive(Serialize)]
+struct Sample<'a> {
+    time: Timestamp,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<&'a str>,
+    samples: Vec<InetDiagMsgExtra<'a>>,
+    duration: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    period: Option<u64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    unscheduled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<SampleStats>,
+}
+
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend((s.len() as u16).to_be_bytes());
+    buf.extend(s.as_bytes());
+}
+
+/// Writes a packet with the given first byte (type and flags).
+fn write_packet(stream: &mut TcpStream, header: u8, parts: &[&[u8]]) -> io::Result<()> {
+    let mut len: usize = parts.iter().map(|part| part.len()).sum();
+    if len > MAX_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("packet of {len} bytes exceeds the MQTT limit"),
+        ));
+    }
+    let mut packet = Vec::with_capacity(len + 5);
+    packet.push(header);
+    loop {
+        let byte = (len % 128) as u8;
+        len /= 128;
+        if len == 0 {
+            packet.push(byte);
+            break;
+        }
+        packet.push(byte | 0x80);
+    }
+    for part in parts {
+        packet.extend(*part);
+    }
+    stream.write_all(&packet)
+}
+
+/// Reads a packet, returning its first byte and its remaining bytes.
+fn read_packet(stream: &mut TcpStream) -> io::Result<(u8, Vec<u8>)> {
+    let mut byte = [0];
+    stream.read_exact(&mut byte)?;
+    let header = byte[0];
+    let mut len = 0;
+    for shift in (0..28).step_by(7) {
+        stream.read_exact(&mut byte)?;
+        len |= usize::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut data = vec![0; len];
+    stream.read_exact(&mut data)?;
+    Ok((header, data))
+}
+
+/// Reads an acknowledgement of the given type for a packet ID.
+fn expect(stream: &mut TcpStream, header: u8, packet_id: u16) -> io::Result<()> {
+    match read_packet(stream)? {
+        (h, data) if h == header && data == packet_id.to_be_bytes() => Ok(()),
+        (h, _) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected packet type {:#x}", h >> 4),
+        )),
+    }
+}
+
+/// Connects to the broker with a clean session and without keep-alive.
+fn connect_broker(url: &MqttUrl) -> io::Result<TcpStream> {
+    let mut stream = connect(&url.host, url.port)?;
+    stream.set_nodelay(true)?;
+    let mut body = Vec::new();
+    write_string(&mut body, "MQTT");
+    body.extend([4, 0x02, 0, 0]); // MQTT 3.1.1, clean session, keep-alive 0
+    write_string(&mut body, &format!("tcpdiag-{}", std::process::id()));
+    write_packet(&mut stream, CONNECT, &[&body])?;
+    match read_packet(&mut stream)? {
+        (CONNACK, data) if data.len() == 2 && data[1] == 0 => Ok(stream),
+        (CONNACK, data) if data.len() == 2 => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("broker refused the connection with code {}", data[1]),
+        )),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "expected CONNACK",
+        )),
+    }
+}
+
+/// Collector that publishes each sample to an MQTT topic (MQTT 3.1.1),
+/// encoded like a line of json output or in CBOR. Publishing waits for the
+/// acknowledgements of QoS 1 and 2. After a failure, which is reported and
+/// drops the sample, the next sample reconnects.
+pub struct MqttOutput {
+    url: MqttUrl,
+    qos: u8,
+    encoding: Encoding,
+    stream: Option<TcpStream>,
+    packet_id: u16,
+    time: SystemTime,
+    source: Option<String>,
+    /// Records of the sample, with their offset in microseconds
+    records: Vec<(Vec<u8>, Option<u32>)>,
+    period: Option<Duration>,
+    unscheduled: bool,
+    stats: Option<SampleStats>,
+    offset: Option<Duration>,
+    buf: Vec<u8>,
+}
+
+impl MqttOutput {
+    pub fn new(url: &MqttUrl, qos: u8, encoding: Encoding) -> Self {
+        assert!(qos <= 2, "invalid QoS {qos}");
+        Self {
+            url: url.clone(),
+            qos,
+            encoding,
+            stream: None,
+            packet_id: 0,
+            time: SystemTime::UNIX_EPOCH,
+            source: None,
+            records: Vec::new(),
+            period: None,
+            unscheduled: false,
+            stats: None,
+            offset: None,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Publishes `buf`.
+    fn publish(&mut self) -> io::Result<()> {
+        if self.stream.is_none() {
+            self.stream = Some(connect_broker(&self.url)?);
+        }
+        let stream = self.stream.as_mut().unwrap();
+        let mut head = Vec::new();
+        write_string(&mut head, &self.url.topic);
+        if self.qos > 0 {
+            self.packet_id = self.packet_id.checked_add(1).unwrap_or(1);
+            head.extend(self.packet_id.to_be_bytes());
+        }
+        write_packet(stream, PUBLISH | self.qos << 1, &[&head, &self.buf])?;
+        match self.qos {
+            1 => expect(stream, PUBACK, self.packet_id),
+            2 => {
+                expect(stream, PUBREC, self.packet_id)?;
+                write_packet(stream, PUBREL, &[&self.packet_id.to_be_bytes()])?;
+                expect(stream, PUBCOMP, self.packet_id)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Collector for MqttOutput {
+    fn source(&mut self, source: &str) {
+        self.source = Some(source.into());
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.time = time;
+        self.records.clear();
+        self.period = None;
+        self.unscheduled = false;
+        self.stats = None;
+        self.offset = None;
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        let offset = self.offset.map(|offset| offset.as_micros() as u32);
+        self.records.push((data.to_vec(), offset));
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.period = Some(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.unscheduled = true;
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.stats = Some(*stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.offset = Some(offset);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        let samples = self
+            .records
+            .iter()
+            .map(|(record, offset)| {
+                let mut extras = InetDiagMsgExtra::parse(record);
+                extras.offset = *offset;
+                extras
+            })
+            .collect();
+        let sample = Sample {
+            time: Timestamp::new(self.time),
+            source: self.source.as_deref(),
+            samples,
+            duration: duration.as_micros() as u64,
+            period: self.period.map(|period| period.as_micros() as u64),
+            unscheduled: self.unscheduled,
+            stats: self.stats,
+        };
+        self.buf.clear();
+        match self.encoding {
+            Encoding::Json => serde_json::to_writer(&mut self.buf, &sample).unwrap(),
+            Encoding::Cbor => ciborium::into_writer(&sample, &mut self.buf).unwrap(),
+        }
+        if let Err(err) = self.publish() {
+            crate::diagnostic!("mqtt publish to {}: {err}", self.url);
+            self.stream = None;
+        }
+    }
+}
+
+impl Drop for MqttOutput {
+    fn drop(&mut self) {
+        if let Some(stream) = &mut self.stream {
+            let _ = write_packet(stream, DISCONNECT, &[]);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    os::{
+        fd::{FromRawFd, OwnedFd, RawFd},
+        linux::net::SocketAddrExt,
+        unix::net::UnixDatagram,
+    },
+    time::Duration,
+};
+
+/// First file descriptor passed by socket activation
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Connection to the service manager (systemd), if tcpdiag runs as a service
+/// with `Type=notify` (and optionally `WatchdogSec=`).
+pub struct Notifier {
+    socket: UnixDatagram,
+    /// Interval after which systemd considers the service hung
+    watchdog: Option<Duration>,
+    ready: bool,
+}
+
+impl Notifier {
+    /// Connects to `$NOTIFY_SOCKET`, if set.
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var_os("NOTIFY_SOCKET")?;
+        let path = path.to_str()?;
+        let socket = UnixDatagram::unbound().unwrap();
+        let result = match path.strip_prefix('@') {
+            Some(name) => std::os::unix::net::SocketAddr::from_abstract_name(name)
+                .and_then(|addr| socket.connect_addr(&addr)),
+            None => socket.connect(path),
+        };
+        if let Err(err) = result {
+            crate::diagnostic!("NOTIFY_SOCKET {path}: {err}");
+            return None;
+        }
+        // The watchdog applies to this process unless WATCHDOG_PID names another.
+        let for_us =
+            std::env::var("WATCHDOG_PID").map_or(true, |pid| pid == std::process::id().to_string());
+        let watchdog = std::env::var("WATCHDOG_USEC")
+            .ok()
+            .filter(|_| for_us)
+            .and_then(|usec| usec.parse().ok())
+            .map(Duration::from_micros);
+        Some(Self {
+            socket,
+            watchdog,
+            ready: false,
+        })
+    }
+
+    pub fn watchdog(&self) -> Option<Duration> {
+        self.watchdog
+    }
+
+    fn send(&self, state: &str) {
+        // A service manager that went away must not stop the capture.
+        let _ = self.socket.send(state.as_bytes());
+    }
+
+    /// Reports a completed sample: the first one signals readiness, and each
+    /// one pets the watchdog.
+    pub fn sampled(&mut self) {
+        if !self.ready {
+            self.send("READY=1");
+            self.ready = true;
+        }
+        if self.watchdog.is_some() {
+            self.send("WATCHDOG=1");
+        }
+    }
+
+    pub fn stopping(&self) {
+        self.send("STOPPING=1");
+    }
+}
+
+/// Takes the sockets passed by systemd socket activation (from a `.socket`
+/// unit), so a listening mode can bind privileged ports without running as
+/// root. Returns nothing unless `$LISTEN_PID` is this process. The variables
+/// are removed, so the sockets are taken at most once.
+pub fn listen_fds() -> Vec<OwnedFd> {
+    let for_us = std::env::var("LISTEN_PID").is_ok_and(|pid| pid == std::process::id().to_string());
+    let count: RawFd = std::env::var("LISTEN_FDS")
+        .ok()
+        .filter(|_| for_us)
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(0);
+    for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        std::env::remove_var(name);
+    }
+    (LISTEN_FDS_START..LISTEN_FDS_START + count)
+        .map(|fd| {
+            // Keep the sockets from being inherited by child processes.
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+            unsafe { OwnedFd::from_raw_fd(fd) }
+        })
+        .collect()
+}
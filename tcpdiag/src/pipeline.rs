@@ -0,0 +1,526 @@
+use std::{
+    collections::VecDeque,
+    ops::Range,
+    sync::{
+        mpsc::{sync_channel, Receiver, SyncSender},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, SystemTime},
+};
+
+use clap::ValueEnum;
+
+use crate::{diagnostic, Collector, SampleStats};
+
+/// Batches in flight between the threads
+const DEPTH: usize = 4;
+/// Message bytes after which a batch is sent (at the end of a sample)
+const BATCH_SIZE: usize = 1 << 20;
+
+enum Event {
+    Metadata(Vec<(String, String)>),
+    Source(Range<usize>),
+    Start(SystemTime),
+    Out(Range<usize>),
+    Period(Duration),
+    Unscheduled,
+    Stats(SampleStats),
+    Offset(Duration),
+    End(Duration),
+}
+
+/// Calls to a collector, with the messages and sources in one buffer.
+#[derive(Default)]
+struct Batch {
+    events: Vec<Event>,
+    data: Vec<u8>,
+}
+
+impl Batch {
+    fn push_data(&mut self, data: &[u8]) -> Range<usize> {
+        let start = self.data.len();
+        self.data.extend_from_slice(data);
+        start..self.data.len()
+    }
+
+    /// Memory used by the batch (without spare capacity)
+    fn size(&self) -> usize {
+        self.data.len() + self.events.len() * std::mem::size_of::<Event>()
+    }
+
+    /// Adds `count` to the dropped samples in the stats of the batch.
+    fn add_dropped(&mut self, count: u32) {
+        for event in &mut self.events {
+            if let Event::Stats(stats) = event {
+                stats.dropped += count;
+            }
+        }
+    }
+
+    /// Takes over the `metadata` events and the `count` of a dropped sample
+    /// before it. Returns by how much the size of the batch grew.
+    fn take_dropped(&mut self, metadata: Vec<Event>, count: u32) -> usize {
+        let size = self.size();
+        self.events.splice(0..0, metadata);
+        self.add_dropped(count);
+        self.size() - size
+    }
+
+    /// Returns a copy with the records reduced to tcp_info and tags.
+    fn shortened(&self) -> Batch {
+        let mut batch = Batch::default();
+        let mut record = Vec::new();
+        for event in &self.events {
+            match event {
+                Event::Out(range) => {
+                    crate::data::shorten(&self.data[range.clone()], &mut record);
+                    batch.out(&record);
+                }
+                Event::Source(range) => {
+                    batch.source(std::str::from_utf8(&self.data[range.clone()]).unwrap())
+                }
+                Event::Metadata(metadata) => batch.metadata(metadata),
+                Event::Start(time) => batch.start(*time),
+                Event::Period(period) => batch.period(*period),
+                Event::Unscheduled => batch.unscheduled(),
+                Event::Stats(stats) => batch.stats(stats),
+                Event::Offset(offset) => batch.offset(*offset),
+                Event::End(duration) => batch.end(*duration),
+            }
+        }
+        batch
+    }
+
+    fn replay<C: Collector>(&self, mut writer: C) {
+        for event in &self.events {
+            match event {
+                Event::Metadata(metadata) => writer.metadata(metadata),
+                Event::Source(range) => {
+                    writer.source(std::str::from_utf8(&self.data[range.clone()]).unwrap())
+                }
+                Event::Start(time) => writer.start(*time),
+                Event::Out(range) => writer.out(&self.data[range.clone()]),
+                Event::Period(period) => writer.period(*period),
+                Event::Unscheduled => writer.unscheduled(),
+                Event::Stats(stats) => writer.stats(stats),
+                Event::Offset(offset) => writer.offset(*offset),
+                Event::End(duration) => writer.end(*duration),
+            }
+        }
+    }
+}
+
+/// Collector that passes the calls in batches to the thread running
+/// `pipeline`. Used buffers are returned for reuse.
+pub struct Pipe {
+    batch: Batch,
+    tx: SyncSender<Batch>,
+    free: Receiver<Batch>,
+}
+
+impl Pipe {
+    fn send(&mut self) {
+        let empty = self.free.try_recv().unwrap_or_default();
+        let batch = std::mem::replace(&mut self.batch, empty);
+        // The receiver only hangs up if the writer panicked.
+        let _ = self.tx.send(batch);
+    }
+}
+
+impl Collector for Batch {
+    fn out(&mut self, data: &[u8]) {
+        let range = self.push_data(data);
+        self.events.push(Event::Out(range));
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.events.push(Event::Start(time));
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.events.push(Event::End(duration));
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.events.push(Event::Period(period));
+    }
+
+    fn unscheduled(&mut self) {
+        self.events.push(Event::Unscheduled);
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.events.push(Event::Stats(*stats));
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.events.push(Event::Offset(offset));
+    }
+
+    fn source(&mut self, source: &str) {
+        let range = self.push_data(source.as_bytes());
+        self.events.push(Event::Source(range));
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.events.push(Event::Metadata(metadata.to_vec()));
+    }
+}
+
+impl Collector for Pipe {
+    fn out(&mut self, data: &[u8]) {
+        self.batch.out(data);
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.batch.start(time);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.batch.end(duration);
+        if self.batch.data.len() >= BATCH_SIZE {
+            self.send();
+        }
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.batch.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.batch.unscheduled();
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.batch.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.batch.offset(offset);
+    }
+
+    fn source(&mut self, source: &str) {
+        self.batch.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.batch.metadata(metadata);
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        if !self.batch.events.is_empty() {
+            self.send();
+        }
+    }
+}
+
+/// Runs `read` (parsing the input) in another thread, while the calls it
+/// makes on the `Pipe` are replayed to `writer` (formatting the output) in
+/// this thread.
+pub fn pipeline<C: Collector>(read: impl FnOnce(Pipe) + Send, mut writer: C) {
+    let (tx, rx) = sync_channel(DEPTH);
+    let (free_tx, free) = sync_channel(DEPTH + 2);
+    std::thread::scope(|scope| {
+        let reader = scope.spawn(move || {
+            read(Pipe {
+                batch: Batch::default(),
+                tx,
+                free,
+            })
+        });
+        for mut batch in rx {
+            batch.replay(&mut writer);
+            batch.events.clear();
+            batch.data.clear();
+            let _ = free_tx.try_send(batch);
+        }
+        if let Err(err) = reader.join() {
+            std::panic::resume_unwind(err);
+        }
+    });
+}
+
+/// What a capture does with a sample while the output stalls and the queue
+/// is full
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum QueuePolicy {
+    /// Wait until the output catches up, which delays the next samples
+    #[default]
+    Block,
+    /// Discard the oldest queued samples
+    DropOldest,
+    /// Reduce the records of the sample to tcp_info and tags, then discard
+    /// the oldest queued samples if it still doesn't fit
+    Shorten,
+}
+
+/// Samples between the sampling and the writing thread
+#[derive(Default)]
+struct QueueState {
+    samples: VecDeque<Batch>,
+    /// Sum of the sizes of the queued samples
+    size: usize,
+    dropped: u64,
+    shortened: u64,
+    /// Set by the sampling thread when it is done
+    closed: bool,
+    /// Set by the writing thread if it failed
+    gone: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<QueueState>,
+    changed: Condvar,
+}
+
+impl Shared {
+    /// Locks the state, which stays consistent if a thread panicked.
+    fn lock(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Collector that queues each sample for the writing thread of `queue`,
+/// holding at most `limit` bytes (but at least one sample).
+pub struct Queue {
+    batch: Batch,
+    shared: Arc<Shared>,
+    policy: QueuePolicy,
+    limit: usize,
+}
+
+impl Queue {
+    fn push(&mut self) {
+        let mut batch = std::mem::take(&mut self.batch);
+        let mut state = self.shared.lock();
+        let fits = |state: &QueueState, batch: &Batch| {
+            state.samples.is_empty() || state.size + batch.size() <= self.limit
+        };
+        match self.policy {
+            QueuePolicy::Block => {
+                while !fits(&state, &batch) && !state.gone {
+                    state = self
+                        .shared
+                        .changed
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+            }
+            QueuePolicy::DropOldest | QueuePolicy::Shorten => {
+                if matches!(self.policy, QueuePolicy::Shorten) && !fits(&state, &batch) {
+                    batch = batch.shortened();
+                    state.shortened += 1;
+                }
+                while !fits(&state, &batch) {
+                    let dropped = state.samples.pop_front().unwrap();
+                    state.size -= dropped.size();
+                    state.dropped += 1;
+                    // The capture metadata is kept with the next sample, whose
+                    // stats count the dropped samples.
+                    let mut count = 1;
+                    let mut metadata = Vec::new();
+                    for event in dropped.events {
+                        match event {
+                            Event::Metadata(_) => metadata.push(event),
+                            Event::Stats(stats) => count += stats.dropped,
+                            _ => (),
+                        }
+                    }
+                    match state.samples.front_mut() {
+                        Some(next) => {
+                            let grown = next.take_dropped(metadata, count);
+                            state.size += grown;
+                        }
+                        None => {
+                            batch.take_dropped(metadata, count);
+                        }
+                    }
+                }
+            }
+        }
+        if state.gone {
+            drop(state);
+            panic!("the output failed");
+        }
+        state.size += batch.size();
+        state.samples.push_back(batch);
+        self.shared.changed.notify_all();
+    }
+}
+
+impl Collector for Queue {
+    fn out(&mut self, data: &[u8]) {
+        self.batch.out(data);
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.batch.start(time);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.batch.end(duration);
+        self.push();
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.batch.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.batch.unscheduled();
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.batch.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.batch.offset(offset);
+    }
+
+    fn source(&mut self, source: &str) {
+        self.batch.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.batch.metadata(metadata);
+    }
+}
+
+impl Drop for Queue {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        if !self.batch.events.is_empty() {
+            let batch = std::mem::take(&mut self.batch);
+            state.size += batch.size();
+            state.samples.push_back(batch);
+        }
+        state.closed = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+/// Marks the queue as gone if the writing thread panics, so that the
+/// sampling thread stops instead of waiting for it.
+struct Writing<'a>(&'a Shared);
+
+impl Drop for Writing<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.lock().gone = true;
+            self.0.changed.notify_all();
+        }
+    }
+}
+
+/// Runs `capture` in another thread, whose samples are queued for `writer`
+/// in this thread, up to `limit` bytes. While the queue is full, `policy`
+/// applies. Dropped and shortened samples are reported on stderr once the
+/// output catches up.
+pub fn queue<C: Collector>(
+    capture: impl FnOnce(Queue) + Send,
+    policy: QueuePolicy,
+    limit: usize,
+    mut writer: C,
+) {
+    let shared = Arc::new(Shared::default());
+    let queue = Queue {
+        batch: Batch::default(),
+        shared: shared.clone(),
+        policy,
+        limit,
+    };
+    std::thread::scope(|scope| {
+        let sampler = scope.spawn(move || capture(queue));
+        // Signals interrupt the sleep of the sampling thread.
+        crate::signal::block_in_thread(true);
+        let writing = Writing(&shared);
+        let mut reported = (0, 0);
+        loop {
+            let mut state = writing.0.lock();
+            let batch = loop {
+                match state.samples.pop_front() {
+                    Some(batch) => break Some(batch),
+                    None if state.closed => break None,
+                    None => {
+                        state = writing
+                            .0
+                            .changed
+                            .wait(state)
+                            .unwrap_or_else(PoisonError::into_inner)
+                    }
+                }
+            };
+            let Some(batch) = batch else {
+                break;
+            };
+            state.size -= batch.size();
+            if state.closed {
+                // Once the capture stopped, a second signal terminates.
+                crate::signal::block_in_thread(false);
+            }
+            let counts = (state.dropped, state.shortened);
+            writing.0.changed.notify_all();
+            drop(state);
+            if counts != reported {
+                diagnostic!(
+                    "output stalled: {} samples dropped, {} shortened",
+                    counts.0,
+                    counts.1
+                );
+                reported = counts;
+            }
+            batch.replay(&mut writer);
+        }
+        if let Err(err) = sampler.join() {
+            std::panic::resume_unwind(err);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(queue: &mut Queue, metadata: bool) {
+        if metadata {
+            queue.metadata(&[("hostname".into(), "test".into())]);
+        }
+        queue.start(SystemTime::UNIX_EPOCH);
+        queue.out(&[0; 100]);
+        queue.stats(&SampleStats::default());
+        queue.end(Duration::ZERO);
+    }
+
+    #[test]
+    fn drop_oldest_keeps_size() {
+        let shared = Arc::new(Shared::default());
+        let mut queue = Queue {
+            batch: Batch::default(),
+            shared: shared.clone(),
+            policy: QueuePolicy::DropOldest,
+            limit: 0,
+        };
+        sample(&mut queue, true);
+        queue.limit = shared.lock().size * 2;
+        sample(&mut queue, false);
+        // Drops the first sample, whose metadata moves to the queued second.
+        sample(&mut queue, false);
+        let mut state = shared.lock();
+        assert_eq!(state.dropped, 1);
+        assert_eq!(state.samples.len(), 2);
+        let first = &state.samples[0];
+        assert!(matches!(first.events[0], Event::Metadata(_)));
+        assert!(first
+            .events
+            .iter()
+            .any(|event| matches!(event, Event::Stats(stats) if stats.dropped == 1)));
+        while let Some(batch) = state.samples.pop_front() {
+            state.size -= batch.size();
+        }
+        assert_eq!(state.size, 0);
+    }
+}
@@ -0,0 +1,261 @@
+//! Output plugins, which are shared libraries loaded with `--plugin`.
+//!
+//! A plugin exports the symbol `TCPDIAG_PLUGIN`, a `PluginVTable` with the
+//! `PLUGIN_ABI_VERSION` it was built for. Its functions are called like the
+//! methods of `Collector`, with the state returned by `create`. Times are in
+//! microseconds (since the Unix epoch for `start`), strings are UTF-8
+//! `PluginStr`s, and a socket is an `inet_diag_msg` followed by its attributes,
+//! like the records of the binary format. The optional functions may be
+//! null. Plugins in Rust implement `Plugin` and use `export_plugin!`.
+
+use std::{
+    ffi::{c_char, c_void, CStr, CString},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{Collector, SampleStats};
+
+/// Version of `PluginVTable`, which must match to load a plugin
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// Name of the exported `PluginVTable`
+const SYMBOL: &CStr = c"TCPDIAG_PLUGIN";
+
+type State = *mut c_void;
+
+/// UTF-8 string, not NUL-terminated
+#[repr(C)]
+pub struct PluginStr {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl PluginStr {
+    fn new(s: &str) -> Self {
+        Self {
+            ptr: s.as_ptr(),
+            len: s.len(),
+        }
+    }
+
+    /// # Safety
+    /// It must point to valid UTF-8 that outlives `'a`.
+    unsafe fn as_str<'a>(&self) -> &'a str {
+        std::str::from_utf8_unchecked(std::slice::from_raw_parts(self.ptr, self.len))
+    }
+}
+
+#[repr(C)]
+pub struct PluginVTable {
+    pub abi_version: u32,
+    /// Creates the state from the `--plugin-arg` (a C string, or null), or
+    /// returns null on failure.
+    pub create: unsafe extern "C" fn(arg: *const c_char) -> State,
+    /// Writes pending output and frees the state.
+    pub destroy: unsafe extern "C" fn(state: State),
+    pub start: unsafe extern "C" fn(state: State, time: u64),
+    pub out: unsafe extern "C" fn(state: State, data: *const u8, len: usize),
+    pub end: unsafe extern "C" fn(state: State, duration: u64),
+    pub period: Option<unsafe extern "C" fn(state: State, period: u64)>,
+    pub unscheduled: Option<unsafe extern "C" fn(state: State)>,
+    pub source: Option<unsafe extern "C" fn(state: State, source: PluginStr)>,
+    /// Gets the metadata as `count` pairs of key and value.
+    pub metadata:
+        Option<unsafe extern "C" fn(state: State, entries: *const [PluginStr; 2], count: usize)>,
+    pub stats: Option<unsafe extern "C" fn(state: State, stats: *const SampleStats)>,
+    pub offset: Option<unsafe extern "C" fn(state: State, offset: u64)>,
+}
+
+/// Collector that can be built as plugin with `export_plugin!`.
+pub trait Plugin: Collector + Sized {
+    fn new(arg: Option<&str>) -> Option<Self>;
+}
+
+fn micros(time: u64) -> Duration {
+    Duration::from_micros(time)
+}
+
+/// The vtable of a plugin type, for `export_plugin!`.
+pub const fn vtable<P: Plugin>() -> PluginVTable {
+    unsafe extern "C" fn create<P: Plugin>(arg: *const c_char) -> State {
+        let arg = (!arg.is_null()).then(|| CStr::from_ptr(arg).to_str().unwrap());
+        match P::new(arg) {
+            Some(plugin) => Box::into_raw(Box::new(plugin)).cast(),
+            None => std::ptr::null_mut(),
+        }
+    }
+    unsafe extern "C" fn destroy<P: Plugin>(state: State) {
+        drop(Box::from_raw(state.cast::<P>()));
+    }
+    unsafe extern "C" fn start<P: Plugin>(state: State, time: u64) {
+        (*state.cast::<P>()).start(UNIX_EPOCH + micros(time));
+    }
+    unsafe extern "C" fn out<P: Plugin>(state: State, data: *const u8, len: usize) {
+        (*state.cast::<P>()).out(std::slice::from_raw_parts(data, len));
+    }
+    unsafe extern "C" fn end<P: Plugin>(state: State, duration: u64) {
+        (*state.cast::<P>()).end(micros(duration));
+    }
+    unsafe extern "C" fn period<P: Plugin>(state: State, period: u64) {
+        (*state.cast::<P>()).period(micros(period));
+    }
+    unsafe extern "C" fn unscheduled<P: Plugin>(state: State) {
+        (*state.cast::<P>()).unscheduled();
+    }
+    unsafe extern "C" fn source<P: Plugin>(state: State, source: PluginStr) {
+        (*state.cast::<P>()).source(source.as_str());
+    }
+    unsafe extern "C" fn metadata<P: Plugin>(
+        state: State,
+        entries: *const [PluginStr; 2],
+        count: usize,
+    ) {
+        let metadata: Vec<_> = std::slice::from_raw_parts(entries, count)
+            .iter()
+            .map(|[key, value]| (key.as_str().into(), value.as_str().into()))
+            .collect();
+        (*state.cast::<P>()).metadata(&metadata);
+    }
+    unsafe extern "C" fn stats<P: Plugin>(state: State, stats: *const SampleStats) {
+        (*state.cast::<P>()).stats(&*stats);
+    }
+    unsafe extern "C" fn offset<P: Plugin>(state: State, offset: u64) {
+        (*state.cast::<P>()).offset(micros(offset));
+    }
+    PluginVTable {
+        abi_version: PLUGIN_ABI_VERSION,
+        create: create::<P>,
+        destroy: destroy::<P>,
+        start: start::<P>,
+        out: out::<P>,
+        end: end::<P>,
+        period: Some(period::<P>),
+        unscheduled: Some(unscheduled::<P>),
+        source: Some(source::<P>),
+        metadata: Some(metadata::<P>),
+        stats: Some(stats::<P>),
+        offset: Some(offset::<P>),
+    }
+}
+
+/// Exports a `Plugin` type as `TCPDIAG_PLUGIN` (in a `cdylib` crate).
+#[macro_export]
+macro_rules! export_plugin {
+    ($plugin:ty) => {
+        #[no_mangle]
+        pub static TCPDIAG_PLUGIN: $crate::plugin::PluginVTable =
+            $crate::plugin::vtable::<$plugin>();
+    };
+}
+
+fn dlerror() -> String {
+    let err = unsafe { libc::dlerror() };
+    if err.is_null() {
+        return "unknown error".into();
+    }
+    unsafe { CStr::from_ptr(err) }.to_string_lossy().into()
+}
+
+/// Collector that forwards to a plugin. The library stays loaded until exit.
+pub struct PluginOutput {
+    vtable: &'static PluginVTable,
+    state: State,
+}
+
+impl PluginOutput {
+    /// Loads the plugin at `path` and creates its state with `arg`.
+    pub fn load(path: &Path, arg: Option<&str>) -> Result<Self, String> {
+        let name = CString::new(path.as_os_str().as_encoded_bytes()).map_err(|e| e.to_string())?;
+        let handle = unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(dlerror());
+        }
+        let symbol = unsafe { libc::dlsym(handle, SYMBOL.as_ptr()) };
+        if symbol.is_null() {
+            return Err(dlerror());
+        }
+        let vtable = unsafe { &*symbol.cast::<PluginVTable>() };
+        if vtable.abi_version != PLUGIN_ABI_VERSION {
+            return Err(format!(
+                "{}: plugin ABI version {}, expected {PLUGIN_ABI_VERSION}",
+                path.display(),
+                vtable.abi_version
+            ));
+        }
+        let arg = arg
+            .map(|arg| CString::new(arg).map_err(|e| e.to_string()))
+            .transpose()?;
+        let state =
+            unsafe { (vtable.create)(arg.as_ref().map_or(std::ptr::null(), |a| a.as_ptr())) };
+        if state.is_null() {
+            return Err(format!("{}: creating the plugin failed", path.display()));
+        }
+        Ok(Self { vtable, state })
+    }
+}
+
+fn as_micros(duration: Duration) -> u64 {
+    duration.as_micros() as u64
+}
+
+impl Collector for PluginOutput {
+    fn start(&mut self, time: SystemTime) {
+        let time = as_micros(time.duration_since(UNIX_EPOCH).unwrap());
+        unsafe { (self.vtable.start)(self.state, time) }
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        unsafe { (self.vtable.out)(self.state, data.as_ptr(), data.len()) }
+    }
+
+    fn end(&mut self, duration: Duration) {
+        unsafe { (self.vtable.end)(self.state, as_micros(duration)) }
+    }
+
+    fn period(&mut self, period: Duration) {
+        if let Some(f) = self.vtable.period {
+            unsafe { f(self.state, as_micros(period)) }
+        }
+    }
+
+    fn unscheduled(&mut self) {
+        if let Some(f) = self.vtable.unscheduled {
+            unsafe { f(self.state) }
+        }
+    }
+
+    fn source(&mut self, source: &str) {
+        if let Some(f) = self.vtable.source {
+            unsafe { f(self.state, PluginStr::new(source)) }
+        }
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        if let Some(f) = self.vtable.metadata {
+            let entries: Vec<_> = metadata
+                .iter()
+                .map(|(key, value)| [PluginStr::new(key), PluginStr::new(value)])
+                .collect();
+            unsafe { f(self.state, entries.as_ptr(), entries.len()) }
+        }
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        if let Some(f) = self.vtable.stats {
+            unsafe { f(self.state, stats) }
+        }
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        if let Some(f) = self.vtable.offset {
+            unsafe { f(self.state, as_micros(offset)) }
+        }
+    }
+}
+
+impl Drop for PluginOutput {
+    fn drop(&mut self) {
+        unsafe { (self.vtable.destroy)(self.state) }
+    }
+}
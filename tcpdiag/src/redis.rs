@@ -0,0 +1,217 @@
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+use crate::csv::{write_socket, CSV_HEADER};
+use crate::data::*;
+use crate::http::{connect, parse_authority};
+use crate::Collector;
+
+/// Commands sent before their replies are read, so that neither side blocks
+/// on a full socket buffer
+const BATCH: usize = 1000;
+
+/// `redis://host[:port][/db]` URL of a Redis server
+#[derive(Debug, Clone)]
+pub struct RedisUrl {
+    host: String,
+    port: u16,
+    db: Option<u32>,
+}
+
+impl FromStr for RedisUrl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let rest = s
+            .strip_prefix("redis://")
+            .ok_or_else(|| format!("expected a redis:// URL, got {s:?}"))?;
+        let (authority, db) = match rest.split_once('/') {
+            Some((authority, "")) => (authority, None),
+            Some((authority, db)) => {
+                let db = db.parse().map_err(|_| format!("invalid database {db:?}"))?;
+                (authority, Some(db))
+            }
+            None => (rest, None),
+        };
+        let (host, port) = parse_authority(authority, 6379)?;
+        Ok(Self { host, port, db })
+    }
+}
+
+impl fmt::Display for RedisUrl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "redis://{}:{}", self.host, self.port)?;
+        match self.db {
+            Some(db) => write!(f, "/{db}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Appends a command in RESP to `buf`.
+fn command<'a>(buf: &mut Vec<u8>, args: impl ExactSizeIterator<Item = &'a [u8]>) {
+    write!(buf, "*{}\r\n", args.len()).unwrap();
+    for arg in args {
+        write!(buf, "${}\r\n", arg.len()).unwrap();
+        buf.extend(arg);
+        buf.extend(b"\r\n");
+    }
+}
+
+/// Reads a reply, which is an error for error replies.
+fn read_reply(stream: &mut BufReader<TcpStream>) -> io::Result<Result<(), String>> {
+    let mut line = String::new();
+    if stream.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let line = line.trim_end();
+    match line.split_at_checked(1) {
+        Some(("+" | ":", _)) => Ok(Ok(())),
+        Some(("-", error)) => Ok(Err(error.into())),
+        Some(("$", len)) => {
+            // Bulk string (like the ID of an entry), or null for -1
+            if let Ok(len) = len.parse::<u64>() {
+                io::copy(&mut stream.take(len + 2), &mut io::sink())?;
+            }
+            Ok(Ok(()))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected reply {line:?}"),
+        )),
+    }
+}
+
+/// Collector that adds each socket to a Redis stream (with XADD), with the
+/// csv columns as fields: `time`, the socket columns, and `source` in merged
+/// captures. Unset columns (`_`) are omitted. The entries of a sample are sent
+/// together at its end. A sample that fails is reported and dropped, and the
+/// next sample reconnects after connection errors.
+pub struct RedisOutput {
+    url: RedisUrl,
+    key: String,
+    maxlen: Option<u64>,
+    stream: Option<BufReader<TcpStream>>,
+    time: SystemTime,
+    offset: Option<Duration>,
+    source: Option<String>,
+    /// XADD commands of the sample
+    commands: Vec<u8>,
+    /// End of each command in `commands`
+    ends: Vec<usize>,
+    buf: Vec<u8>,
+}
+
+impl RedisOutput {
+    /// Adds to the stream `key`, trimmed to about `maxlen` entries if given.
+    pub fn new(url: &RedisUrl, key: &str, maxlen: Option<u64>) -> Self {
+        Self {
+            url: url.clone(),
+            key: key.into(),
+            maxlen,
+            stream: None,
+            time: SystemTime::UNIX_EPOCH,
+            offset: None,
+            source: None,
+            commands: Vec::new(),
+            ends: Vec::new(),
+            buf: Vec::new(),
+        }
+    }
+
+    fn connect(&self) -> io::Result<BufReader<TcpStream>> {
+        let mut stream = BufReader::new(connect(&self.url.host, self.url.port)?);
+        if let Some(db) = self.url.db {
+            let mut select = Vec::new();
+            let db = db.to_string();
+            command(&mut select, [&b"SELECT"[..], db.as_bytes()].into_iter());
+            stream.get_mut().write_all(&select)?;
+            if let Err(err) = read_reply(&mut stream)? {
+                return Err(io::Error::other(format!("SELECT {db}: {err}")));
+            }
+        }
+        Ok(stream)
+    }
+
+    /// Sends the commands of the sample and reads their replies.
+    fn send(&mut self) -> io::Result<()> {
+        if self.stream.is_none() {
+            self.stream = Some(self.connect()?);
+        }
+        let stream = self.stream.as_mut().unwrap();
+        let mut error = None;
+        let mut start = 0;
+        for batch in self.ends.chunks(BATCH) {
+            let end = *batch.last().unwrap();
+            stream.get_mut().write_all(&self.commands[start..end])?;
+            start = end;
+            for _ in batch {
+                if let Err(err) = read_reply(stream)? {
+                    error.get_or_insert(err);
+                }
+            }
+        }
+        if let Some(err) = error {
+            crate::diagnostic!("redis XADD to {} at {}: {err}", self.key, self.url);
+        }
+        Ok(())
+    }
+}
+
+impl Collector for RedisOutput {
+    fn source(&mut self, source: &str) {
+        self.source = Some(source.into());
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.time = time;
+        self.offset = None;
+        self.commands.clear();
+        self.ends.clear();
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.offset = Some(offset);
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        let extras = InetDiagMsgExtra {
+            offset: self.offset.map(|offset| offset.as_micros() as u32),
+            ..InetDiagMsgExtra::parse(data)
+        };
+        self.buf.clear();
+        write_socket(self.time, extras, &mut self.buf);
+        let line = std::str::from_utf8(&self.buf).unwrap();
+        let maxlen = self.maxlen.map(|maxlen| maxlen.to_string());
+        let mut args: Vec<&[u8]> = vec![b"XADD", self.key.as_bytes()];
+        if let Some(maxlen) = &maxlen {
+            args.extend([&b"MAXLEN"[..], b"~", maxlen.as_bytes()]);
+        }
+        args.push(b"*");
+        for (column, value) in CSV_HEADER.split(' ').zip(line.split(' ')) {
+            if value != "_" {
+                args.extend([column.as_bytes(), value.as_bytes()]);
+            }
+        }
+        if let Some(source) = &self.source {
+            args.extend([&b"source"[..], source.as_bytes()]);
+        }
+        command(&mut self.commands, args.into_iter());
+        self.ends.push(self.commands.len());
+    }
+
+    fn end(&mut self, _duration: Duration) {
+        if self.ends.is_empty() {
+            return;
+        }
+        if let Err(err) = self.send() {
+            crate::diagnostic!("redis {}: {err}", self.url);
+            self.stream = None;
+        }
+    }
+}
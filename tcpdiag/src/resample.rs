@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use zerocopy::FromBytes;
+
+use crate::data::InetDiagMsg;
+use crate::Collector;
+
+/// Collector that forwards only every Nth sample, starting with the first.
+pub struct Every<C: Collector> {
+    inner: C,
+    n: u32,
+    index: u32,
+    source: Option<String>,
+}
+
+impl<C: Collector> Every<C> {
+    pub fn new(inner: C, n: std::num::NonZeroU32) -> Self {
+        Self {
+            inner,
+            n: n.get(),
+            index: 0,
+            source: None,
+        }
+    }
+
+    fn keep(&self) -> bool {
+        self.index.is_multiple_of(self.n)
+    }
+}
+
+impl<C: Collector> Collector for Every<C> {
+    fn source(&mut self, source: &str) {
+        self.source = Some(source.into());
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.inner.metadata(metadata);
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        if self.keep() {
+            if let Some(source) = self.source.take() {
+                self.inner.source(&source);
+            }
+            self.inner.start(time);
+        }
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        if self.keep() {
+            self.inner.out(data);
+        }
+    }
+
+    fn period(&mut self, period: Duration) {
+        if self.keep() {
+            self.inner.period(period * self.n);
+        }
+    }
+
+    fn unscheduled(&mut self) {
+        if self.keep() {
+            self.inner.unscheduled();
+        }
+    }
+
+    fn stats(&mut self, stats: &crate::SampleStats) {
+        if self.keep() {
+            self.inner.stats(stats);
+        }
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        if self.keep() {
+            self.inner.offset(offset);
+        }
+    }
+
+    fn end(&mut self, duration: Duration) {
+        if self.keep() {
+            self.inner.end(duration);
+        }
+        self.index = self.index.wrapping_add(1);
+    }
+}
+
+struct Bucket {
+    index: i128,
+    time: SystemTime,
+    source: Option<String>,
+    duration: Duration,
+    /// Last observation of each connection, in order of appearance
+    messages: Vec<Vec<u8>>,
+    cookies: HashMap<u64, usize>,
+}
+
+/// Collector that aggregates the samples within each interval (aligned to the
+/// Unix epoch) into one sample.
+///
+/// The aggregated sample has the time of the first sample in the interval,
+/// the summed durations, and the last observation of each connection (by
+/// cookie). Its period is the interval, and the stats and offsets of the dumps
+/// are dropped.
+/// The last interval is forwarded on drop.
+pub struct Resample<C: Collector> {
+    inner: C,
+    interval: Duration,
+    source: Option<String>,
+    bucket: Option<Bucket>,
+}
+
+impl<C: Collector> Resample<C> {
+    pub fn new(inner: C, interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "resample interval must not be zero");
+        Self {
+            inner,
+            interval,
+            source: None,
+            bucket: None,
+        }
+    }
+
+    fn flush(&mut self) {
+        let Some(bucket) = self.bucket.take() else {
+            return;
+        };
+        if let Some(source) = &bucket.source {
+            self.inner.source(source);
+        }
+        self.inner.start(bucket.time);
+        for message in &bucket.messages {
+            self.inner.out(message);
+        }
+        self.inner.period(self.interval);
+        self.inner.end(bucket.duration);
+    }
+}
+
+impl<C: Collector> Collector for Resample<C> {
+    fn source(&mut self, source: &str) {
+        self.source = Some(source.into());
+    }
+
+    /// Forwarded after the samples of the previous capture.
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.flush();
+        self.inner.metadata(metadata);
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        // Times before the epoch fall into intervals before it.
+        let micros = match time.duration_since(UNIX_EPOCH) {
+            Ok(since_epoch) => since_epoch.as_micros() as i128,
+            Err(err) => -(err.duration().as_micros() as i128),
+        };
+        let index = micros.div_euclid(self.interval.as_micros().max(1) as i128);
+        if self.bucket.as_ref().is_some_and(|b| b.index != index) {
+            self.flush();
+        }
+        let bucket = self.bucket.get_or_insert_with(|| Bucket {
+            index,
+            time,
+            source: None,
+            duration: Duration::ZERO,
+            messages: Vec::new(),
+            cookies: HashMap::new(),
+        });
+        if self.source.is_some() {
+            bucket.source.clone_from(&self.source);
+        }
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        let bucket = self.bucket.as_mut().unwrap();
+        let (msg, _) = InetDiagMsg::ref_from_prefix(data).unwrap();
+        match bucket.cookies.get(&msg.id.cookie.get()) {
+            Some(&i) => bucket.messages[i] = data.to_vec(),
+            None => {
+                bucket
+                    .cookies
+                    .insert(msg.id.cookie.get(), bucket.messages.len());
+                bucket.messages.push(data.to_vec());
+            }
+        }
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.bucket.as_mut().unwrap().duration += duration;
+    }
+}
+
+impl<C: Collector> Drop for Resample<C> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
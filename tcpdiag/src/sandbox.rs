@@ -0,0 +1,110 @@
+use libc::sock_filter;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// System calls of the sample loop: netlink requests and replies, writing
+/// the output and log, sleeping (on a timerfd), allocating, and aborting on
+/// errors.
+const ALLOWED: &[libc::c_long] = &[
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_writev,
+    libc::SYS_close,
+    libc::SYS_sendto,
+    libc::SYS_sendmsg,
+    libc::SYS_recvfrom,
+    libc::SYS_recvmsg,
+    libc::SYS_ppoll,
+    libc::SYS_timerfd_create,
+    libc::SYS_timerfd_settime,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_nanosleep,
+    libc::SYS_gettimeofday,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_brk,
+    libc::SYS_futex,
+    libc::SYS_sched_yield,
+    libc::SYS_getrandom,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigaction,
+    libc::SYS_sigaltstack,
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_tgkill,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+];
+
+fn stmt(code: u32, k: u32) -> sock_filter {
+    jump(code, k, 0, 0)
+}
+
+fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+    sock_filter {
+        code: code as u16,
+        jt,
+        jf,
+        k,
+    }
+}
+
+/// Restricts the process to the system calls of the sample loop (for
+/// `--sandbox`), which must have opened its sockets and files already.
+/// Other calls fail with EPERM, so that tcpdiag reports what it attempted,
+/// and calls of another architecture or ABI kill the process.
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub fn sandbox() {
+    use libc::*;
+    let ret = |action: u32| stmt(BPF_RET | BPF_K, action);
+    let mut filter = vec![
+        stmt(BPF_LD | BPF_W | BPF_ABS, 4), // seccomp_data.arch
+        jump(BPF_JMP | BPF_JEQ | BPF_K, AUDIT_ARCH, 1, 0),
+        ret(SECCOMP_RET_KILL_PROCESS),
+        stmt(BPF_LD | BPF_W | BPF_ABS, 0), // seccomp_data.nr
+    ];
+    // x32 system calls have the same architecture, but other numbers.
+    #[cfg(target_arch = "x86_64")]
+    filter.extend([
+        jump(BPF_JMP | BPF_JGE | BPF_K, 0x4000_0000, 0, 1),
+        ret(SECCOMP_RET_KILL_PROCESS),
+    ]);
+    for (i, &nr) in ALLOWED.iter().enumerate() {
+        let to_allow = ALLOWED.len() - i;
+        filter.push(jump(
+            BPF_JMP | BPF_JEQ | BPF_K,
+            nr as u32,
+            to_allow as u8,
+            0,
+        ));
+    }
+    filter.push(ret(SECCOMP_RET_ERRNO | EPERM as u32));
+    filter.push(ret(SECCOMP_RET_ALLOW));
+    let prog = sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+    unsafe {
+        if prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+            panic!("PR_SET_NO_NEW_PRIVS: {}", std::io::Error::last_os_error());
+        }
+        if syscall(SYS_seccomp, SECCOMP_SET_MODE_FILTER, 0, &prog) != 0 {
+            panic!("seccomp: {}", std::io::Error::last_os_error());
+        }
+    }
+}
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn sandbox() {
+    panic!("--sandbox is not supported on this architecture");
+}
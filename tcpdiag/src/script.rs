@@ -0,0 +1,175 @@
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use rhai::{Dynamic, Engine, Scope, AST};
+use serde_json::Value;
+
+use crate::data::*;
+use crate::{Collector, SampleStats};
+
+/// Applies the changes from `before` to `after` to `value`, so that values
+/// that the script left alone keep their exact representation (rhai has no
+/// unsigned integers).
+fn patch(value: &mut Value, before: &Value, after: &Value) {
+    match (value, before, after) {
+        (Value::Object(value), Value::Object(before), Value::Object(after)) => {
+            value.retain(|key, _| after.contains_key(key));
+            for (key, new) in after {
+                match (value.get_mut(key), before.get(key)) {
+                    (Some(value), Some(old)) => patch(value, old, new),
+                    _ => {
+                        value.insert(key.clone(), new.clone());
+                    }
+                }
+            }
+        }
+        (value, before, after) => {
+            if before != after {
+                *value = after.clone();
+            }
+        }
+    }
+}
+
+/// Collector that runs a rhai script for each socket before passing it on.
+/// The script sees the socket as object map `record`, like in json output
+/// (with an empty `record.tags` if it has no tags). It drops the socket by
+/// evaluating to `false`, tags it by setting `record.tags.KEY` (values are
+/// converted to strings), and rewrites it by assigning to other fields. A
+/// changed socket is rebuilt from its fields, without the attributes that
+/// tcpdiag doesn't model. Script errors are reported, and the socket is
+/// passed on unchanged. `print` and `debug` write to stderr.
+pub struct Script<C: Collector> {
+    inner: C,
+    path: String,
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    buf: Vec<u8>,
+}
+
+impl<C: Collector> Script<C> {
+    pub fn new(path: &Path, inner: C) -> Self {
+        let mut engine = Engine::new();
+        engine.on_print(|s| eprintln!("{s}"));
+        engine.on_debug(|s, _, pos| eprintln!("{pos:?}: {s}"));
+        let ast = engine
+            .compile_file(path.into())
+            .unwrap_or_else(|err| panic!("{}: {err}", path.display()));
+        let mut scope = Scope::new();
+        scope.push("record", ());
+        Self {
+            inner,
+            path: path.display().to_string(),
+            engine,
+            ast,
+            scope,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Runs the script for a socket, returning whether to keep it and its
+    /// fields if they were changed.
+    fn run(&mut self, data: &[u8]) -> Result<(bool, Option<Value>), String> {
+        let extras = InetDiagMsgExtra::parse(data);
+        let mut value = serde_json::to_value(&extras).unwrap();
+        value
+            .as_object_mut()
+            .unwrap()
+            .entry("tags")
+            .or_insert(Value::Object(Default::default()));
+        let record = rhai::serde::to_dynamic(&value).map_err(|err| err.to_string())?;
+        let before: Value = rhai::serde::from_dynamic(&record).map_err(|err| err.to_string())?;
+        self.scope.set_value("record", record);
+        let result = self
+            .engine
+            .eval_ast_with_scope::<Dynamic>(&mut self.scope, &self.ast);
+        let record = self.scope.get_value::<Dynamic>("record").unwrap();
+        // Forget the variables of the script.
+        self.scope.rewind(1);
+        let keep = result.map_err(|err| err.to_string())?.as_bool() != Ok(false);
+        let after: Value = rhai::serde::from_dynamic(&record).map_err(|err| err.to_string())?;
+        if !keep || after == before {
+            return Ok((keep, None));
+        }
+        patch(&mut value, &before, &after);
+        let fields = value.as_object_mut().ok_or("record is not an object map")?;
+        if let Some(Value::Object(tags)) = fields.get_mut("tags") {
+            for tag in tags.values_mut() {
+                let string = match &*tag {
+                    Value::String(_) => continue,
+                    Value::Null => String::new(),
+                    other => other.to_string(),
+                };
+                *tag = Value::String(string);
+            }
+        }
+        if fields
+            .get("tags")
+            .and_then(Value::as_object)
+            .is_some_and(|tags| tags.is_empty())
+        {
+            fields.remove("tags");
+        }
+        Ok((true, Some(value)))
+    }
+}
+
+impl<C: Collector> Collector for Script<C> {
+    fn out(&mut self, data: &[u8]) {
+        match self.run(data) {
+            Ok((false, _)) => (),
+            Ok((true, None)) => self.inner.out(data),
+            Ok((true, Some(value))) => match serde_json::from_value::<InetDiagMsgExtraOwned>(value)
+            {
+                Ok(extras) => {
+                    self.buf.clear();
+                    extras.write_to(&mut self.buf);
+                    self.inner.out(&self.buf);
+                }
+                Err(err) => {
+                    crate::diagnostic!("{}: invalid record: {err}", self.path);
+                    self.inner.out(data);
+                }
+            },
+            Err(err) => {
+                crate::diagnostic!("{}: {err}", self.path);
+                self.inner.out(data);
+            }
+        }
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.inner.start(time);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.inner.end(duration);
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.inner.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.inner.unscheduled();
+    }
+
+    fn source(&mut self, source: &str) {
+        self.inner.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.inner.metadata(metadata);
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.inner.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.inner.offset(offset);
+    }
+}
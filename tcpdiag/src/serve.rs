@@ -0,0 +1,143 @@
+use std::{
+    io::BufReader,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::mpsc::{sync_channel, RecvTimeoutError, SyncSender},
+    time::{Duration, SystemTime},
+};
+
+use crate::binary::try_read_binary;
+use crate::merge::{Recorder, Sample};
+use crate::validate::check_message;
+use crate::{Collector, SampleStats};
+
+/// Samples in flight from the agents to the writer
+const DEPTH: usize = 64;
+/// Interval in which the writer checks for a stop request while idle
+const POLL: Duration = Duration::from_millis(100);
+
+/// Collector that sends each complete sample of an agent to the writer, with
+/// the agent's address as source (prefixed to sources of the agent's input).
+/// Malformed sockets and records outside of a sample are dropped, as the
+/// writer expects valid captures.
+struct Forward {
+    peer: String,
+    recorder: Recorder,
+    in_sample: bool,
+    tx: SyncSender<Sample>,
+}
+
+impl Collector for Forward {
+    fn source(&mut self, source: &str) {
+        self.recorder.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.recorder.metadata(metadata);
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.recorder.start(time);
+        self.in_sample = true;
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        match check_message(data) {
+            Ok(()) if self.in_sample => self.recorder.out(data),
+            Ok(()) => (),
+            Err(err) => crate::diagnostic!("{}: {err}", self.peer),
+        }
+    }
+
+    fn period(&mut self, period: Duration) {
+        if self.in_sample {
+            self.recorder.period(period);
+        }
+    }
+
+    fn unscheduled(&mut self) {
+        if self.in_sample {
+            self.recorder.unscheduled();
+        }
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        if self.in_sample {
+            self.recorder.stats(stats);
+        }
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        if self.in_sample {
+            self.recorder.offset(offset);
+        }
+    }
+
+    fn end(&mut self, duration: Duration) {
+        if !std::mem::take(&mut self.in_sample) {
+            return;
+        }
+        self.recorder.end(duration);
+        for mut sample in self.recorder.samples.drain(..) {
+            sample.source = Some(match sample.source {
+                Some(source) => format!("{}/{source}", self.peer),
+                None => self.peer.clone(),
+            });
+            // The receiver only hangs up when the server stops.
+            let _ = self.tx.send(sample);
+        }
+    }
+}
+
+/// Reads the binary capture of an agent until it disconnects or sends a
+/// malformed record. A sample cut off by the disconnect is dropped.
+fn receive(stream: TcpStream, peer: SocketAddr, tx: SyncSender<Sample>) {
+    crate::diagnostic!("{peer}: connected");
+    let forward = Forward {
+        peer: peer.to_string(),
+        recorder: Recorder::default(),
+        in_sample: false,
+        tx,
+    };
+    match try_read_binary(BufReader::new(stream), forward) {
+        Ok(()) => crate::diagnostic!("{peer}: disconnected"),
+        Err(err) => crate::diagnostic!("{peer}: disconnected ({err})"),
+    }
+}
+
+/// Accepts binary captures streamed by remote agents (e.g. `tcpdiag -p 1 -o
+/// binary | nc collector 9033`) and passes their samples to the writer as
+/// they complete, tagged with the address of the agent, until a stop is
+/// requested (see `signal::handle_termination`). Each connection is read by
+/// its own thread, so a corrupt stream only ends its connection.
+pub fn serve<C: Collector>(listeners: Vec<TcpListener>, mut writer: C) {
+    let (tx, rx) = sync_channel(DEPTH);
+    for listener in listeners {
+        let tx = tx.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        crate::diagnostic!("accept: {err}");
+                        continue;
+                    }
+                };
+                let Ok(peer) = stream.peer_addr() else {
+                    continue;
+                };
+                // IPv4 agents on a dual-stack listener as 10.0.0.1:43210
+                let peer = SocketAddr::new(peer.ip().to_canonical(), peer.port());
+                let tx = tx.clone();
+                std::thread::spawn(move || receive(stream, peer, tx));
+            }
+        });
+    }
+    drop(tx);
+    while !crate::signal::stop_requested() {
+        match rx.recv_timeout(POLL) {
+            Ok(sample) => sample.replay(&mut writer),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    ptr::null_mut,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static SAMPLE_REQUESTED: AtomicBool = AtomicBool::new(false);
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_sample(_signal: libc::c_int) {
+    SAMPLE_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+extern "C" fn request_stop(_signal: libc::c_int) {
+    STOP_REQUESTED.store(true, Ordering::Relaxed);
+}
+
+/// Installs a handler without SA_RESTART, so it interrupts the sleep until
+/// the next period.
+fn install(signal: libc::c_int, handler: extern "C" fn(libc::c_int), flags: libc::c_int) {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler as usize;
+        action.sa_flags = flags;
+        libc::sigemptyset(&mut action.sa_mask);
+        let ret = libc::sigaction(signal, &action, null_mut());
+        assert!(ret == 0);
+    }
+}
+
+/// Installs the SIGUSR1 handler, which requests an extra sample.
+pub fn handle_sigusr1() {
+    install(libc::SIGUSR1, request_sample, 0);
+}
+
+/// Installs the SIGINT and SIGTERM handlers, which stop the capture after
+/// the current sample. A second signal terminates immediately.
+pub fn handle_termination() {
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        install(signal, request_stop, libc::SA_RESETHAND);
+    }
+}
+
+/// Changes the mask of this thread for the handled signals, returning the
+/// previous mask.
+fn mask(how: libc::c_int) -> libc::sigset_t {
+    unsafe {
+        let mut set: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut set);
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGUSR1] {
+            libc::sigaddset(&mut set, signal);
+        }
+        let mut previous = std::mem::zeroed();
+        let ret = libc::pthread_sigmask(how, &set, &mut previous);
+        assert!(ret == 0);
+        previous
+    }
+}
+
+/// Blocks (or unblocks) the handled signals in this thread, so that they
+/// interrupt the sleep of the sampling thread instead.
+pub fn block_in_thread(block: bool) {
+    mask(if block {
+        libc::SIG_BLOCK
+    } else {
+        libc::SIG_UNBLOCK
+    });
+}
+
+/// Blocks the handled signals in this thread until it is dropped, so that
+/// a wait can unblock them atomically (with `ppoll`) after checking for a
+/// request: a signal in between is then delivered during the wait.
+pub(crate) struct Blocked(libc::sigset_t);
+
+impl Blocked {
+    pub(crate) fn new() -> Self {
+        Self(mask(libc::SIG_BLOCK))
+    }
+
+    /// Mask of this thread before the signals were blocked
+    pub(crate) fn previous(&self) -> &libc::sigset_t {
+        &self.0
+    }
+}
+
+impl Drop for Blocked {
+    fn drop(&mut self) {
+        let ret = unsafe { libc::pthread_sigmask(libc::SIG_SETMASK, &self.0, null_mut()) };
+        assert!(ret == 0);
+    }
+}
+
+pub fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Whether the sleep until the next period should be interrupted
+pub fn interrupted() -> bool {
+    sample_requested() || stop_requested()
+}
+
+pub fn sample_requested() -> bool {
+    SAMPLE_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Returns whether an extra sample was requested, and resets the request.
+pub fn take_sample_request() -> bool {
+    SAMPLE_REQUESTED.swap(false, Ordering::Relaxed)
+}
@@ -0,0 +1,94 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::merge::Recorder;
+use crate::{Collector, SampleStats};
+
+/// Maximum rate at which `SmoothTime` adjusts the times to the wall time, like
+/// the slew rate of adjtime (500 ppm)
+const MAX_SLEW: f64 = 500e-6;
+
+/// Collector that replaces the wall time of each sample with its boottime
+/// plus an offset, which follows the wall time by at most 500 µs per second,
+/// so that the times are consistent with the time between the samples when
+/// the wall time was stepped (e.g. by NTP). The offset starts at the wall
+/// time of the first sample of each source, and again after a reboot. Samples
+/// without boottime (from captures of earlier versions) are passed on
+/// unchanged.
+pub struct SmoothTime<C: Collector> {
+    inner: C,
+    recorder: Recorder,
+    /// Boottime and offset of the wall time (in microseconds) of the last
+    /// sample of each source
+    clocks: HashMap<Option<String>, (u64, i64)>,
+}
+
+impl<C: Collector> SmoothTime<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            recorder: Recorder::default(),
+            clocks: HashMap::new(),
+        }
+    }
+
+    /// Smoothed time of a sample
+    fn time(&mut self, source: Option<String>, time: SystemTime, boottime: u64) -> SystemTime {
+        let wall = time.duration_since(UNIX_EPOCH).unwrap().as_micros() as i64;
+        let target = wall - boottime as i64;
+        let offset = match self.clocks.get(&source) {
+            Some(&(last, offset)) if boottime >= last => {
+                let max = ((boottime - last) as f64 * MAX_SLEW) as i64;
+                offset + (target - offset).clamp(-max, max)
+            }
+            _ => target,
+        };
+        self.clocks.insert(source, (boottime, offset));
+        UNIX_EPOCH + Duration::from_micros((boottime as i64 + offset) as u64)
+    }
+}
+
+impl<C: Collector> Collector for SmoothTime<C> {
+    fn source(&mut self, source: &str) {
+        self.recorder.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.recorder.metadata(metadata);
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.recorder.start(time);
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        self.recorder.out(data);
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.recorder.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.recorder.unscheduled();
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.recorder.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.recorder.offset(offset);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.recorder.end(duration);
+        let mut sample = self.recorder.samples.pop().unwrap();
+        if let Some(boottime) = sample.stats.map(|s| s.boottime).filter(|&b| b != 0) {
+            sample.time = self.time(sample.source.clone(), sample.time, boottime);
+        }
+        sample.replay(&mut self.inner);
+    }
+}
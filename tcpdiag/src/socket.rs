@@ -0,0 +1,154 @@
+use std::{cell::RefCell, collections::VecDeque, io};
+
+use netlink_sys::{Socket, SocketAddr};
+use zerocopy::{FromBytes, IntoBytes};
+
+use clap::ValueEnum;
+
+use crate::data::*;
+use crate::merge::Sample;
+use crate::Protocol;
+
+/// Datagram size up to which `Replay` packs the sockets of a dump
+const DATAGRAM_SIZE: usize = 8192;
+const NLM_F_MULTI: u16 = 2;
+
+/// The netlink operations of a capture on a sock_diag socket, so that
+/// `read_diag_sockets` can sample from other sources than the kernel.
+pub trait DiagSocket {
+    /// Sends a dump request.
+    fn send_request(&self, request: &[u8]) -> io::Result<()>;
+
+    /// Receives a datagram into `buf` (which is cleared first) up to its
+    /// capacity. Returns the full length of the datagram, which exceeds the
+    /// capacity if the datagram was truncated.
+    fn recv_datagram(&self, buf: &mut Vec<u8>) -> io::Result<usize>;
+}
+
+impl DiagSocket for Socket {
+    fn send_request(&self, request: &[u8]) -> io::Result<()> {
+        self.send_to(request, &SocketAddr::new(0, 0), 0).map(|_| ())
+    }
+
+    fn recv_datagram(&self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        buf.clear();
+        // With MSG_TRUNC, the full length is returned even if the datagram
+        // did not fit into the buffer.
+        self.recv_from(buf, libc::MSG_TRUNC).map(|(len, _)| len)
+    }
+}
+
+/// Socket that answers each dump request with the next of canned dumps
+/// (records as passed to `Collector::out`), for tests without root or
+/// reproducible runs. Like the kernel, it only replies with the sockets of
+/// the requested family, protocol (by the `protocol` tag of the records, TCP
+/// if untagged), and states, and ports if requested. Requests after the last
+/// dump get empty replies.
+///
+/// Each request pops the next dump, so every dump of a capture (see
+/// `dumps`) needs its own `Replay`, even if they replay the same samples.
+pub struct Replay {
+    dumps: RefCell<VecDeque<Vec<Vec<u8>>>>,
+    datagrams: RefCell<VecDeque<Vec<u8>>>,
+}
+
+impl Replay {
+    pub fn new(dumps: impl IntoIterator<Item = Vec<Vec<u8>>>) -> Self {
+        Self {
+            dumps: RefCell::new(dumps.into_iter().collect()),
+            datagrams: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    /// Socket that replays the sockets of each sample of a capture as a dump.
+    pub fn from_samples(samples: &[Sample]) -> Self {
+        Self::new(samples.iter().map(|sample| sample.messages.clone()))
+    }
+}
+
+/// Protocol number of a record, by its `protocol` tag
+fn protocol(attributes: &[u8]) -> u32 {
+    let tag = NlattrIter::new(attributes)
+        .filter(|attribute| attribute.hdr.nla_type == TCPDIAG_TAGS)
+        .flat_map(|attribute| Tags(&attribute.data).iter())
+        .find(|&(key, _)| key == "protocol");
+    let protocol = match tag {
+        Some((_, name)) => Protocol::from_str(name, false).ok(),
+        None => Some(Protocol::Tcp),
+    };
+    protocol.map_or(0, Protocol::number)
+}
+
+/// Whether the kernel would reply to `req` for `protocol` with the socket
+/// `msg` (with `attributes`)
+fn requested(req: &InetDiagReqV2, protocol: u32, msg: &InetDiagMsg, attributes: &[u8]) -> bool {
+    let port = |requested: u16, port: u16| requested == 0 || requested == port;
+    msg.family == req.family
+        && self::protocol(attributes) == protocol
+        && req.states & 1u32.checked_shl(msg.state.into()).unwrap_or(0) != 0
+        && port(req.id.sport.get(), msg.id.sport.get())
+        && port(req.id.dport.get(), msg.id.dport.get())
+}
+
+/// Appends a netlink message of a dump reply, with padding.
+fn push_message(datagram: &mut Vec<u8>, ty: u16, seq: u32, data: &[u8]) {
+    let hdr = nlmsghdr {
+        nlmsg_len: (std::mem::size_of::<nlmsghdr>() + data.len())
+            .try_into()
+            .unwrap(),
+        nlmsg_type: ty,
+        nlmsg_flags: NLM_F_MULTI,
+        nlmsg_seq: seq,
+        nlmsg_pid: 0,
+    };
+    datagram.extend(hdr.as_bytes());
+    datagram.extend(data);
+    while datagram.len() & 3 != 0 {
+        datagram.push(0);
+    }
+}
+
+impl DiagSocket for Replay {
+    fn send_request(&self, request: &[u8]) -> io::Result<()> {
+        let invalid = || io::Error::from(io::ErrorKind::InvalidInput);
+        let (encap, attributes) = Encap::read_from_prefix(request).map_err(|_| invalid())?;
+        if encap.hdr.nlmsg_type != SOCK_DIAG_BY_FAMILY {
+            return Err(invalid());
+        }
+        let protocol = NlattrIter::new(attributes)
+            .find(|attribute| attribute.hdr.nla_type == INET_DIAG_REQ_PROTOCOL)
+            .and_then(|attribute| attribute.data.try_into().ok())
+            .map_or(encap.data.protocol.into(), u32::from_ne_bytes);
+        let seq = encap.hdr.nlmsg_seq;
+        let dump = self.dumps.borrow_mut().pop_front().unwrap_or_default();
+        let mut datagrams = self.datagrams.borrow_mut();
+        let mut datagram = Vec::new();
+        for record in &dump {
+            let Ok((msg, attributes)) = InetDiagMsg::ref_from_prefix(record) else {
+                continue;
+            };
+            if !requested(&encap.data, protocol, msg, attributes) {
+                continue;
+            }
+            if !datagram.is_empty() && datagram.len() + record.len() > DATAGRAM_SIZE {
+                datagrams.push_back(std::mem::take(&mut datagram));
+            }
+            push_message(&mut datagram, SOCK_DIAG_BY_FAMILY, seq, record);
+        }
+        push_message(&mut datagram, NLMSG_DONE, seq, &0i32.to_ne_bytes());
+        datagrams.push_back(datagram);
+        Ok(())
+    }
+
+    fn recv_datagram(&self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let datagram = self
+            .datagrams
+            .borrow_mut()
+            .pop_front()
+            .ok_or(io::ErrorKind::WouldBlock)?;
+        buf.clear();
+        let len = datagram.len().min(buf.capacity());
+        buf.extend_from_slice(&datagram[..len]);
+        Ok(datagram.len())
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use zerocopy::FromBytes;
+
+use crate::data::InetDiagMsg;
+use crate::{Collector, SampleStats};
+
+/// Records of a connection in the current sample, with the receive offset
+/// in effect for each
+type Records = Vec<(Option<Duration>, Vec<u8>)>;
+
+/// Collector that forwards each connection (by cookie) to its own output,
+/// which is created on its first appearance. Samples without the connection
+/// are omitted in its output. The records of a sample are passed on at its
+/// end, so that at most `max_open` outputs are open: outputs of connections
+/// absent from a sample are closed, and if a sample has more connections,
+/// outputs are closed after their part of it. Closed outputs are created again
+/// with `append` set when their connection reappears.
+pub struct Split<F: FnMut(u64, bool) -> Box<dyn Collector>> {
+    create: F,
+    max_open: usize,
+    outputs: HashMap<u64, Box<dyn Collector>>,
+    /// Cookies of the connections whose output was created before
+    created: HashSet<u64>,
+    /// Connections of the current sample in order of appearance, by cookie
+    sample: Vec<(u64, Records)>,
+    /// Index of each connection in `sample`
+    index: HashMap<u64, usize>,
+    time: SystemTime,
+    period: Option<Duration>,
+    unscheduled: bool,
+    /// Stats of the whole dump, passed to each output of the sample
+    stats: Option<SampleStats>,
+    /// Receive offset of the following sockets
+    offset: Option<Duration>,
+    source: Option<String>,
+    /// Metadata of the capture, passed to each output on creation
+    metadata: Option<Vec<(String, String)>>,
+}
+
+impl<F: FnMut(u64, bool) -> Box<dyn Collector>> Split<F> {
+    pub fn new(max_open: usize, create: F) -> Self {
+        Self {
+            create,
+            max_open: max_open.max(1),
+            outputs: HashMap::new(),
+            created: HashSet::new(),
+            sample: Vec::new(),
+            index: HashMap::new(),
+            time: UNIX_EPOCH,
+            period: None,
+            unscheduled: false,
+            stats: None,
+            offset: None,
+            source: None,
+            metadata: None,
+        }
+    }
+
+    /// Returns the output of a connection, which is opened (closing another
+    /// one if too many are open) unless it is open already.
+    fn output(&mut self, cookie: u64) -> &mut Box<dyn Collector> {
+        if !self.outputs.contains_key(&cookie) {
+            if self.outputs.len() >= self.max_open {
+                // All open outputs have passed the current sample already.
+                let closed = *self.outputs.keys().next().unwrap();
+                self.outputs.remove(&closed);
+            }
+            let append = !self.created.insert(cookie);
+            let mut output = (self.create)(cookie, append);
+            if let Some(metadata) = self.metadata.as_ref().filter(|_| !append) {
+                output.metadata(metadata);
+            }
+            if let Some(source) = &self.source {
+                output.source(source);
+            }
+            self.outputs.insert(cookie, output);
+        }
+        self.outputs.get_mut(&cookie).unwrap()
+    }
+}
+
+impl<F: FnMut(u64, bool) -> Box<dyn Collector>> Collector for Split<F> {
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.metadata = Some(metadata.to_vec());
+        for output in self.outputs.values_mut() {
+            output.metadata(metadata);
+        }
+    }
+
+    fn source(&mut self, source: &str) {
+        self.source = Some(source.into());
+        for output in self.outputs.values_mut() {
+            output.source(source);
+        }
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.time = time;
+        self.period = None;
+        self.unscheduled = false;
+        self.stats = None;
+        self.offset = None;
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        let (msg, _) = InetDiagMsg::ref_from_prefix(data).unwrap();
+        let cookie = msg.id.cookie.get();
+        let index = *self.index.entry(cookie).or_insert_with(|| {
+            self.sample.push((cookie, Vec::new()));
+            self.sample.len() - 1
+        });
+        self.sample[index].1.push((self.offset, data.to_vec()));
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.period = Some(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.unscheduled = true;
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.stats = Some(*stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.offset = Some(offset);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.index.clear();
+        // Connections absent from this sample have likely been closed.
+        let sample = std::mem::take(&mut self.sample);
+        let present: HashSet<u64> = sample.iter().map(|&(cookie, _)| cookie).collect();
+        self.outputs.retain(|cookie, _| present.contains(cookie));
+        for (cookie, records) in sample {
+            let (time, period, unscheduled, stats) =
+                (self.time, self.period, self.unscheduled, self.stats);
+            let output = self.output(cookie);
+            output.start(time);
+            for (offset, data) in &records {
+                if let Some(offset) = offset {
+                    output.offset(*offset);
+                }
+                output.out(data);
+            }
+            if let Some(period) = period {
+                output.period(period);
+            }
+            if unscheduled {
+                output.unscheduled();
+            }
+            if let Some(stats) = &stats {
+                output.stats(stats);
+            }
+            output.end(duration);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::BTreeMap;
+
+use crate::data::*;
+use crate::histogram::Histogram;
+
+/// Aggregate statistics over the sockets of a sample.
+#[derive(Default, Debug)]
+pub struct Stats {
+    pub sockets: u64,
+    pub states: BTreeMap<u8, u64>,
+    pub families: BTreeMap<u8, u64>,
+    pub congs: BTreeMap<String, u64>,
+    /// Smoothed RTTs in microseconds, of connected sockets with tcp_info
+    pub rtt: Histogram,
+    pub total_retrans: u64,
+    pub segs_out: u64,
+    pub bytes_acked: u64,
+    pub bytes_received: u64,
+}
+
+impl Stats {
+    pub fn add(&mut self, extras: &InetDiagMsgExtra) {
+        self.sockets += 1;
+        *self.states.entry(extras.base.state).or_default() += 1;
+        *self.families.entry(extras.base.family).or_default() += 1;
+        if let Some(cong) = extras.cong {
+            *self.congs.entry(cong.into()).or_default() += 1;
+        }
+        if let Some(info) = extras.tcp_info.filter(|_| extras.base.state != TCP_LISTEN) {
+            self.rtt.record(info.rtt.into());
+            self.total_retrans += u64::from(info.total_retrans);
+            self.segs_out += u64::from(info.segs_out);
+            self.bytes_acked += info.bytes_acked.get();
+            self.bytes_received += info.bytes_received.get();
+        }
+    }
+
+    /// Retransmitted segments relative to all sent segments.
+    pub fn retrans_rate(&self) -> f64 {
+        if self.segs_out == 0 {
+            0.0
+        } else {
+            self.total_retrans as f64 / self.segs_out as f64
+        }
+    }
+
+    /// RTT percentile (0 to 100) in microseconds, using the nearest-rank
+    /// method (up to 1/16 too high, see `Histogram::percentile`).
+    pub fn rtt_percentile(&self, percentile: f64) -> Option<u32> {
+        self.rtt.percentile(percentile).map(|rtt| rtt as u32)
+    }
+
+    pub fn rtt_mean(&self) -> Option<f64> {
+        self.rtt.mean()
+    }
+}
@@ -0,0 +1,108 @@
+use std::{
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::data::*;
+use crate::human::{self, Unit};
+use crate::stats::Stats;
+use crate::timestamp::{TimeFormat, Timestamp};
+
+use crate::Collector;
+
+/// Prints aggregate statistics per sample instead of individual sockets.
+pub struct SummaryOutput<T: Write> {
+    writer: T,
+    time: SystemTime,
+    time_format: TimeFormat,
+    source: Option<String>,
+    stats: Stats,
+}
+
+crate::impl_output!(SummaryOutput<T>);
+
+impl<T: Write> SummaryOutput<T> {
+    pub fn new(writer: T) -> Self {
+        Self {
+            writer,
+            time: UNIX_EPOCH,
+            time_format: TimeFormat::default(),
+            source: None,
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn set_time_format(&mut self, time_format: TimeFormat) {
+        self.time_format = time_format;
+    }
+
+    fn write_counts<'a>(&mut self, title: &str, counts: impl Iterator<Item = (&'a str, u64)>) {
+        let counts: Vec<_> = counts
+            .map(|(name, count)| format!("{name} {count}"))
+            .collect();
+        writeln!(&mut self.writer, "{title:8} {}", counts.join(", ")).unwrap();
+    }
+}
+
+impl<T: Write> Collector for SummaryOutput<T> {
+    fn start(&mut self, time: SystemTime) {
+        self.time = time;
+        self.stats = Stats::default();
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        self.stats.add(&InetDiagMsgExtra::parse(data));
+    }
+
+    fn end(&mut self, duration: Duration) {
+        let stats = std::mem::take(&mut self.stats);
+        let time = Timestamp::new(self.time).display(&self.time_format);
+        writeln!(
+            &mut self.writer,
+            "time {time}{}: {} sockets in {}us",
+            self.source
+                .as_ref()
+                .map(|s| format!(" ({s})"))
+                .unwrap_or_default(),
+            stats.sockets,
+            duration.as_micros()
+        )
+        .unwrap();
+        let states = stats.states.iter().map(|(&s, &n)| (tcp_state_name(s), n));
+        self.write_counts("state:", states);
+        let families = stats.families.iter().map(|(&f, &n)| (family_name(f), n));
+        self.write_counts("family:", families);
+        let congs = stats.congs.iter().map(|(c, &n)| (&c[..], n));
+        self.write_counts("cong:", congs);
+        writeln!(
+            &mut self.writer,
+            "retrans: {} of {} segments ({:.3}%), acked {}, received {}",
+            stats.total_retrans,
+            stats.segs_out,
+            stats.retrans_rate() * 100.0,
+            human::format(Unit::Bytes, stats.bytes_acked),
+            human::format(Unit::Bytes, stats.bytes_received),
+        )
+        .unwrap();
+        if let Some(mean) = stats.rtt_mean() {
+            let rtt = |p| human::format(Unit::Micros, stats.rtt_percentile(p).unwrap().into());
+            writeln!(
+                &mut self.writer,
+                "rtt:     min {}, mean {}, p50 {}, p95 {}, p99 {}, max {}",
+                rtt(0.0),
+                human::format(Unit::Micros, mean as u64),
+                rtt(50.0),
+                rtt(95.0),
+                rtt(99.0),
+                rtt(100.0),
+            )
+            .unwrap();
+        }
+        writeln!(&mut self.writer).unwrap();
+        self.writer.flush().unwrap();
+    }
+
+    fn source(&mut self, source: &str) {
+        self.source = Some(source.into());
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    io::Write,
+    net::SocketAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::data::*;
+use crate::human;
+use crate::timestamp::{TimeFormat, Timestamp};
+
+use crate::Collector;
+
+/// Column header and the CSV column that determines its unit.
+const COLUMNS: [(&str, &str); 12] = [
+    ("state", ""),
+    ("local", ""),
+    ("peer", ""),
+    ("cong", ""),
+    ("rtt", "tcp_info.rtt"),
+    ("rttvar", "tcp_info.rttvar"),
+    ("cwnd", ""),
+    ("retrans", ""),
+    ("pacing", "tcp_info.pacing_rate"),
+    ("delivery", "tcp_info.delivery_rate"),
+    ("acked", "tcp_info.bytes_acked"),
+    ("received", "tcp_info.bytes_received"),
+];
+
+/// Aligned table for reading on a terminal. It cannot be converted back.
+pub struct TableOutput<T: Write> {
+    writer: T,
+    human: bool,
+    time: SystemTime,
+    time_format: TimeFormat,
+    source: Option<String>,
+    rows: Vec<[String; COLUMNS.len()]>,
+}
+
+crate::impl_output!(TableOutput<T>);
+
+impl<T: Write> TableOutput<T> {
+    pub fn new(writer: T, human: bool) -> Self {
+        Self {
+            writer,
+            human,
+            time: UNIX_EPOCH,
+            time_format: TimeFormat::default(),
+            source: None,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn set_time_format(&mut self, time_format: TimeFormat) {
+        self.time_format = time_format;
+    }
+
+    fn value(&self, column: usize, value: Option<u64>) -> String {
+        match value {
+            Some(value) if self.human => match human::unit(COLUMNS[column].1) {
+                Some(unit) => human::format(unit, value),
+                None => value.to_string(),
+            },
+            Some(value) => value.to_string(),
+            None => "-".into(),
+        }
+    }
+}
+
+impl<T: Write> Collector for TableOutput<T> {
+    fn start(&mut self, time: SystemTime) {
+        self.time = time;
+        self.rows.clear();
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        let extras = InetDiagMsgExtra::parse(data);
+        let base = extras.base;
+        let id = &base.id;
+        let local = SocketAddr::new(id.src.get(base.family), id.sport.get());
+        let peer = SocketAddr::new(id.dst.get(base.family), id.dport.get());
+        let info = extras.tcp_info;
+        let row = [
+            tcp_state_name(base.state).into(),
+            local.to_string(),
+            peer.to_string(),
+            extras.cong.unwrap_or("-").into(),
+            self.value(4, info.map(|i| i.rtt.into())),
+            self.value(5, info.map(|i| i.rttvar.into())),
+            self.value(6, info.map(|i| i.snd_cwnd.into())),
+            self.value(7, info.map(|i| i.total_retrans.into())),
+            self.value(8, info.map(|i| i.pacing_rate.get())),
+            self.value(9, info.map(|i| i.delivery_rate.get())),
+            self.value(10, info.map(|i| i.bytes_acked.get())),
+            self.value(11, info.map(|i| i.bytes_received.get())),
+        ];
+        self.rows.push(row);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        let time = Timestamp::new(self.time).display(&self.time_format);
+        writeln!(
+            &mut self.writer,
+            "time {time}{}: {} sockets in {}us",
+            self.source
+                .as_ref()
+                .map(|s| format!(" ({s})"))
+                .unwrap_or_default(),
+            self.rows.len(),
+            duration.as_micros()
+        )
+        .unwrap();
+        let header = COLUMNS.map(|(name, _)| name.to_string());
+        let mut widths = [0; COLUMNS.len()];
+        for row in std::iter::once(&header).chain(&self.rows) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        for row in std::iter::once(&header).chain(&self.rows) {
+            let mut line = String::new();
+            for (width, cell) in widths.iter().zip(row) {
+                line += &format!("{cell:width$} ");
+            }
+            writeln!(&mut self.writer, "{}", line.trim_end()).unwrap();
+        }
+        writeln!(&mut self.writer).unwrap();
+        self.writer.flush().unwrap();
+    }
+
+    fn source(&mut self, source: &str) {
+        self.source = Some(source.into());
+    }
+}
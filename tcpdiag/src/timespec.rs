@@ -1,34 +1,123 @@
 use libc::{clock_gettime, clock_nanosleep};
-use std::{io::Error, mem::MaybeUninit, ptr::null_mut, time::Duration};
+use std::{
+    io::Error,
+    mem::MaybeUninit,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    ptr::{null, null_mut},
+    time::Duration,
+};
+
+use crate::signal::Blocked;
+
+/// Clock that schedules periodic sampling
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Clock {
+    /// Stops while the system is suspended
+    #[default]
+    Monotonic,
+    /// Includes the time the system is suspended, so the schedule keeps up
+    /// with the wall time after a suspend
+    Boottime,
+}
+
+impl Clock {
+    fn id(self) -> libc::clockid_t {
+        match self {
+            Clock::Monotonic => libc::CLOCK_MONOTONIC,
+            Clock::Boottime => libc::CLOCK_BOOTTIME,
+        }
+    }
+}
+
+/// CPU time of the process (all threads)
+pub fn cpu_time() -> Duration {
+    let mut value = MaybeUninit::uninit();
+    unsafe {
+        let ret = clock_gettime(libc::CLOCK_PROCESS_CPUTIME_ID, value.as_mut_ptr());
+        assert!(ret == 0);
+        Timespec(value.assume_init()).as_duration()
+    }
+}
 
 #[derive(Clone, Copy)]
 #[repr(transparent)]
 pub struct Timespec(libc::timespec);
 
 impl Timespec {
-    pub fn now() -> Self {
+    pub fn now(clock: Clock) -> Self {
         let mut value = MaybeUninit::uninit();
         unsafe {
-            let ret = clock_gettime(libc::CLOCK_MONOTONIC, value.as_mut_ptr());
+            let ret = clock_gettime(clock.id(), value.as_mut_ptr());
             assert!(ret == 0);
             Self(value.assume_init())
         }
     }
-    pub fn sleep_until(&self) {
+    /// Time since the epoch of the clock
+    pub fn as_duration(&self) -> Duration {
+        Duration::new(self.0.tv_sec as u64, self.0.tv_nsec as u32)
+    }
+
+    pub fn sleep_until(&self, clock: Clock) {
         loop {
             let ret = unsafe {
                 clock_nanosleep(
-                    libc::CLOCK_MONOTONIC,
+                    clock.id(),
                     libc::TIMER_ABSTIME,
                     &self.0 as *const _,
                     null_mut(),
                 )
             };
-            let err = (ret != 0).then(Error::last_os_error);
-            match err {
-                Some(err) if err.raw_os_error() == Some(libc::EINTR) => continue,
-                Some(err) => panic!("{err}"),
-                _ => break,
+            match ret {
+                0 => return,
+                libc::EINTR => continue,
+                _ => panic!("{}", Error::from_raw_os_error(ret)),
+            }
+        }
+    }
+
+    /// Sleeps until this time, unless `interrupt` returns true (checked
+    /// initially and after each signal). Returns whether the time was reached.
+    ///
+    /// The handled signals are blocked while `interrupt` is checked and only
+    /// unblocked by the wait, so a signal right after the check still ends
+    /// the wait instead of being noticed one period late.
+    pub fn sleep_until_or(&self, clock: Clock, interrupt: impl Fn() -> bool) -> bool {
+        let timer = unsafe { libc::timerfd_create(clock.id(), libc::TFD_CLOEXEC) };
+        assert!(timer >= 0, "timerfd_create: {}", Error::last_os_error());
+        let timer = unsafe { OwnedFd::from_raw_fd(timer) };
+        let value = libc::itimerspec {
+            it_interval: libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            },
+            it_value: self.0,
+        };
+        let ret = unsafe {
+            libc::timerfd_settime(
+                timer.as_raw_fd(),
+                libc::TFD_TIMER_ABSTIME,
+                &value,
+                null_mut(),
+            )
+        };
+        assert!(ret == 0, "timerfd_settime: {}", Error::last_os_error());
+        let blocked = Blocked::new();
+        loop {
+            if interrupt() {
+                return false;
+            }
+            let mut fd = libc::pollfd {
+                fd: timer.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let ret = unsafe { libc::ppoll(&mut fd, 1, null(), blocked.previous()) };
+            if ret > 0 {
+                return true;
+            }
+            let err = Error::last_os_error();
+            if err.raw_os_error() != Some(libc::EINTR) {
+                panic!("ppoll: {err}");
             }
         }
     }
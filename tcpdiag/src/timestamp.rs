@@ -0,0 +1,115 @@
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimeFormat {
+    /// Microseconds since the Unix epoch
+    #[default]
+    Micros,
+    /// RFC 3339 date and time with microsecond precision, in the given time zone
+    Rfc3339(jiff::tz::TimeZone),
+}
+
+/// Parses `local`, `UTC`, or an IANA time zone name like `Europe/Berlin`.
+pub fn parse_time_zone(name: &str) -> Result<jiff::tz::TimeZone, jiff::Error> {
+    match name {
+        "local" => jiff::tz::TimeZone::try_system(),
+        "UTC" | "utc" => Ok(jiff::tz::TimeZone::UTC),
+        name => jiff::tz::TimeZone::get(name),
+    }
+}
+
+/// Point in time with microsecond resolution, as stored in captures.
+///
+/// It is written in the selected [`TimeFormat`], but always read from either
+/// representation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub u64);
+
+impl Timestamp {
+    pub fn new(time: SystemTime) -> Self {
+        Self(time.duration_since(UNIX_EPOCH).unwrap().as_micros() as u64)
+    }
+
+    pub fn system_time(self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(self.0)
+    }
+
+    pub fn display(self, format: &TimeFormat) -> String {
+        match format {
+            TimeFormat::Micros => self.0.to_string(),
+            TimeFormat::Rfc3339(tz) => {
+                let ts = jiff::Timestamp::from_microsecond(self.0 as i64).unwrap();
+                let printer = jiff::fmt::temporal::DateTimePrinter::new().precision(Some(6));
+                let mut out = String::new();
+                if *tz == jiff::tz::TimeZone::UTC {
+                    printer.print_timestamp(&ts, &mut out).unwrap();
+                } else {
+                    printer
+                        .print_timestamp_with_offset(&ts, tz.to_offset(ts), &mut out)
+                        .unwrap();
+                }
+                out
+            }
+        }
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = jiff::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(micros) = s.parse() {
+            return Ok(Self(micros));
+        }
+        let ts: jiff::Timestamp = s.parse()?;
+        Ok(Self(ts.as_microsecond() as u64))
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Timestamp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Timestamp;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("microseconds since the epoch or an RFC 3339 timestamp")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Timestamp, E> {
+                Ok(Timestamp(v))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Timestamp, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+impl csv::CsvWrite for Timestamp {
+    type Context = TimeFormat;
+    const DESC: csv::Desc = csv::Desc::Atom;
+    fn write<W: std::io::Write>(obj: &Self, ctx: &Self::Context, w: &mut W) {
+        write!(w, "{}", obj.display(ctx)).unwrap();
+    }
+}
+impl csv::Csv for Timestamp {
+    fn read<'a, I: Iterator<Item = &'a str>>(r: &mut I) -> Result<Self, csv::Error> {
+        csv::parse(r)
+    }
+}
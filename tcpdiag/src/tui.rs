@@ -0,0 +1,282 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, VecDeque},
+    net::SocketAddr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Row, Table},
+    DefaultTerminal, Frame,
+};
+
+use crate::data::*;
+use crate::human::{self, Unit};
+use crate::timestamp::{TimeFormat, Timestamp};
+
+use crate::Collector;
+
+/// Number of samples shown in the sparklines.
+const HISTORY: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SortKey {
+    Rtt,
+    Cwnd,
+    Retrans,
+    Delivery,
+    Local,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            Self::Rtt => Self::Cwnd,
+            Self::Cwnd => Self::Retrans,
+            Self::Retrans => Self::Delivery,
+            Self::Delivery => Self::Local,
+            Self::Local => Self::Rtt,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Rtt => "rtt",
+            Self::Cwnd => "cwnd",
+            Self::Retrans => "retrans",
+            Self::Delivery => "delivery",
+            Self::Local => "local",
+        }
+    }
+}
+
+/// Connection state over the last samples.
+struct Connection {
+    state: u8,
+    local: SocketAddr,
+    peer: SocketAddr,
+    cong: String,
+    rtt: VecDeque<u64>,
+    cwnd: VecDeque<u64>,
+    /// Retransmissions per sample
+    retrans: VecDeque<u64>,
+    total_retrans: Option<u64>,
+    delivery_rate: u64,
+    seen: bool,
+}
+
+fn push(history: &mut VecDeque<u64>, value: u64) {
+    if history.len() == HISTORY {
+        history.pop_front();
+    }
+    history.push_back(value);
+}
+
+fn sparkline(history: &VecDeque<u64>) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = history.iter().copied().max().unwrap_or(0).max(1);
+    history
+        .iter()
+        .map(|&v| BARS[(v * (BARS.len() as u64 - 1)).div_ceil(max) as usize])
+        .collect()
+}
+
+/// Live dashboard of connections, refreshed after each sample.
+///
+/// Keys: `s` cycles the sort column, `r` reverses the order, `q` quits.
+pub struct TuiOutput {
+    terminal: Option<DefaultTerminal>,
+    human: bool,
+    time: SystemTime,
+    time_format: TimeFormat,
+    duration: Duration,
+    connections: BTreeMap<u64, Connection>,
+    sort: SortKey,
+    reverse: bool,
+}
+
+impl TuiOutput {
+    pub fn new(human: bool) -> Self {
+        Self {
+            terminal: Some(ratatui::init()),
+            human,
+            time: UNIX_EPOCH,
+            time_format: TimeFormat::default(),
+            duration: Duration::ZERO,
+            connections: BTreeMap::new(),
+            sort: SortKey::Rtt,
+            reverse: false,
+        }
+    }
+
+    pub fn set_time_format(&mut self, time_format: TimeFormat) {
+        self.time_format = time_format;
+    }
+
+    fn value(&self, unit: Option<Unit>, value: Option<u64>) -> String {
+        match (value, unit) {
+            (Some(value), Some(unit)) if self.human => human::format(unit, value),
+            (Some(value), _) => value.to_string(),
+            (None, _) => "-".into(),
+        }
+    }
+
+    fn sorted(&self) -> Vec<&Connection> {
+        let mut connections: Vec<_> = self.connections.values().collect();
+        let last = |h: &VecDeque<u64>| h.back().copied().unwrap_or(0);
+        match self.sort {
+            SortKey::Rtt => connections.sort_by_key(|c| Reverse(last(&c.rtt))),
+            SortKey::Cwnd => connections.sort_by_key(|c| Reverse(last(&c.cwnd))),
+            SortKey::Retrans => connections.sort_by_key(|c| Reverse(c.retrans.iter().sum::<u64>())),
+            SortKey::Delivery => connections.sort_by_key(|c| Reverse(c.delivery_rate)),
+            SortKey::Local => connections.sort_by_key(|c| (c.local, c.peer)),
+        }
+        if self.reverse {
+            connections.reverse();
+        }
+        connections
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [title, table] =
+            Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).areas(frame.area());
+        let time = Timestamp::new(self.time).display(&self.time_format);
+        frame.render_widget(
+            Line::from(format!(
+                "time {time}: {} sockets in {}us | sort: {}{} (s: sort, r: reverse, q: quit)",
+                self.connections.len(),
+                self.duration.as_micros(),
+                self.sort.name(),
+                if self.reverse { ", reversed" } else { "" },
+            )),
+            title,
+        );
+        let rows = self.sorted().into_iter().map(|c| {
+            Row::new([
+                tcp_state_name(c.state).to_string(),
+                c.local.to_string(),
+                c.peer.to_string(),
+                c.cong.clone(),
+                self.value(Some(Unit::Micros), c.rtt.back().copied()),
+                sparkline(&c.rtt),
+                self.value(None, c.cwnd.back().copied()),
+                sparkline(&c.cwnd),
+                self.value(None, c.total_retrans),
+                sparkline(&c.retrans),
+                self.value(Some(Unit::BytesPerSec), Some(c.delivery_rate)),
+            ])
+        });
+        let history = HISTORY as u16;
+        let widths = [
+            Constraint::Length(11),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Length(8),
+            Constraint::Length(8),
+            Constraint::Length(history),
+            Constraint::Length(6),
+            Constraint::Length(history),
+            Constraint::Length(7),
+            Constraint::Length(history),
+            Constraint::Length(12),
+        ];
+        let header = Row::new([
+            "state", "local", "peer", "cong", "rtt", "", "cwnd", "", "retrans", "", "delivery",
+        ])
+        .style(Style::new().add_modifier(Modifier::BOLD));
+        frame.render_widget(Table::new(rows, widths).header(header), table);
+    }
+
+    fn render(&mut self) {
+        let mut terminal = self.terminal.take().unwrap();
+        terminal.draw(|frame| self.draw(frame)).unwrap();
+        self.terminal = Some(terminal);
+    }
+
+    /// Handles pending key presses without blocking the sampler.
+    fn handle_events(&mut self) {
+        while event::poll(Duration::ZERO).unwrap() {
+            let Event::Key(key) = event::read().unwrap() else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => self.quit(),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit(),
+                KeyCode::Char('s') => self.sort = self.sort.next(),
+                KeyCode::Char('r') => self.reverse = !self.reverse,
+                _ => continue,
+            }
+            self.render();
+        }
+    }
+
+    fn quit(&mut self) -> ! {
+        ratatui::restore();
+        std::process::exit(0);
+    }
+}
+
+impl Collector for TuiOutput {
+    fn start(&mut self, time: SystemTime) {
+        self.time = time;
+        for connection in self.connections.values_mut() {
+            connection.seen = false;
+        }
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        let extras = InetDiagMsgExtra::parse(data);
+        let base = extras.base;
+        let id = &base.id;
+        let connection = self
+            .connections
+            .entry(id.cookie.get())
+            .or_insert_with(|| Connection {
+                state: base.state,
+                local: SocketAddr::new(id.src.get(base.family), id.sport.get()),
+                peer: SocketAddr::new(id.dst.get(base.family), id.dport.get()),
+                cong: String::new(),
+                rtt: VecDeque::new(),
+                cwnd: VecDeque::new(),
+                retrans: VecDeque::new(),
+                total_retrans: None,
+                delivery_rate: 0,
+                seen: true,
+            });
+        connection.state = base.state;
+        connection.cong = extras.cong.unwrap_or("-").into();
+        connection.seen = true;
+        if let Some(info) = extras.tcp_info {
+            let total_retrans = u64::from(info.total_retrans);
+            let previous = connection.total_retrans.unwrap_or(total_retrans);
+            push(&mut connection.rtt, info.rtt.into());
+            push(&mut connection.cwnd, info.snd_cwnd.into());
+            push(
+                &mut connection.retrans,
+                total_retrans.saturating_sub(previous),
+            );
+            connection.total_retrans = Some(total_retrans);
+            connection.delivery_rate = info.delivery_rate.get();
+        }
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.duration = duration;
+        self.connections.retain(|_, connection| connection.seen);
+        self.render();
+        self.handle_events();
+    }
+}
+
+impl Drop for TuiOutput {
+    fn drop(&mut self) {
+        ratatui::restore();
+    }
+}
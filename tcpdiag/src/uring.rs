@@ -0,0 +1,231 @@
+use std::{
+    cell::RefCell,
+    io::Write,
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+    rc::Rc,
+};
+
+use io_uring::{opcode, squeue, types::Fd, IoUring};
+use netlink_sys::Socket;
+
+use crate::{Collector, NetlinkArgs};
+
+const SEND: u64 = u64::MAX;
+const WRITE: u64 = u64::MAX - 1;
+/// Output bytes after which a write is queued even without a flush
+const MAX_BUFFERED: usize = 1 << 20;
+
+struct PendingWrite {
+    fd: RawFd,
+    data: Vec<u8>,
+    done: usize,
+}
+
+/// Ring shared by the capture loop (netlink requests and receives) and the
+/// output (writes), so both are submitted with one system call.
+pub struct Uring {
+    ring: IoUring,
+    /// Result of the receive on each netlink socket
+    recvs: Vec<Option<i32>>,
+    /// At most one write is in flight, as writes in a ring may be reordered.
+    write: Option<PendingWrite>,
+    /// Buffer of the last completed write, for reuse
+    spare: Vec<u8>,
+}
+
+impl Uring {
+    pub fn new() -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            ring: IoUring::new(16).unwrap(),
+            recvs: Vec::new(),
+            write: None,
+            spare: Vec::new(),
+        }))
+    }
+
+    /// The buffers of the entry must be valid until its completion.
+    unsafe fn push(&mut self, entry: &squeue::Entry) {
+        while self.ring.submission().push(entry).is_err() {
+            self.submit_and_wait(0);
+        }
+    }
+
+    /// Submits the queued entries and waits for `want` completions, also when
+    /// interrupted by a signal.
+    fn submit_and_wait(&mut self, want: usize) {
+        loop {
+            match self.ring.submit_and_wait(want) {
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                result => {
+                    result.unwrap();
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Submits the queued entries and waits for at least one completion.
+    fn wait(&mut self) {
+        self.submit_and_wait(1);
+        let cqes: Vec<_> = self.ring.completion().collect();
+        for cqe in cqes {
+            let result = cqe.result();
+            match cqe.user_data() {
+                SEND if result < 0 => {
+                    panic!("send: {}", std::io::Error::from_raw_os_error(-result))
+                }
+                SEND => (),
+                WRITE => self.complete_write(result),
+                i => self.recvs[i as usize] = Some(result),
+            }
+        }
+    }
+
+    fn push_write(&mut self) {
+        let write = self.write.as_ref().unwrap();
+        let rest = &write.data[write.done..];
+        let entry = opcode::Write::new(Fd(write.fd), rest.as_ptr(), rest.len() as u32)
+            .offset(u64::MAX) // current file position
+            .build()
+            .user_data(WRITE);
+        // The data is kept in self.write until completion.
+        unsafe { self.push(&entry) };
+    }
+
+    fn complete_write(&mut self, result: i32) {
+        if result <= 0 {
+            let err = match result {
+                0 => std::io::ErrorKind::WriteZero.into(),
+                _ => std::io::Error::from_raw_os_error(-result),
+            };
+            panic!("write: {err}");
+        }
+        let write = self.write.as_mut().unwrap();
+        write.done += result as usize;
+        if write.done < write.data.len() {
+            self.push_write();
+        } else {
+            self.spare = self.write.take().unwrap().data;
+            self.spare.clear();
+        }
+    }
+
+    /// Queues a write of `data`, replacing it by an empty buffer.
+    fn queue_write(&mut self, fd: RawFd, data: &mut Vec<u8>) {
+        self.finish_write();
+        let data = std::mem::replace(data, std::mem::take(&mut self.spare));
+        self.write = Some(PendingWrite { fd, data, done: 0 });
+        self.push_write();
+    }
+
+    fn finish_write(&mut self) {
+        while self.write.is_some() {
+            self.wait();
+        }
+    }
+}
+
+/// Output whose flushes are written through the ring. The write is submitted
+/// with the requests of the next sample and completes in the background.
+pub struct UringWriter {
+    uring: Rc<RefCell<Uring>>,
+    fd: OwnedFd,
+    buf: Vec<u8>,
+}
+
+impl UringWriter {
+    pub fn new(uring: Rc<RefCell<Uring>>, fd: OwnedFd) -> Self {
+        Self {
+            uring,
+            fd,
+            buf: Vec::new(),
+        }
+    }
+}
+
+impl Write for UringWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= MAX_BUFFERED {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.buf.is_empty() {
+            let mut uring = self.uring.borrow_mut();
+            uring.queue_write(self.fd.as_raw_fd(), &mut self.buf);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for UringWriter {
+    fn drop(&mut self) {
+        self.flush().unwrap();
+        self.uring.borrow_mut().finish_write();
+    }
+}
+
+/// Like `read_netlink`, but sends the requests and receives the replies of
+/// all dumps through the ring.
+pub fn read_netlink_uring<C: Collector>(args: &NetlinkArgs, uring: &RefCell<Uring>, writer: C) {
+    let dumps = crate::dumps(args);
+    let diag_sockets: Vec<Socket> = dumps
+        .iter()
+        .map(|_| crate::diag_socket(args).unwrap())
+        .collect();
+    let mut bufs = vec![vec![0u8; 1 << 18]; diag_sockets.len()];
+    let mut requests: Vec<Vec<u8>> = Vec::new();
+    uring.borrow_mut().recvs = vec![None; diag_sockets.len()];
+    let recv = |i: usize, buf: &mut Vec<u8>| {
+        let entry = opcode::Recv::new(Fd(diag_sockets[i].as_raw_fd()), buf.as_mut_ptr(), 1 << 18)
+            .flags(libc::MSG_TRUNC)
+            .build()
+            .user_data(i as u64);
+        // The buffer is not touched until the result of this receive is taken.
+        unsafe { uring.borrow_mut().push(&entry) };
+    };
+    crate::sample_loop(args, writer, |seq, start, writer, stats, activity| {
+        // The previous requests were sent, as all their replies were received.
+        requests.clear();
+        requests.extend(dumps.iter().map(|&dump| crate::request(args, dump, seq)));
+        for (i, request) in requests.iter().enumerate() {
+            let bytes = request.as_slice();
+            let fd = Fd(diag_sockets[i].as_raw_fd());
+            let entry = opcode::Send::new(fd, bytes.as_ptr(), bytes.len() as u32)
+                .build()
+                .flags(squeue::Flags::IO_LINK)
+                .user_data(SEND);
+            unsafe { uring.borrow_mut().push(&entry) };
+            recv(i, &mut bufs[i]);
+        }
+        for (i, buf) in bufs.iter_mut().enumerate() {
+            loop {
+                let result = loop {
+                    let mut uring = uring.borrow_mut();
+                    match uring.recvs[i].take() {
+                        Some(result) => break result,
+                        None => uring.wait(),
+                    }
+                };
+                if result < 0 {
+                    let err = std::io::Error::from_raw_os_error(-result);
+                    return Err(std::io::Error::new(err.kind(), format!("recv: {err}")));
+                }
+                let len = result as usize;
+                crate::check_truncation(len, buf.len())?;
+                let offset = start.elapsed();
+                let reply = (seq, dumps[i].1);
+                if crate::handle_datagram(&buf[..len], reply, args, writer, stats, activity, offset)
+                {
+                    break;
+                }
+                recv(i, buf);
+            }
+        }
+        Ok(())
+    })
+    .unwrap_or_else(|err| panic!("{err}"));
+}
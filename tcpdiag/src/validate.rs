@@ -0,0 +1,408 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, ErrorKind, Read, Write},
+};
+
+use zerocopy::{FromBytes, IntoBytes};
+
+use crate::csv::CSV_HEADER;
+use crate::data::*;
+use crate::json::{JsonFormat, JsonMetadata};
+use crate::timestamp::{TimeFormat, Timestamp};
+use crate::{detect_format, CaptureFormat};
+
+/// Number of problems of each kind that are listed individually.
+const LISTED: usize = 10;
+
+/// Result of checking a capture.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub format: Option<CaptureFormat>,
+    pub samples: u64,
+    pub messages: u64,
+    pub first: Option<Timestamp>,
+    pub last: Option<Timestamp>,
+    /// Metadata of the (first) capture
+    pub metadata: Vec<(String, String)>,
+    /// Corruption: truncated or malformed records, out-of-order timestamps
+    pub errors: Vec<String>,
+    pub error_count: u64,
+    /// Data that tcpdiag reads, but maybe not as intended (e.g. missing columns)
+    pub warnings: Vec<String>,
+    pub warning_count: u64,
+}
+
+impl Report {
+    fn error(&mut self, message: String) {
+        if self.errors.len() < LISTED {
+            self.errors.push(message);
+        }
+        self.error_count += 1;
+    }
+
+    fn warning(&mut self, message: String) {
+        if self.warnings.len() < LISTED {
+            self.warnings.push(message);
+        }
+        self.warning_count += 1;
+    }
+
+    fn sample(&mut self, location: &str, time: Timestamp) {
+        if self.last.is_some_and(|last| time < last) {
+            let last = self.last.unwrap().0;
+            self.error(format!("{location}: time {} is before {last}", time.0));
+        }
+        self.first.get_or_insert(time);
+        self.last = Some(self.last.map_or(time, |last| last.max(time)));
+        self.samples += 1;
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.error_count == 0
+    }
+
+    pub fn write<W: Write>(&self, mut w: W, time_format: &TimeFormat) {
+        let format = match self.format {
+            Some(format) => format!("{format:?}").to_lowercase(),
+            None => "empty".into(),
+        };
+        writeln!(w, "format:   {format}").unwrap();
+        writeln!(w, "samples:  {}", self.samples).unwrap();
+        writeln!(w, "sockets:  {} records", self.messages).unwrap();
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            let (a, b) = (first.display(time_format), last.display(time_format));
+            writeln!(w, "time:     {a} to {b}").unwrap();
+        }
+        for (key, value) in &self.metadata {
+            writeln!(w, "{:9} {value}", format!("{key}:")).unwrap();
+        }
+        for (title, count, list) in [
+            ("errors:", self.error_count, &self.errors),
+            ("warnings:", self.warning_count, &self.warnings),
+        ] {
+            writeln!(w, "{title:9} {count}").unwrap();
+            for message in list {
+                writeln!(w, "  {message}").unwrap();
+            }
+            if count > list.len() as u64 {
+                writeln!(w, "  ... and {} more", count - list.len() as u64).unwrap();
+            }
+        }
+        w.flush().unwrap();
+    }
+}
+
+/// Checks a socket record (InetDiagMsg followed by attributes).
+pub(crate) fn check_message(data: &[u8]) -> Result<(), String> {
+    let Ok((_, mut rest)) = InetDiagMsg::ref_from_prefix(data) else {
+        return Err(format!("socket record has only {} bytes", data.len()));
+    };
+    while !rest.is_empty() {
+        let Ok((hdr, _)) = nlattr::ref_from_prefix(rest) else {
+            return Err("truncated attribute header".into());
+        };
+        let len = usize::from(hdr.nla_len);
+        if len < size_of::<nlattr>() || len > rest.len() {
+            return Err(format!(
+                "attribute {} has invalid length {len}",
+                hdr.nla_type
+            ));
+        }
+        let payload = &rest[size_of::<nlattr>()..len];
+        match hdr.nla_type {
+            INET_DIAG_INFO if payload.len() < size_of::<TcpInfo>() => {
+                return Err(format!(
+                    "tcp_info has {} bytes, expected {}",
+                    payload.len(),
+                    size_of::<TcpInfo>()
+                ));
+            }
+            INET_DIAG_CONG => {
+                let valid = payload
+                    .strip_suffix(b"\0")
+                    .is_some_and(|cong| std::str::from_utf8(cong).is_ok());
+                if !valid {
+                    return Err("congestion control name is not a C string".into());
+                }
+            }
+            TCPDIAG_TAGS => {
+                let valid = payload.strip_suffix(b"\0").is_some_and(|tags| {
+                    tags.split(|&b| b == 0).all(|tag| {
+                        std::str::from_utf8(tag).is_ok_and(|tag| {
+                            tag.split_once('=').is_some_and(|(k, _)| !k.is_empty())
+                        })
+                    })
+                });
+                if !valid {
+                    return Err("tags are not key=value C strings".into());
+                }
+            }
+            _ => (),
+        }
+        rest = &rest[((len + 3) & !3).min(rest.len())..];
+    }
+    Ok(())
+}
+
+/// Reads as many bytes as available, up to the length of `buf`.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> usize {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => (),
+            Err(err) => panic!("{err}"),
+        }
+    }
+    filled
+}
+
+fn validate_binary<R: BufRead>(mut reader: R, report: &mut Report) {
+    let mut offset = 0;
+    let mut in_sample = false;
+    let mut buf = Vec::new();
+    // Checksum of the current sample: its end, expected CRC, and location
+    let mut frame: Option<(usize, u32, crc32fast::Hasher, String)> = None;
+    loop {
+        let mut attr = nlattr::default();
+        let n = read_full(&mut reader, attr.as_mut_bytes());
+        if n == 0 {
+            break;
+        }
+        let location = format!("offset {offset}");
+        if n < size_of::<nlattr>() {
+            report.error(format!("{location}: truncated record header"));
+            return;
+        }
+        let Some(len) = usize::from(attr.nla_len).checked_sub(size_of::<nlattr>()) else {
+            report.error(format!(
+                "{location}: invalid record length {}",
+                attr.nla_len
+            ));
+            return;
+        };
+        buf.resize(len, 0);
+        if read_full(&mut reader, &mut buf) < len {
+            report.error(format!(
+                "{location}: truncated record of type {}",
+                attr.nla_type
+            ));
+            return;
+        }
+        offset += size_of::<nlattr>() + len;
+        if let Some((end, crc, hasher, frame_location)) = &mut frame {
+            hasher.update(attr.as_bytes());
+            hasher.update(&buf);
+            if offset >= *end {
+                if offset > *end {
+                    report.error(format!("{location}: record crosses the end of the sample"));
+                } else if hasher.clone().finalize() != *crc {
+                    report.error(format!("{frame_location}: checksum mismatch in the sample"));
+                }
+                frame = None;
+            }
+        }
+        let expected_len = match attr.nla_type {
+            9 => Some(8),
+            1 | 3 => Some(8),
+            // 4 bytes in captures of earlier versions
+            2 if len == 4 => Some(4),
+            2 => Some(8),
+            8 => Some(4),
+            5 => Some(0),
+            // 24 bytes (without the boottime) or 32 bytes (without the
+            // counters after it) in captures of earlier versions
+            7 if len == 24 || len == 32 => Some(len),
+            7 => Some(size_of::<crate::SampleStats>()),
+            _ => None,
+        };
+        if expected_len.is_some_and(|expected| expected != len) {
+            report.error(format!(
+                "{location}: record of type {} has {len} bytes",
+                attr.nla_type
+            ));
+            continue;
+        }
+        match attr.nla_type {
+            0 | 2 | 3 | 5 | 7 | 8 if !in_sample => {
+                report.error(format!(
+                    "{location}: record of type {} outside of a sample",
+                    attr.nla_type
+                ));
+                continue;
+            }
+            1 | 4 | 6 | 9 if in_sample => {
+                report.error(format!("{location}: sample without duration"));
+                in_sample = false;
+            }
+            _ => (),
+        }
+        match attr.nla_type {
+            0 => {
+                report.messages += 1;
+                if let Err(err) = check_message(&buf) {
+                    report.error(format!("{location}: {err}"));
+                }
+            }
+            1 => {
+                report.sample(
+                    &location,
+                    Timestamp(u64::from_ne_bytes(buf[..].try_into().unwrap())),
+                );
+                in_sample = true;
+            }
+            2 => in_sample = false,
+            3 | 5 | 7 | 8 => (),
+            9 if frame.is_some() => report.error(format!("{location}: nested checksum record")),
+            9 => {
+                let len = u32::from_ne_bytes(buf[..4].try_into().unwrap());
+                let crc = u32::from_ne_bytes(buf[4..].try_into().unwrap());
+                let end = offset + len as usize;
+                frame = Some((end, crc, crc32fast::Hasher::new(), location));
+            }
+            4 => {
+                if std::str::from_utf8(&buf).is_err() {
+                    report.error(format!("{location}: source is not UTF-8"));
+                }
+            }
+            6 => match std::str::from_utf8(&buf) {
+                Ok(text) if report.metadata.is_empty() => {
+                    report.metadata = crate::binary::parse_metadata(text);
+                }
+                Ok(_) => (),
+                Err(_) => report.error(format!("{location}: metadata is not UTF-8")),
+            },
+            ty => report.error(format!("{location}: unknown record type {ty}")),
+        }
+    }
+    if in_sample {
+        report.error("last sample has no duration (truncated capture)".into());
+    } else if frame.is_some() {
+        report.error("last sample is shorter than its checksum record (truncated capture)".into());
+    }
+}
+
+fn validate_json<R: BufRead>(reader: R, report: &mut Report) {
+    for (i, line) in reader.lines().enumerate() {
+        let location = format!("line {}", i + 1);
+        let Ok(line) = line else {
+            report.error(format!("{location}: not UTF-8"));
+            continue;
+        };
+        if line.starts_with("{\"metadata\"") {
+            match serde_json::from_str::<JsonMetadata>(&line) {
+                Ok(json) if report.metadata.is_empty() => report.metadata = json.metadata,
+                Ok(_) => (),
+                Err(err) => report.error(format!("{location}: {err}")),
+            }
+            continue;
+        }
+        match serde_json::from_str::<JsonFormat>(&line) {
+            Ok(json) => {
+                report.sample(&location, json.time);
+                report.messages += json.samples.len() as u64;
+            }
+            Err(err) if err.is_eof() => report.error(format!("{location}: truncated ({err})")),
+            Err(err) => report.error(format!("{location}: {err}")),
+        }
+    }
+}
+
+fn validate_csv<R: BufRead>(reader: R, report: &mut Report) {
+    let mut lines = reader
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.unwrap_or_else(|_| "<not UTF-8>".into())));
+    // Comment lines before the first sample hold the metadata.
+    let Some((_, header)) = lines.find(|(_, line)| {
+        report.metadata.extend(crate::csv::metadata_entry(line));
+        !line.starts_with('#')
+    }) else {
+        return;
+    };
+    let columns: Vec<&str> = header.trim_end().split(' ').collect();
+    let index: HashMap<&str, usize> = columns.iter().copied().zip(0..).collect();
+    let expected: Vec<&str> = CSV_HEADER.trim_end().split(' ').collect();
+    let missing: Vec<_> = expected
+        .iter()
+        .filter(|c| !index.contains_key(*c))
+        .collect();
+    if !missing.is_empty() {
+        report.warning(format!(
+            "header lacks {} columns (read as _): {missing:?}",
+            missing.len()
+        ));
+    }
+    for column in columns.iter().filter(|c| !expected.contains(c)) {
+        report.warning(format!("header has unknown column {column:?} (ignored)"));
+    }
+    let (Some(&time_index), Some(&duration_index)) = (index.get("time"), index.get("duration"))
+    else {
+        report.error("header lacks the time or duration column".into());
+        return;
+    };
+    let family_index = index.get("base.family").copied();
+    let mut current: Option<Timestamp> = None;
+    for (number, line) in lines {
+        if line.starts_with('#') {
+            if report.samples == 0 {
+                report.metadata.extend(crate::csv::metadata_entry(&line));
+            }
+            continue;
+        }
+        let location = format!("line {number}");
+        let fields: Vec<&str> = line.split(' ').collect();
+        if fields.len() != columns.len() {
+            report.error(format!(
+                "{location}: {} fields, expected {}",
+                fields.len(),
+                columns.len()
+            ));
+            continue;
+        }
+        let Ok(time) = fields[time_index].parse::<Timestamp>() else {
+            report.error(format!("{location}: invalid time {:?}", fields[time_index]));
+            continue;
+        };
+        if current.is_some_and(|current| current != time) {
+            report.error(format!("{location}: sample without duration"));
+            current = None;
+        }
+        if current.is_none() {
+            report.sample(&location, time);
+            current = Some(time);
+        }
+        if family_index.is_some_and(|i| fields[i] != "_") {
+            report.messages += 1;
+        }
+        match fields[duration_index] {
+            "_" => (),
+            duration if duration.parse::<u64>().is_ok() => current = None,
+            duration => report.error(format!("{location}: invalid duration {duration:?}")),
+        }
+    }
+    if current.is_some() {
+        report.error("last sample has no duration (truncated capture)".into());
+    }
+}
+
+/// Checks a capture for truncated or malformed records, timestamps that are
+/// out of order, and deviations from the expected schema.
+pub fn validate<R: BufRead>(mut reader: R) -> Report {
+    let mut report = Report::default();
+    match detect_format(reader.fill_buf().unwrap()) {
+        Ok(format) => report.format = format,
+        Err(err) => {
+            report.error(err.into());
+            return report;
+        }
+    }
+    match report.format {
+        Some(CaptureFormat::Binary) => validate_binary(reader, &mut report),
+        Some(CaptureFormat::Json) => validate_json(reader, &mut report),
+        Some(CaptureFormat::Csv) => validate_csv(reader, &mut report),
+        None => (),
+    }
+    report
+}
@@ -0,0 +1,148 @@
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use wasmtime::{
+    Config, Engine, Instance, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc,
+};
+
+use crate::validate::check_message;
+use crate::{Collector, SampleStats};
+
+/// Fuel of each call, which bounds the run time of the module per socket
+const FUEL: u64 = 10_000_000;
+/// Largest memory of the module
+const MAX_MEMORY: usize = 64 << 20;
+
+/// Collector that passes each socket through a WebAssembly module before
+/// passing it on. The module has no imports, so it cannot access the system,
+/// and its fuel and memory are limited. It exports its `memory`,
+/// `alloc(len: i32) -> i32`, which returns where the record (as in binary
+/// captures) of the socket is written, and `transform(ptr: i32, len: i32) ->
+/// i64`, which returns -1 to drop the socket, or the position of the resulting
+/// record as `ptr << 32 | len` (e.g. the input to keep it unchanged). Traps and
+/// malformed records are reported, and the socket is passed on unchanged.
+pub struct Wasm<C: Collector> {
+    inner: C,
+    path: String,
+    store: Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    transform: TypedFunc<(i32, i32), i64>,
+    buf: Vec<u8>,
+}
+
+impl<C: Collector> Wasm<C> {
+    /// Loads the module at `path` (binary or text format).
+    pub fn new(path: &Path, inner: C) -> Self {
+        let fail = |err: wasmtime::Error| -> ! { panic!("{}: {err:#}", path.display()) };
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).unwrap_or_else(|err| fail(err));
+        let module = Module::from_file(&engine, path).unwrap_or_else(|err| fail(err));
+        if let Some(import) = module.imports().next() {
+            panic!(
+                "{}: the module must not have imports, but imports {}::{}",
+                path.display(),
+                import.module(),
+                import.name()
+            );
+        }
+        let limits = StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build();
+        let mut store = Store::new(&engine, limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(FUEL).unwrap();
+        let instance = Instance::new(&mut store, &module, &[]).unwrap_or_else(|err| fail(err));
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .unwrap_or_else(|| panic!("{}: the module must export its memory", path.display()));
+        let alloc = instance
+            .get_typed_func(&mut store, "alloc")
+            .unwrap_or_else(|err| fail(err));
+        let transform = instance
+            .get_typed_func(&mut store, "transform")
+            .unwrap_or_else(|err| fail(err));
+        Self {
+            inner,
+            path: path.display().to_string(),
+            store,
+            memory,
+            alloc,
+            transform,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Runs the module for a socket, returning false if it is dropped. The
+    /// result is in `buf`.
+    fn run(&mut self, data: &[u8]) -> Result<bool, String> {
+        let len = i32::try_from(data.len()).unwrap();
+        self.store.set_fuel(FUEL).unwrap();
+        let ptr = self
+            .alloc
+            .call(&mut self.store, len)
+            .map_err(|err| err.root_cause().to_string())?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, data)
+            .map_err(|_| format!("alloc returned {ptr} for {len} bytes"))?;
+        let result = self
+            .transform
+            .call(&mut self.store, (ptr, len))
+            .map_err(|err| err.root_cause().to_string())?;
+        if result == -1 {
+            return Ok(false);
+        }
+        let (ptr, len) = ((result >> 32) as u32 as usize, result as u32 as usize);
+        self.buf.resize(len, 0);
+        self.memory
+            .read(&self.store, ptr, &mut self.buf)
+            .map_err(|_| format!("transform returned {len} bytes at {ptr}"))?;
+        check_message(&self.buf).map(|()| true)
+    }
+}
+
+impl<C: Collector> Collector for Wasm<C> {
+    fn out(&mut self, data: &[u8]) {
+        match self.run(data) {
+            Ok(false) => (),
+            Ok(true) => self.inner.out(&self.buf),
+            Err(err) => {
+                crate::diagnostic!("{}: {err}", self.path);
+                self.inner.out(data);
+            }
+        }
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.inner.start(time);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.inner.end(duration);
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.inner.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.inner.unscheduled();
+    }
+
+    fn source(&mut self, source: &str) {
+        self.inner.source(source);
+    }
+
+    fn metadata(&mut self, metadata: &[(String, String)]) {
+        self.inner.metadata(metadata);
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.inner.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.inner.offset(offset);
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::http::{self, HttpUrl};
+use crate::json::{JsonKey, JsonOutput};
+use crate::timestamp::TimeFormat;
+use crate::{Collector, Output, SampleStats};
+
+/// Delay before the first retry, doubled for each further one
+const BACKOFF: Duration = Duration::from_secs(1);
+
+/// Result of a POST request
+enum Sent {
+    Ok,
+    /// Failed, but may succeed later (connection errors, 408, 429, and 5xx)
+    Retry(String),
+    Failed(String),
+}
+
+/// Collector that POSTs the samples as JSON array (of the objects of json
+/// output) to a URL, each time the interval has elapsed at the end of a
+/// sample, and at exit. A failed batch is retried with exponential backoff,
+/// together with the samples in the meantime, and dropped after the given
+/// number of retries. Retries don't delay the capture, except at exit.
+pub struct WebhookOutput {
+    url: HttpUrl,
+    interval: Duration,
+    retries: u32,
+    json: JsonOutput<Vec<u8>>,
+    /// Comma-separated samples of the batch
+    batch: Vec<u8>,
+    samples: usize,
+    last: Instant,
+    failures: u32,
+    retry_at: Option<Instant>,
+}
+
+impl WebhookOutput {
+    pub fn new(url: &HttpUrl, interval: Duration, retries: u32) -> Self {
+        Self {
+            url: url.clone(),
+            interval,
+            retries,
+            json: JsonOutput::new(Vec::new()),
+            batch: Vec::new(),
+            samples: 0,
+            last: Instant::now(),
+            failures: 0,
+            retry_at: None,
+        }
+    }
+
+    pub fn set_time_format(&mut self, time_format: TimeFormat) {
+        self.json.set_time_format(time_format);
+    }
+
+    pub fn set_key(&mut self, key: Option<JsonKey>) {
+        self.json.set_key(key);
+    }
+
+    fn post(&mut self) -> Sent {
+        let mut body = Vec::with_capacity(self.batch.len() + 2);
+        body.push(b'[');
+        body.extend(&self.batch);
+        body.push(b']');
+        match http::post(&self.url, "application/json", &body) {
+            Ok(200..=299) => Sent::Ok,
+            Ok(status @ (408 | 429 | 500..)) => Sent::Retry(format!("HTTP {status}")),
+            Ok(status) => Sent::Failed(format!("HTTP {status}")),
+            Err(err) => Sent::Retry(err.to_string()),
+        }
+    }
+
+    /// Sends the batch, which is kept for a retry if that may succeed.
+    fn send(&mut self) {
+        let error = match self.post() {
+            Sent::Ok => None,
+            Sent::Retry(err) if self.failures < self.retries => {
+                let delay = BACKOFF.saturating_mul(2u32.saturating_pow(self.failures));
+                crate::diagnostic!("webhook {}: {err}, retrying in {delay:?}", self.url);
+                self.failures += 1;
+                self.retry_at = Some(Instant::now() + delay);
+                return;
+            }
+            Sent::Retry(err) | Sent::Failed(err) => Some(err),
+        };
+        if let Some(err) = error {
+            let samples = self.samples;
+            crate::diagnostic!("webhook {}: {err}, dropping {samples} samples", self.url);
+        }
+        self.batch.clear();
+        self.samples = 0;
+        self.last = Instant::now();
+        self.failures = 0;
+        self.retry_at = None;
+    }
+}
+
+impl Collector for WebhookOutput {
+    fn source(&mut self, source: &str) {
+        self.json.source(source);
+    }
+
+    fn start(&mut self, time: SystemTime) {
+        self.json.start(time);
+    }
+
+    fn out(&mut self, data: &[u8]) {
+        self.json.out(data);
+    }
+
+    fn period(&mut self, period: Duration) {
+        self.json.period(period);
+    }
+
+    fn unscheduled(&mut self) {
+        self.json.unscheduled();
+    }
+
+    fn stats(&mut self, stats: &SampleStats) {
+        self.json.stats(stats);
+    }
+
+    fn offset(&mut self, offset: Duration) {
+        self.json.offset(offset);
+    }
+
+    fn end(&mut self, duration: Duration) {
+        self.json.end(duration);
+        let line = std::mem::take(self.json.inner_mut());
+        if self.samples > 0 {
+            self.batch.push(b',');
+        }
+        self.batch.extend(line.strip_suffix(b"\n").unwrap_or(&line));
+        self.samples += 1;
+        let due = match self.retry_at {
+            Some(at) => Instant::now() >= at,
+            None => self.last.elapsed() >= self.interval,
+        };
+        if due {
+            self.send();
+        }
+    }
+}
+
+impl Drop for WebhookOutput {
+    fn drop(&mut self) {
+        while self.samples > 0 {
+            if let Some(at) = self.retry_at {
+                std::thread::sleep(at.saturating_duration_since(Instant::now()));
+            }
+            self.send();
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use clap::Parser;
+use tcpdiag::{
+    data::InetDiagMsg, dumps, integer::U16BE, merge::Recorder, read_diag_sockets, socket::Replay,
+    NetlinkArgs,
+};
+use zerocopy::{FromZeros, IntoBytes};
+
+const INET: u8 = libc::AF_INET as u8;
+const INET6: u8 = libc::AF_INET6 as u8;
+const ESTABLISHED: u8 = 1;
+const LISTEN: u8 = 10;
+
+fn record(family: u8, state: u8, sport: u16, dport: u16) -> Vec<u8> {
+    let mut msg = InetDiagMsg::new_zeroed();
+    msg.family = family;
+    msg.state = state;
+    msg.id.sport = U16BE::new(sport);
+    msg.id.dport = U16BE::new(dport);
+    msg.as_bytes().to_vec()
+}
+
+/// Replays `samples` (each a dump) with `args` and returns the recorded
+/// records of each sample.
+fn replay(args: &[&str], samples: &[Vec<Vec<u8>>]) -> Vec<Vec<Vec<u8>>> {
+    let args = NetlinkArgs::parse_from(std::iter::once("tcpdiag").chain(args.iter().copied()));
+    let sockets: Vec<_> = dumps(&args)
+        .iter()
+        .map(|_| Replay::new(samples.iter().cloned()))
+        .collect();
+    let mut recorder = Recorder::default();
+    read_diag_sockets(&args, &sockets, &mut recorder);
+    recorder
+        .samples
+        .into_iter()
+        .map(|sample| sample.messages)
+        .collect()
+}
+
+#[test]
+fn samples_in_order() {
+    let samples = [
+        vec![record(INET, ESTABLISHED, 1000, 80)],
+        vec![
+            record(INET, ESTABLISHED, 1001, 80),
+            record(INET, ESTABLISHED, 1002, 80),
+        ],
+    ];
+    assert_eq!(replay(&["-4", "-p.001", "-c2"], &samples), samples);
+}
+
+#[test]
+fn filtered_like_the_kernel() {
+    let sample = [
+        record(INET, ESTABLISHED, 1000, 80),
+        record(INET6, ESTABLISHED, 1001, 80),
+        record(INET, LISTEN, 80, 0),
+        record(INET6, ESTABLISHED, 1002, 443),
+    ];
+    let replay = |args| replay(args, &[sample.to_vec()]);
+    let records = |indices: &[usize]| -> Vec<Vec<_>> {
+        vec![indices.iter().map(|&i| sample[i].clone()).collect()]
+    };
+    // One dump per family, IPv4 before IPv6
+    assert_eq!(replay(&[]), records(&[0, 1, 3]));
+    assert_eq!(replay(&["--dport", "80"]), records(&[0, 1]));
+    assert_eq!(replay(&["-l"]), records(&[2]));
+}
+
+#[test]
+fn empty_after_last_dump() {
+    let samples = [vec![record(INET, ESTABLISHED, 1000, 80)]];
+    assert_eq!(
+        replay(&["-p.001", "-c3"], &samples),
+        [samples[0].clone(), vec![], vec![]]
+    );
+}
+
+#[test]
+fn from_recorded_samples() {
+    let args = NetlinkArgs::parse_from(["tcpdiag", "-a", "-p.001", "-c2"]);
+    let samples = [
+        vec![
+            record(INET, LISTEN, 80, 0),
+            record(INET6, ESTABLISHED, 1000, 80),
+        ],
+        vec![record(INET6, ESTABLISHED, 1001, 80)],
+    ];
+    let sockets: Vec<_> = dumps(&args)
+        .iter()
+        .map(|_| Replay::new(samples.iter().cloned()))
+        .collect();
+    let mut first = Recorder::default();
+    read_diag_sockets(&args, &sockets, &mut first);
+    // A recorded capture replays to the same records.
+    let sockets: Vec<_> = dumps(&args)
+        .iter()
+        .map(|_| Replay::from_samples(&first.samples))
+        .collect();
+    let mut second = Recorder::default();
+    read_diag_sockets(&args, &sockets, &mut second);
+    let messages = |recorder: Recorder| -> Vec<_> {
+        recorder
+            .samples
+            .into_iter()
+            .map(|sample| sample.messages)
+            .collect()
+    };
+    assert_eq!(messages(first), samples);
+    assert_eq!(messages(second), samples);
+}
@@ -1,6 +1,6 @@
 #!/bin/sh -ex
 cargo build --verbose
-cargo +nightly build -p synconn
+cargo build -p synconn
 export PATH=$PATH:target/debug
 CONNS=10
 COUNT=10
@@ -11,5 +11,9 @@ for fmt in json csv; do
     tcpdiag --convert -o "$fmt" < data$fmt.bin > data$fmt.$fmt
     cmp "data.$fmt" "data$fmt.$fmt"
 done
-test "$(wc -l <data.json)" = "$((COUNT))"
-test "$(wc -l <data.csv)" = "$((1+CONNS*COUNT))"
+# json starts with the capture metadata, csv has it in comment lines
+test "$(head -n1 data.json | cut -c1-12)" = '{"metadata":'
+test "$(wc -l <data.json)" = "$((1+COUNT))"
+test "$(grep -vc '^#' data.csv)" = "$((1+CONNS*COUNT))"
+# the csv header is followed by the units of the columns
+test "$(sed -n 2p data.csv | cut -c1-9)" = '# units: '